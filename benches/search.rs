@@ -0,0 +1,55 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+use berlin_core::bench::{
+    graph_analysis, indexed_fixture, load_fixture, search_terms, EXACT_QUERIES, FUZZY_QUERIES,
+    SENTENCE_QUERIES,
+};
+
+fn bench_load(c: &mut Criterion) {
+    c.bench_function("load", |b| b.iter(load_fixture));
+    c.bench_function("mk_fst", |b| {
+        b.iter_batched(load_fixture, |db| db.mk_fst(), BatchSize::SmallInput)
+    });
+}
+
+fn bench_search(c: &mut Criterion) {
+    let db = indexed_fixture();
+    let mut group = c.benchmark_group("search");
+    for (name, queries) in [
+        ("exact", EXACT_QUERIES),
+        ("fuzzy", FUZZY_QUERIES),
+        ("sentence", SENTENCE_QUERIES),
+    ] {
+        let terms = search_terms(queries);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for st in terms.iter() {
+                    black_box(db.search(st));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_graph(c: &mut Criterion) {
+    let db = indexed_fixture();
+    let scores = search_terms(SENTENCE_QUERIES)
+        .iter()
+        .map(|st| db.score_candidates(st, &db.pre_filter(st)))
+        .collect::<Vec<_>>();
+    c.bench_function("graph_analysis", |b| {
+        b.iter_batched(
+            || scores.clone(),
+            |scores| {
+                for s in scores {
+                    black_box(graph_analysis(&db, s));
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, bench_load, bench_search, bench_graph);
+criterion_main!(benches);
@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+use berlin_core::locations_db::{decode_json, parse_data_blocks, parse_data_files};
+use berlin_core::rayon::iter::IntoParallelIterator;
+
+fn test_data_dir() -> PathBuf {
+    let mut data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    data_dir.extend(["tests", "data"]);
+    data_dir
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let bytes = std::fs::read(test_data_dir().join("test-codes.json")).expect("read test codes");
+    c.bench_function("decode_json", |b| {
+        b.iter_batched(
+            || bytes.clone(),
+            |mut buf| decode_json(black_box(&mut buf)).expect("decode"),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn bench_parse_blocks(c: &mut Criterion) {
+    let mut bytes =
+        std::fs::read(test_data_dir().join("test-codes.json")).expect("read test codes");
+    let json = decode_json(&mut bytes).expect("decode");
+    c.bench_function("parse_data_blocks", |b| {
+        b.iter_batched(
+            || vec![("test-codes.json".to_string(), json.clone())],
+            |blocks| parse_data_blocks(blocks.into_par_iter(), None).expect("parse"),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+// Full cold start over a real data directory, when one is provided
+fn bench_cold_start(c: &mut Criterion) {
+    let data_dir = match std::env::var_os("BERLIN_DATA_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => return,
+    };
+    let mut group = c.benchmark_group("cold_start");
+    group.sample_size(10);
+    group.bench_function("parse_data_files", |b| {
+        b.iter(|| parse_data_files(data_dir.clone()).expect("load data"))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_decode, bench_parse_blocks, bench_cold_start);
+criterion_main!(benches);
@@ -0,0 +1,29 @@
+fn main() {
+    #[cfg(feature = "node")]
+    napi_build::setup();
+    #[cfg(feature = "embedded-data")]
+    embed_snapshot();
+}
+
+/// Passes the absolute path of the snapshot to embed to `include_bytes!`,
+/// resolving `BERLIN_EMBEDDED_SNAPSHOT` against the manifest directory.
+#[cfg(feature = "embedded-data")]
+fn embed_snapshot() {
+    println!("cargo:rerun-if-env-changed=BERLIN_EMBEDDED_SNAPSHOT");
+    let path = std::env::var("BERLIN_EMBEDDED_SNAPSHOT")
+        .unwrap_or_else(|_| "data/berlin.snapshot".to_string());
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").expect("manifest directory");
+    let path = std::path::Path::new(&manifest_dir).join(path);
+    if !path.is_file() {
+        panic!(
+            "no snapshot to embed at {}: write one with `berlin build-snapshot` and \
+             name it with BERLIN_EMBEDDED_SNAPSHOT",
+            path.display()
+        );
+    }
+    println!("cargo:rerun-if-changed={}", path.display());
+    println!(
+        "cargo:rustc-env=BERLIN_EMBEDDED_SNAPSHOT_PATH={}",
+        path.display()
+    );
+}
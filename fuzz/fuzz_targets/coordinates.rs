@@ -0,0 +1,8 @@
+#![no_main]
+
+use berlin_core::fuzz::fuzz_coordinates;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: &str| {
+    fuzz_coordinates(input);
+});
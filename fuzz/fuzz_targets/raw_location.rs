@@ -0,0 +1,8 @@
+#![no_main]
+
+use berlin_core::fuzz::{fuzz_location, FuzzLocation};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|location: FuzzLocation| {
+    fuzz_location(&location);
+});
@@ -0,0 +1,15 @@
+#![no_main]
+
+use std::sync::OnceLock;
+
+use berlin_core::fuzz::{fuzz_search, FuzzQuery};
+use berlin_core::locations_db::LocationsDb;
+use berlin_core::test_support::FixtureSpec;
+use libfuzzer_sys::fuzz_target;
+
+static DB: OnceLock<LocationsDb> = OnceLock::new();
+
+fuzz_target!(|query: FuzzQuery| {
+    let db = DB.get_or_init(|| FixtureSpec::default().generate().db);
+    fuzz_search(db, &query);
+});
@@ -0,0 +1,142 @@
+//! Apache Arrow record batches of the location table and of search results,
+//! enabled by the `arrow` feature, for joining against Polars or Spark
+//! dataframes without a round trip through CSV.
+
+use std::sync::Arc;
+
+use arrow_array::builder::{
+    Float64Builder, Int64Builder, ListBuilder, StringBuilder, UInt32Builder, UInt64Builder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+use ustr::Ustr;
+
+use crate::location::Location;
+use crate::locations_db::LocationsDb;
+use crate::search::Score;
+
+/// Builders for the columns describing a location, shared by both batches.
+#[derive(Default)]
+struct LocationColumns {
+    key: StringBuilder,
+    encoding: StringBuilder,
+    id: StringBuilder,
+    state: StringBuilder,
+    subdiv: StringBuilder,
+    names: ListBuilder<StringBuilder>,
+    codes: ListBuilder<StringBuilder>,
+    lat: Float64Builder,
+    lon: Float64Builder,
+}
+
+impl LocationColumns {
+    fn fields() -> Vec<Field> {
+        let list = DataType::List(Arc::new(Field::new("item", DataType::Utf8, true)));
+        vec![
+            Field::new("key", DataType::Utf8, false),
+            Field::new("encoding", DataType::Utf8, false),
+            Field::new("id", DataType::Utf8, false),
+            Field::new("state", DataType::Utf8, false),
+            Field::new("subdiv", DataType::Utf8, true),
+            Field::new("names", list.clone(), false),
+            Field::new("codes", list, false),
+            Field::new("lat", DataType::Float64, true),
+            Field::new("lon", DataType::Float64, true),
+        ]
+    }
+    fn append(&mut self, loc: &Location) {
+        self.key.append_value(loc.key);
+        self.encoding.append_value(loc.encoding);
+        self.id.append_value(loc.id);
+        self.state.append_value(loc.get_state());
+        self.subdiv.append_option(loc.get_subdiv());
+        for name in loc.get_names() {
+            self.names.values().append_value(name);
+        }
+        self.names.append(true);
+        for code in loc.get_codes() {
+            self.codes.values().append_value(code);
+        }
+        self.codes.append(true);
+        let coordinates = loc.get_coordinates();
+        self.lat.append_option(coordinates.map(|c| c.lat));
+        self.lon.append_option(coordinates.map(|c| c.lon));
+    }
+    fn finish(mut self) -> Vec<ArrayRef> {
+        vec![
+            Arc::new(self.key.finish()),
+            Arc::new(self.encoding.finish()),
+            Arc::new(self.id.finish()),
+            Arc::new(self.state.finish()),
+            Arc::new(self.subdiv.finish()),
+            Arc::new(self.names.finish()),
+            Arc::new(self.codes.finish()),
+            Arc::new(self.lat.finish()),
+            Arc::new(self.lon.finish()),
+        ]
+    }
+}
+
+pub fn locations_schema() -> Schema {
+    Schema::new(LocationColumns::fields())
+}
+
+/// One row per location, ordered by key.
+pub fn locations_batch(db: &LocationsDb) -> Result<RecordBatch, ArrowError> {
+    let mut locations = db.all.values().collect::<Vec<_>>();
+    locations.sort_unstable_by_key(|l| l.key.as_str());
+    let mut columns = LocationColumns::default();
+    locations.into_iter().for_each(|loc| columns.append(loc));
+    RecordBatch::try_new(Arc::new(locations_schema()), columns.finish())
+}
+
+pub fn results_schema() -> Schema {
+    let mut fields = vec![
+        Field::new("query_index", DataType::UInt64, false),
+        Field::new("query", DataType::Utf8, false),
+        Field::new("rank", DataType::UInt32, false),
+        Field::new("score", DataType::Int64, false),
+        Field::new("offset_start", DataType::UInt64, false),
+        Field::new("offset_end", DataType::UInt64, false),
+    ];
+    fields.extend(LocationColumns::fields());
+    Schema::new(fields)
+}
+
+/// One row per result of a batch of searches, given as each query with its
+/// results. `query_index` is the position of the query in the batch, so the
+/// output joins back to the rows the queries were taken from.
+pub fn results_batch<'a, I>(db: &LocationsDb, searches: I) -> Result<RecordBatch, ArrowError>
+where
+    I: IntoIterator<Item = (&'a str, &'a [(Ustr, Score)])>,
+{
+    let mut query_index = UInt64Builder::new();
+    let mut query = StringBuilder::new();
+    let mut rank = UInt32Builder::new();
+    let mut score = Int64Builder::new();
+    let mut offset_start = UInt64Builder::new();
+    let mut offset_end = UInt64Builder::new();
+    let mut columns = LocationColumns::default();
+    for (i, (raw, results)) in searches.into_iter().enumerate() {
+        for (r, (key, sc)) in results.iter().enumerate() {
+            let loc = db.all.get(key).expect("location in db");
+            query_index.append_value(i as u64);
+            query.append_value(raw);
+            rank.append_value(r as u32);
+            score.append_value(sc.score);
+            offset_start.append_value(sc.offset.start as u64);
+            offset_end.append_value(sc.offset.end as u64);
+            columns.append(loc);
+        }
+    }
+    let mut arrays: Vec<ArrayRef> = vec![
+        Arc::new(query_index.finish()),
+        Arc::new(query.finish()),
+        Arc::new(rank.finish()),
+        Arc::new(score.finish()),
+        Arc::new(offset_start.finish()),
+        Arc::new(offset_end.finish()),
+    ];
+    arrays.extend(columns.finish());
+    RecordBatch::try_new(Arc::new(results_schema()), arrays)
+}
@@ -0,0 +1,37 @@
+//! Searching and loading from async code on tokio, enabled by the `async`
+//! feature. The CPU-bound work runs on tokio's blocking threads, so it
+//! does not stall the runtime's workers.
+
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use ustr::Ustr;
+
+use crate::config::LoadOptions;
+use crate::locations_db::{parse_data_files_with, LocationsDb};
+use crate::search::{Score, SearchTerm};
+use crate::snapshot::read_snapshot;
+
+pub type AsyncResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+pub async fn search_async(db: Arc<LocationsDb>, st: SearchTerm) -> AsyncResult<Vec<(Ustr, Score)>> {
+    Ok(tokio::task::spawn_blocking(move || db.search(&st)).await?)
+}
+
+/// Loads and indexes a data directory, as `parse_data_files_with` does.
+pub async fn load_data_files(data_dir: PathBuf, options: LoadOptions) -> AsyncResult<LocationsDb> {
+    tokio::task::spawn_blocking(move || {
+        parse_data_files_with(data_dir, &options).map_err(|e| e.to_string())
+    })
+    .await?
+    .map_err(|e| e.into())
+}
+
+/// Reads a snapshot file with `tokio::fs`, then decodes and indexes it.
+pub async fn load_snapshot(path: PathBuf) -> AsyncResult<LocationsDb> {
+    let bytes = tokio::fs::read(path).await?;
+    tokio::task::spawn_blocking(move || read_snapshot(bytes.as_slice()).map_err(|e| e.to_string()))
+        .await?
+        .map_err(|e| e.into())
+}
@@ -0,0 +1,78 @@
+//! Reproducible query corpora and fixtures for measuring search
+//! performance, enabled by the `bench` feature.
+
+use csv::ReaderBuilder;
+#[cfg(feature = "parallel")]
+use rayon::iter::IntoParallelIterator;
+use serde_json::Value;
+use ustr::UstrMap;
+
+use crate::graph::ResultsGraph;
+use crate::location::CsvLocode;
+use crate::locations_db::{parse_data_blocks, parse_data_list, LocationsDb};
+use crate::search::{Score, SearchTerm};
+
+const FIXTURE_CODES: &str = include_str!("../tests/data/test-codes.json");
+const FIXTURE_CODE_LIST: &str = include_str!("../tests/data/test-code-list.csv");
+
+/// Queries that resolve through exact, interned matches.
+pub const EXACT_QUERIES: &[&str] = &[
+    "Lyuliakovo",
+    "Abercarn",
+    "Bognor Regis",
+    "West Sussex",
+    "Bulgaria",
+    "Armagh City, Banbridge and Craigavon",
+];
+
+/// Misspelt queries that need the Levenshtein automaton.
+pub const FUZZY_QUERIES: &[&str] = &[
+    "Lyuliakova",
+    "abercorn",
+    "Bognore Regis",
+    "Bognoreregis",
+    "Stonehavn",
+    "Blagoevgard",
+];
+
+/// Long free-text queries mixing places with unrelated words.
+pub const SENTENCE_QUERIES: &[&str] = &[
+    "Where are all the dentists in Abercorn I would like to find some somewhere",
+    "Whereareallthedentists in Bognore Regis Iwouldlike some somewhere",
+    "Whereareallthedentists inAbercornIwouldliketofind some somewhere",
+    "flights from Burgas to Stonehaven in West Sussex next week",
+];
+
+pub fn fixture_blocks() -> Vec<(String, Value)> {
+    let json = serde_json::from_str(FIXTURE_CODES).expect("decode fixture json");
+    vec![("test-codes.json".to_string(), json)]
+}
+
+/// Parses the fixture datasets, without building the index.
+pub fn load_fixture() -> LocationsDb {
+    let blocks = fixture_blocks();
+    #[cfg(feature = "parallel")]
+    let blocks = blocks.into_par_iter();
+    let db = parse_data_blocks(blocks, None).expect("parse fixture");
+    let mut csv_reader = ReaderBuilder::new().from_reader(FIXTURE_CODE_LIST.as_bytes());
+    let iter = csv_reader
+        .deserialize::<CsvLocode>()
+        .map(|rec| rec.expect("CSV Locode decode"));
+    parse_data_list(db, iter).expect("merge fixture code list")
+}
+
+pub fn indexed_fixture() -> LocationsDb {
+    load_fixture().mk_fst()
+}
+
+pub fn search_terms(queries: &[&str]) -> Vec<SearchTerm> {
+    queries
+        .iter()
+        .map(|q| SearchTerm::from_raw_query(q.to_string(), None, 5, 3))
+        .collect()
+}
+
+/// Runs only the hierarchical graph analysis over already scored candidates.
+pub fn graph_analysis(db: &LocationsDb, scores: UstrMap<Score>) -> UstrMap<Score> {
+    ResultsGraph::from_results(scores, db).scores
+}
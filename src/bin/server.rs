@@ -0,0 +1,169 @@
+//! HTTP geocoding service, enabled by the `server` feature.
+//!
+//! Configured through the environment: `BERLIN_DATA_DIR` (default `data`),
+//! `BERLIN_ADDR` (default `0.0.0.0:3001`) and `BERLIN_MAX_LIMIT`, the
+//! largest `limit` a search may ask for (default 100).
+
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Json, Response};
+use axum::routing::get;
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use berlin_core::key::LocationKey;
+use berlin_core::location::{DisplayStyle, Location};
+use berlin_core::locations_db::{parse_data_files, LocationsDb};
+use berlin_core::profile::SearchProfile;
+use berlin_core::search::{Offset, Score, SearchTerm};
+use berlin_core::stats::SearchStatsSnapshot;
+
+const DEFAULT_LIMIT: usize = 10;
+const DEFAULT_MAX_LIMIT: usize = 100;
+
+// the largest limit a search may ask for, from BERLIN_MAX_LIMIT
+static MAX_LIMIT: OnceLock<usize> = OnceLock::new();
+const DEFAULT_LEV_DIST: u32 = 2;
+
+#[derive(Deserialize)]
+struct SearchParams {
+    q: String,
+    state: Option<String>,
+    limit: Option<usize>,
+    lev_distance: Option<u32>,
+    profile: Option<SearchProfile>,
+}
+
+#[derive(Serialize)]
+struct SearchResult {
+    key: LocationKey,
+    country: Option<String>,
+    code: String,
+    display_name: String,
+    #[serde(flatten)]
+    score: Score,
+    // the retired key the query named, if the location was found by it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redirected_from: Option<String>,
+    loc: Location,
+}
+
+#[derive(Serialize)]
+struct SearchResponse {
+    query: String,
+    normalized: String,
+    results: Vec<SearchResult>,
+    // spans of `normalized` that no result matched
+    remainder: Vec<Offset>,
+}
+
+async fn search(
+    State(db): State<Arc<LocationsDb>>,
+    Query(params): Query<SearchParams>,
+) -> Result<Json<SearchResponse>, Response> {
+    let max_limit = *MAX_LIMIT.get().unwrap_or(&DEFAULT_MAX_LIMIT);
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 || limit > max_limit {
+        let message = format!("limit must be from 1 to {max_limit}");
+        return Err((StatusCode::BAD_REQUEST, message).into_response());
+    }
+    let response = tokio::task::spawn_blocking(move || {
+        let st = SearchTerm::from_raw_query(
+            params.q,
+            params.state,
+            limit,
+            params.lev_distance.unwrap_or(DEFAULT_LEV_DIST),
+        );
+        let st = match params.profile {
+            Some(profile) => st.with_profile(profile),
+            None => st,
+        };
+        let results = db
+            .search(&st)
+            .into_iter()
+            .map(|(key, score)| {
+                let redirected_from = db.redirected_from(&st, key, &score);
+                let loc = db.all[&key].clone();
+                let key = loc.location_key();
+                SearchResult {
+                    key,
+                    country: key.country().map(|c| c.to_string()),
+                    code: key.code().to_string(),
+                    display_name: loc.display_name(&db, DisplayStyle::Hierarchy),
+                    score,
+                    redirected_from: redirected_from.map(|k| k.to_string()),
+                    loc,
+                }
+            })
+            .collect::<Vec<_>>();
+        let remainder = st.remainder(results.iter().map(|r| r.score.offset));
+        SearchResponse {
+            query: st.raw,
+            normalized: st.normalized,
+            results,
+            remainder,
+        }
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    Ok(Json(response))
+}
+
+async fn code(
+    State(db): State<Arc<LocationsDb>>,
+    Path(key): Path<String>,
+) -> Result<Response, StatusCode> {
+    match db.resolve(&key) {
+        Some(resolution) => Ok(Json(resolution).into_response()),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+async fn stats(State(db): State<Arc<LocationsDb>>) -> Json<SearchStatsSnapshot> {
+    Json(db.stats.as_ref().map(|s| s.snapshot()).unwrap_or_default())
+}
+
+// returns the counts until the reset
+async fn reset_stats(State(db): State<Arc<LocationsDb>>) -> Json<SearchStatsSnapshot> {
+    Json(db.stats.as_ref().map(|s| s.reset()).unwrap_or_default())
+}
+
+async fn health() -> &'static str {
+    "OK"
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt::init();
+    let data_dir = std::env::var("BERLIN_DATA_DIR").unwrap_or_else(|_| "data".to_string());
+    let addr = std::env::var("BERLIN_ADDR").unwrap_or_else(|_| "0.0.0.0:3001".to_string());
+    let max_limit = match std::env::var("BERLIN_MAX_LIMIT") {
+        Ok(max) => max.parse()?,
+        Err(_) => DEFAULT_MAX_LIMIT,
+    };
+    MAX_LIMIT.set(max_limit).expect("max limit set once");
+
+    let db = tokio::task::spawn_blocking(move || {
+        let mut db = parse_data_files(PathBuf::from(data_dir)).map_err(|e| e.to_string())?;
+        db.warm_up(["london", "new york", "paris france"]);
+        db.stats = Some(Arc::default());
+        Ok::<_, String>(db)
+    })
+    .await??;
+    let app = Router::new()
+        .route("/berlin/search", get(search))
+        .route("/berlin/code/:key", get(code))
+        .route("/berlin/stats", get(stats).delete(reset_stats))
+        .route("/health", get(health))
+        .with_state(Arc::new(db));
+
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    info!("Listening on {addr}");
+    axum::serve(listener, app).await?;
+    Ok(())
+}
@@ -0,0 +1,237 @@
+//! Geocoding batches of queries into a CSV file, for data-cleaning jobs
+//! that work entirely in files, or as JSON Lines, for Unix pipelines.
+
+use std::error::Error;
+use std::io::{BufRead, Read, Write};
+
+#[cfg(feature = "parallel")]
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::locations_db::{DetailedResult, LocationsDb};
+use crate::search::SearchTerm;
+
+// queries searched at a time, bounding the memory held for a long input
+const CHUNK_SIZE: usize = 1024;
+// written by themselves when there are no queries, and no rows to head
+const HEADERS: [&str; 8] = [
+    "input",
+    "matched_key",
+    "name",
+    "state",
+    "subdiv",
+    "score",
+    "lat",
+    "lon",
+];
+
+/// The best match for a query, empty where there is none.
+#[derive(Debug, Default, Serialize)]
+pub struct BulkRow {
+    pub input: String,
+    pub matched_key: Option<String>,
+    pub name: Option<String>,
+    pub state: Option<String>,
+    pub subdiv: Option<String>,
+    pub score: Option<i64>,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+}
+
+impl BulkRow {
+    fn geocode(db: &LocationsDb, st: SearchTerm) -> Self {
+        let input = st.raw.clone();
+        let Some((key, score)) = db.search(&st).into_iter().next() else {
+            return BulkRow {
+                input,
+                ..Default::default()
+            };
+        };
+        let loc = &db.all[&key];
+        let coordinates = loc.get_coordinates();
+        BulkRow {
+            input,
+            matched_key: Some(key.to_string()),
+            name: loc.get_names().first().map(|n| n.to_string()),
+            state: Some(loc.get_state().to_string()),
+            subdiv: loc.get_subdiv().map(|sd| sd.to_string()),
+            score: Some(score.score),
+            lat: coordinates.map(|c| c.lat),
+            lon: coordinates.map(|c| c.lon),
+        }
+    }
+}
+
+/// Searches each query, built into a search term by `search_term`, and
+/// writes a CSV row of its best match, in the order of the queries.
+pub fn write_csv<I, F, W>(
+    db: &LocationsDb,
+    queries: I,
+    search_term: F,
+    writer: W,
+) -> Result<(), Box<dyn Error>>
+where
+    I: IntoIterator<Item = String>,
+    F: Fn(String) -> SearchTerm + Sync,
+    W: Write,
+{
+    let mut writer = csv::Writer::from_writer(writer);
+    let mut queries = queries.into_iter().peekable();
+    if queries.peek().is_none() {
+        writer.write_record(HEADERS)?;
+    }
+    while queries.peek().is_some() {
+        let chunk = queries.by_ref().take(CHUNK_SIZE).collect::<Vec<_>>();
+        #[cfg(feature = "parallel")]
+        let chunk = chunk.into_par_iter();
+        #[cfg(not(feature = "parallel"))]
+        let chunk = chunk.into_iter();
+        let rows = chunk
+            .map(|q| BulkRow::geocode(db, search_term(q)))
+            .collect::<Vec<_>>();
+        for row in rows {
+            writer.serialize(row)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// A line of JSON Lines input, e.g. `{"q": "abercorn", "state": "gb"}`.
+/// Fields other than `q` default to those given to `search_ndjson`.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct NdjsonQuery {
+    pub q: String,
+    pub state: Option<String>,
+    pub limit: Option<usize>,
+    pub lev_distance: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct NdjsonResponse<'a> {
+    q: &'a str,
+    results: Vec<DetailedResult>,
+}
+
+/// Reads a query from each line of `reader` and writes a line of results
+/// for it, flushing each so that results stream out as queries come in.
+/// A line that is not a query gets a line with its `error` instead.
+pub fn search_ndjson<R, W>(
+    db: &LocationsDb,
+    reader: R,
+    mut writer: W,
+    defaults: &NdjsonQuery,
+) -> Result<(), Box<dyn Error>>
+where
+    R: BufRead,
+    W: Write,
+{
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<NdjsonQuery>(&line) {
+            Ok(query) => {
+                let st = SearchTerm::from_raw_query(
+                    query.q.clone(),
+                    query.state.or(defaults.state.clone()),
+                    query.limit.or(defaults.limit).unwrap_or(1),
+                    query.lev_distance.or(defaults.lev_distance).unwrap_or(2),
+                );
+                let response = NdjsonResponse {
+                    q: &query.q,
+                    results: db.search_detailed(&st),
+                };
+                serde_json::to_writer(&mut writer, &response)?;
+            }
+            Err(e) => {
+                let error = serde_json::json!({ "error": e.to_string() });
+                serde_json::to_writer(&mut writer, &error)?;
+            }
+        }
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// How `geocode_csv` searches each cell of the column.
+#[derive(Debug, Clone)]
+pub struct GeocodeOptions {
+    pub state: Option<String>,
+    pub lev_distance: u32,
+}
+
+impl Default for GeocodeOptions {
+    fn default() -> Self {
+        GeocodeOptions {
+            state: None,
+            lev_distance: 2,
+        }
+    }
+}
+
+/// Copies a CSV, appending `berlin_key`, `berlin_score` and
+/// `berlin_country` columns holding the best match for the named column of
+/// each row, or left empty where nothing matches. Rows are searched in
+/// parallel, in chunks, and written in their input order.
+pub fn geocode_csv<R, W>(
+    db: &LocationsDb,
+    input: R,
+    column: &str,
+    options: &GeocodeOptions,
+    output: W,
+) -> Result<(), Box<dyn Error>>
+where
+    R: Read,
+    W: Write,
+{
+    let mut reader = csv::Reader::from_reader(input);
+    let mut headers = reader.headers()?.clone();
+    let index = headers
+        .iter()
+        .position(|h| h == column)
+        .ok_or_else(|| format!("no column named {column}"))?;
+    headers.extend(["berlin_key", "berlin_score", "berlin_country"]);
+    let mut writer = csv::Writer::from_writer(output);
+    writer.write_record(&headers)?;
+    let mut records = reader.records().peekable();
+    while records.peek().is_some() {
+        let chunk = records
+            .by_ref()
+            .take(CHUNK_SIZE)
+            .collect::<Result<Vec<_>, _>>()?;
+        #[cfg(feature = "parallel")]
+        let chunk = chunk.into_par_iter();
+        #[cfg(not(feature = "parallel"))]
+        let chunk = chunk.into_iter();
+        let rows = chunk
+            .map(|mut record| {
+                let query = record.get(index).unwrap_or_default().to_string();
+                let st = SearchTerm::from_raw_query(
+                    query,
+                    options.state.clone(),
+                    1,
+                    options.lev_distance,
+                );
+                match db.search(&st).first() {
+                    Some((key, score)) => {
+                        record.push_field(key);
+                        record.push_field(&score.score.to_string());
+                        record.push_field(&db.all[key].get_state());
+                    }
+                    None => record.extend(["", "", ""]),
+                }
+                record
+            })
+            .collect::<Vec<_>>();
+        for row in rows {
+            writer.write_record(&row)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
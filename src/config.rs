@@ -0,0 +1,277 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use ustr::{Ustr, UstrMap};
+
+use crate::filters::FilterChain;
+use crate::location::{ChangeDate, CustomKind};
+use crate::regions::RegionDef;
+use crate::similarity::{Levenshtein, Similarity};
+use crate::{
+    LEV_2_LENGTH_MAX, LEV_3_LENGTH_MAX, LEV_LENGTH_MAX, MAX_CODE_LENGTH, MIN_TOKEN_LENGTH,
+    MIN_WORD_LENGTH, STATUS_PENALTY,
+};
+
+pub const STOP_PHRASES: [&str; 7] = [
+    "city of",
+    "town of",
+    "port of",
+    "isle of",
+    "county of",
+    "borough of",
+    "municipality of",
+];
+
+/// Tunable search behaviour, carried by each `SearchTerm`. The defaults
+/// give the standard ranking.
+#[derive(Debug, Clone)]
+pub struct SearchConfig {
+    pub dedup: Option<DedupPolicy>,
+    // scores inexact matches of query terms against names
+    pub similarity: Arc<dyn Similarity>,
+    // normalized phrases dropped from queries, such as "city of"; none by
+    // default, see `standard_stop_phrases`
+    pub stop_phrases: Vec<String>,
+    // also try query words without a plural or possessive ending; off by
+    // default, as it changes the ranking
+    pub stem: bool,
+    // the fraction of query tokens, other than stop words, a result's
+    // match must cover
+    pub min_coverage: Option<f64>,
+    // spread scores iteratively through the results graph, rather than
+    // boosting children by their parents once
+    pub propagation: Option<Propagation>,
+    // rank on the candidates' own scores, without the results graph, as
+    // for autocomplete
+    pub skip_graph: bool,
+    // how the query is case folded, if not by Unicode's defaults
+    pub case_locale: Option<CaseLocale>,
+    // score multipliers by lowercase ISO 3166-1 alpha-2 code, biasing the
+    // ranking toward some countries without filtering out the others
+    pub country_boosts: UstrMap<f64>,
+    // how LOCODEs are treated by lowercase code list status; none are by
+    // default, and `standard_status_rules` are the usual ones
+    pub status_rules: UstrMap<StatusRule>,
+    // match pairs and triples of adjacent words, for names of several words
+    pub doublets: bool,
+    pub triplets: bool,
+    // the most pairs and triples matched for a query, bounding the work
+    // on long machine-generated input
+    pub max_ngrams: Option<usize>,
+    // only results within the region of this key, such as "REGION-eu"
+    pub region: Option<Ustr>,
+    // rank the members of a matching region, such as the places of a
+    // metro area, in its stead
+    pub expand_regions: bool,
+    // when one country alone is named in the query, by name or code, and
+    // the rest of the query finds places in it, keep only those rather than
+    // only boosting them
+    pub detect_state: bool,
+    // the term lengths from which fuzzy matching is narrowed
+    pub lev_breakpoints: LevBreakpoints,
+    // query tokens shorter than this, in bytes, are ignored
+    pub min_token_length: usize,
+    // query tokens up to this long are matched exactly only, as codes are,
+    // rather than fuzzily; raise it for four-character admin codes
+    pub max_code_length: usize,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            dedup: None,
+            similarity: Arc::new(Levenshtein),
+            stop_phrases: vec![],
+            stem: false,
+            min_coverage: None,
+            propagation: None,
+            skip_graph: false,
+            case_locale: None,
+            country_boosts: UstrMap::default(),
+            status_rules: UstrMap::default(),
+            doublets: true,
+            triplets: true,
+            max_ngrams: None,
+            region: None,
+            expand_regions: false,
+            detect_state: false,
+            lev_breakpoints: LevBreakpoints::default(),
+            min_token_length: MIN_TOKEN_LENGTH,
+            max_code_length: MAX_CODE_LENGTH,
+        }
+    }
+}
+
+/// The `STOP_PHRASES`, for `SearchConfig::stop_phrases`.
+pub fn standard_stop_phrases() -> Vec<String> {
+    STOP_PHRASES.iter().map(|p| p.to_string()).collect()
+}
+
+/// Term lengths, in characters, from which fuzzy matching of candidates is
+/// narrowed, so that long terms do not match too much. Datasets of long
+/// names, such as Welsh place names, may want them raised.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevBreakpoints {
+    // terms shorter than this are searched at the query's full distance
+    pub lev_3_length_max: usize,
+    // terms shorter than this at a distance of at most 2, and longer
+    // ones at most 1
+    pub lev_2_length_max: usize,
+    // terms this long or longer are matched exactly only
+    pub lev_length_max: usize,
+}
+
+impl Default for LevBreakpoints {
+    fn default() -> Self {
+        LevBreakpoints {
+            lev_3_length_max: LEV_3_LENGTH_MAX,
+            lev_2_length_max: LEV_2_LENGTH_MAX,
+            lev_length_max: LEV_LENGTH_MAX,
+        }
+    }
+}
+
+impl LevBreakpoints {
+    /// The Levenshtein distance a term of `length` characters is searched
+    /// at, for a query allowing `lev_dist`, if it is matched fuzzily.
+    pub fn distance(&self, length: usize, lev_dist: u32) -> u32 {
+        match length {
+            _ if length < self.lev_3_length_max => lev_dist,
+            _ if length < self.lev_2_length_max => lev_dist.min(2),
+            _ => lev_dist.min(1),
+        }
+    }
+}
+
+/// The treatment of results whose code list status marks them as doubtful
+/// or withdrawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusRule {
+    // subtracted from the score
+    Penalty(i64),
+    Exclude,
+}
+
+/// Penalises entries requested, rejected or under review, and excludes
+/// those marked for removal.
+pub fn standard_status_rules() -> UstrMap<StatusRule> {
+    [
+        ("rq", StatusRule::Penalty(STATUS_PENALTY)),
+        ("rr", StatusRule::Penalty(STATUS_PENALTY)),
+        ("ur", StatusRule::Penalty(STATUS_PENALTY)),
+        ("qq", StatusRule::Penalty(STATUS_PENALTY)),
+        ("xx", StatusRule::Exclude),
+    ]
+    .into_iter()
+    .map(|(status, rule)| (Ustr::from(status), rule))
+    .collect()
+}
+
+/// Case folding conventions, where a language's differ from Unicode's
+/// defaults.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CaseLocale {
+    #[default]
+    Unicode,
+    // Turkish and Azerbaijani, where I folds to dotless ı and İ to i
+    Turkic,
+}
+
+/// Iterative, PageRank-style propagation over the parent/child edges of
+/// the results graph, so that many matching children lift their parent
+/// as well as a matching parent lifting its children.
+#[derive(Debug, Clone, Copy)]
+pub struct Propagation {
+    // the share of a neighbour's evidence passed on at each iteration
+    pub damping: f64,
+    pub iterations: usize,
+}
+
+impl Default for Propagation {
+    fn default() -> Self {
+        Propagation {
+            damping: 0.5,
+            iterations: 3,
+        }
+    }
+}
+
+/// Options for building the word index, read by `LocationsDb::mk_fst`
+/// and `LocationsDb::add_locations`. Snapshots carry them with the index.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndexOptions {
+    // also index multiword names run together, as in "badhersfeld"
+    pub compounds: bool,
+    // also index names with hyphens as spaces and without apostrophes
+    pub punctuation: bool,
+    // words of names this long or longer, in bytes, are also indexed and
+    // matched on their own
+    pub min_word_length: usize,
+    // further filters for the normalized names indexed and for queries,
+    // such as '&' to "and"; none by default
+    pub filters: FilterChain,
+}
+
+impl Default for IndexOptions {
+    fn default() -> Self {
+        IndexOptions {
+            compounds: false,
+            punctuation: true,
+            min_word_length: MIN_WORD_LENGTH,
+            filters: FilterChain::default(),
+        }
+    }
+}
+
+/// How data files are read into a database, for `parse_data_files_with`.
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    pub custom_kinds: Vec<CustomKind>,
+    // key prefixes by encoding, as (encoding, namespace)
+    pub key_namespaces: Vec<(String, String)>,
+    // groupings of states and subdivisions added as locations, such as
+    // `RegionDef::builtin()`
+    pub regions: Vec<RegionDef>,
+    // tags to attach, as (location key, tag), besides those in the data
+    pub tags: Vec<(String, String)>,
+    // LOCODEs the code list changed or removed after this month are left
+    // out, to reproduce the results of an earlier code list
+    pub code_list_cutoff: Option<ChangeDate>,
+}
+
+/// Collapses results describing the same place in several datasets into
+/// the one from the most preferred encoding, ranked at the best score of
+/// the group.
+#[derive(Debug, Clone)]
+pub struct DedupPolicy {
+    pub by: Vec<DedupBy>,
+    // encodings in order of preference, ahead of any not listed
+    pub preferred: Vec<Ustr>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DedupBy {
+    // the same primary name within the same state and subdivision
+    NameAndSubdivision,
+    // linked through `Location::related_keys`
+    CrossReference,
+}
+
+impl DedupPolicy {
+    pub fn preference(&self, encoding: Ustr) -> usize {
+        self.preferred
+            .iter()
+            .position(|e| *e == encoding)
+            .unwrap_or(self.preferred.len())
+    }
+}
+
+impl Default for DedupPolicy {
+    fn default() -> Self {
+        DedupPolicy {
+            by: vec![DedupBy::NameAndSubdivision, DedupBy::CrossReference],
+            preferred: vec![],
+        }
+    }
+}
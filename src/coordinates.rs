@@ -1,3 +1,4 @@
+use std::fmt;
 use std::str::FromStr;
 
 use nom::branch::alt;
@@ -5,15 +6,87 @@ use nom::character::complete::{char, digit1, satisfy, space1};
 use nom::multi::count;
 use nom::sequence::tuple;
 use nom::{AsChar, IResult};
-use serde::Serialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
 // north and east are positive numbers
-#[derive(Debug, Copy, Clone, Serialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 pub struct Coordinates {
     pub lat: f64,
     pub lon: f64,
 }
 
+impl Coordinates {
+    /// The UN/LOCODE notation, "DDMMN DDDMME", to the nearest minute, as
+    /// read by `coordinate_parser`.
+    pub fn to_unlocode_string(self) -> String {
+        let minutes = |v: f64| (v.abs() * 60.).round() as i64;
+        let (lat, lon) = (minutes(self.lat), minutes(self.lon));
+        format!(
+            "{:02}{:02}{} {:03}{:02}{}",
+            lat / 60,
+            lat % 60,
+            if self.lat < 0. { 'S' } else { 'N' },
+            lon / 60,
+            lon % 60,
+            if self.lon < 0. { 'W' } else { 'E' },
+        )
+    }
+    /// The great-circle distance to `other` in kilometres, by the haversine
+    /// formula.
+    pub fn distance_km(self, other: Coordinates) -> f64 {
+        const EARTH_RADIUS_KM: f64 = 6371.0;
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.lon - self.lon).to_radians();
+        let a = (dlat / 2.).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.).sin().powi(2);
+        2. * EARTH_RADIUS_KM * a.sqrt().asin()
+    }
+    /// Decimal degrees, latitude first, to `precision` decimal places.
+    pub fn to_decimal_string(self, precision: usize) -> String {
+        format!("{:.*}, {:.*}", precision, self.lat, precision, self.lon)
+    }
+}
+
+impl fmt::Display for Coordinates {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_unlocode_string())
+    }
+}
+
+/// The smallest latitude and longitude ranges holding a set of points,
+/// without regard to the antimeridian.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BoundingBox {
+    pub min: Coordinates,
+    pub max: Coordinates,
+}
+
+impl BoundingBox {
+    pub fn from_points(points: impl IntoIterator<Item = Coordinates>) -> Option<Self> {
+        points.into_iter().fold(None, |bbox, c| {
+            Some(match bbox {
+                None => BoundingBox { min: c, max: c },
+                Some(BoundingBox { min, max }) => BoundingBox {
+                    min: Coordinates {
+                        lat: min.lat.min(c.lat),
+                        lon: min.lon.min(c.lon),
+                    },
+                    max: Coordinates {
+                        lat: max.lat.max(c.lat),
+                        lon: max.lon.max(c.lon),
+                    },
+                },
+            })
+        })
+    }
+    pub fn contains(&self, c: Coordinates) -> bool {
+        (self.min.lat..=self.max.lat).contains(&c.lat)
+            && (self.min.lon..=self.max.lon).contains(&c.lon)
+    }
+}
+
 pub fn coordinate_parser(i: &str) -> IResult<&str, Coordinates> {
     let (i, (lat_deg, lat_min, bearing, _)) = tuple((
         count(satisfy(|c| c.is_dec_digit()), 2),
@@ -52,9 +125,18 @@ mod tests {
     #[test]
     fn can_parse_coordinates() {
         let res = coordinate_parser("600N 01212E").unwrap();
-        assert!(res.0 == "");
+        assert!(res.0.is_empty());
         let coordinates = res.1;
         assert!((coordinates.lat - 60.0).abs() < 1e-3);
         assert!((coordinates.lon - 12.2).abs() < 1e-3);
     }
+
+    #[test]
+    fn can_format_coordinates() {
+        let (_, coordinates) = coordinate_parser("5130N 00007W").unwrap();
+        assert_eq!(coordinates.to_string(), "5130N 00007W");
+        assert_eq!(coordinates.to_decimal_string(3), "51.500, -0.117");
+        let (_, south) = coordinate_parser("3352S 15112E").unwrap();
+        assert_eq!(south.to_unlocode_string(), "3352S 15112E");
+    }
 }
@@ -0,0 +1,124 @@
+//! Comparing two databases, such as before and after a data release.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::Value;
+use ustr::Ustr;
+
+use crate::location::Location;
+use crate::locations_db::LocationsDb;
+
+/// What changes between an older and a newer database, each list sorted
+/// by key.
+#[derive(Debug, Default, Serialize)]
+pub struct DbDiff {
+    pub added: Vec<Ustr>,
+    pub removed: Vec<Ustr>,
+    pub changed: Vec<LocationChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LocationChange {
+    pub key: Ustr,
+    pub fields: Vec<FieldChange>,
+}
+
+/// A changed field, named by its path, as in `coordinates.lat`. Fields
+/// missing on one side are null.
+#[derive(Debug, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+impl DbDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// The fields of a location by path, with the `LocData` variant named as
+/// `kind` rather than nesting the data under it. Null fields and derived
+/// words are left out.
+fn fields(loc: &Location) -> BTreeMap<String, Value> {
+    fn flatten(prefix: &str, value: Value, out: &mut BTreeMap<String, Value>) {
+        match value {
+            Value::Object(map) if !map.is_empty() => {
+                for (k, v) in map {
+                    let path = match prefix {
+                        "" => k,
+                        _ => format!("{prefix}.{k}"),
+                    };
+                    flatten(&path, v, out);
+                }
+            }
+            Value::Null => {}
+            other => {
+                out.insert(prefix.to_string(), other);
+            }
+        }
+    }
+    let mut out = BTreeMap::new();
+    let Ok(Value::Object(mut value)) = serde_json::to_value(loc) else {
+        return out;
+    };
+    value.remove("words");
+    if let Some(Value::Object(data)) = value.remove("data") {
+        for (kind, data) in data {
+            out.insert("kind".to_string(), Value::String(kind));
+            flatten("", data, &mut out);
+        }
+    }
+    flatten("", Value::Object(value), &mut out);
+    out
+}
+
+impl LocationsDb {
+    /// The locations added, removed and changed in `newer`, with the
+    /// fields that changed, to review a data release before deploying it.
+    pub fn diff(&self, newer: &LocationsDb) -> DbDiff {
+        let mut diff = DbDiff::default();
+        for (key, loc) in &self.all {
+            let Some(new_loc) = newer.all.get(key) else {
+                diff.removed.push(*key);
+                continue;
+            };
+            let (old, mut new) = (fields(loc), fields(new_loc));
+            let mut changes = vec![];
+            for (field, old_value) in old {
+                let new_value = new.remove(&field).unwrap_or(Value::Null);
+                if new_value != old_value {
+                    changes.push(FieldChange {
+                        field,
+                        old: old_value,
+                        new: new_value,
+                    });
+                }
+            }
+            changes.extend(new.into_iter().map(|(field, new)| FieldChange {
+                field,
+                old: Value::Null,
+                new,
+            }));
+            if !changes.is_empty() {
+                changes.sort_unstable_by(|a, b| a.field.cmp(&b.field));
+                diff.changed.push(LocationChange {
+                    key: *key,
+                    fields: changes,
+                });
+            }
+        }
+        diff.added = newer
+            .all
+            .keys()
+            .filter(|k| !self.all.contains_key(k))
+            .copied()
+            .collect();
+        diff.added.sort_unstable();
+        diff.removed.sort_unstable();
+        diff.changed.sort_unstable_by_key(|c| c.key);
+        diff
+    }
+}
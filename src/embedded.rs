@@ -0,0 +1,18 @@
+//! A snapshot compiled into the binary by the `embedded-data` feature, for
+//! command-line and WebAssembly builds that ship without data files. The
+//! snapshot is named at build time by `BERLIN_EMBEDDED_SNAPSHOT`, by
+//! default `data/berlin.snapshot`, and may hold a trimmed dataset.
+
+use std::error::Error;
+
+use crate::locations_db::LocationsDb;
+use crate::snapshot::read_snapshot;
+
+static SNAPSHOT: &[u8] = include_bytes!(env!("BERLIN_EMBEDDED_SNAPSHOT_PATH"));
+
+impl LocationsDb {
+    /// Loads the embedded snapshot into an indexed database.
+    pub fn builtin() -> Result<LocationsDb, Box<dyn Error>> {
+        read_snapshot(SNAPSHOT)
+    }
+}
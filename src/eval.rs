@@ -0,0 +1,135 @@
+//! Evaluating the ranking against a labelled corpus of queries and the
+//! keys they should find, so that scoring changes can be measured.
+
+use std::error::Error;
+use std::io::Read;
+
+#[cfg(feature = "parallel")]
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use ustr::Ustr;
+
+use crate::config::SearchConfig;
+use crate::locations_db::LocationsDb;
+use crate::search::SearchTerm;
+
+/// A row of a corpus, read from CSV with `query` and `expected_key`
+/// columns and an optional `state` column.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabelledQuery {
+    pub query: String,
+    pub expected_key: String,
+    #[serde(default)]
+    pub state: Option<String>,
+}
+
+/// How `evaluate` searches each query.
+#[derive(Debug, Clone)]
+pub struct EvalOptions {
+    pub lev_distance: u32,
+    // the results searched for the expected key; those ranked lower count
+    // as not found
+    pub depth: usize,
+    pub config: SearchConfig,
+}
+
+impl Default for EvalOptions {
+    fn default() -> Self {
+        EvalOptions {
+            lev_distance: 2,
+            depth: 10,
+            config: SearchConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EvalReport {
+    pub queries: usize,
+    pub precision_at_1: f64,
+    pub precision_at_5: f64,
+    // mean reciprocal rank, counting keys not found as zero
+    pub mrr: f64,
+    // the queries whose expected key was not ranked first
+    pub failures: Vec<EvalFailure>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EvalFailure {
+    pub query: String,
+    pub expected_key: String,
+    // from 1, if found within the depth searched
+    pub rank: Option<usize>,
+    // whether the expected key is in the database at all
+    pub known: bool,
+    // the best results, as (key, score)
+    pub top: Vec<(Ustr, i64)>,
+}
+
+impl LabelledQuery {
+    pub fn read_csv<R: Read>(reader: R) -> Result<Vec<Self>, Box<dyn Error>> {
+        let mut reader = csv::Reader::from_reader(reader);
+        Ok(reader.deserialize().collect::<Result<Vec<_>, _>>()?)
+    }
+    // the rank of the expected key, and the best results
+    fn rank(&self, db: &LocationsDb, options: &EvalOptions) -> (Option<usize>, Vec<(Ustr, i64)>) {
+        let st = SearchTerm::with_config(
+            self.query.clone(),
+            self.state.clone(),
+            options.depth,
+            options.lev_distance,
+            options.config.clone(),
+        );
+        let results = db.search(&st);
+        let rank = results
+            .iter()
+            .position(|(key, _)| key.as_str() == self.expected_key)
+            .map(|n| n + 1);
+        let top = results.iter().take(5).map(|(k, s)| (*k, s.score)).collect();
+        (rank, top)
+    }
+}
+
+/// Searches each query of the corpus and reports how well the expected
+/// keys are ranked: the share ranked first and within the first five, the
+/// mean reciprocal rank, and the details of each query that missed.
+pub fn evaluate(db: &LocationsDb, corpus: &[LabelledQuery], options: &EvalOptions) -> EvalReport {
+    #[cfg(feature = "parallel")]
+    let queries = corpus.into_par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let queries = corpus.iter();
+    let ranked = queries
+        .map(|q| (q, q.rank(db, options)))
+        .collect::<Vec<_>>();
+    let count = corpus.len().max(1) as f64;
+    let share = |within: usize| {
+        let hits = ranked
+            .iter()
+            .filter(|(_, (rank, _))| rank.is_some_and(|r| r <= within));
+        hits.count() as f64 / count
+    };
+    let mrr = ranked
+        .iter()
+        .filter_map(|(_, (rank, _))| rank.map(|r| 1. / r as f64))
+        .sum::<f64>()
+        / count;
+    let (precision_at_1, precision_at_5) = (share(1), share(5));
+    let failures = ranked
+        .into_iter()
+        .filter(|(_, (rank, _))| *rank != Some(1))
+        .map(|(q, (rank, top))| EvalFailure {
+            query: q.query.clone(),
+            expected_key: q.expected_key.clone(),
+            rank,
+            known: db.resolve(&q.expected_key).is_some(),
+            top,
+        })
+        .collect();
+    EvalReport {
+        queries: corpus.len(),
+        precision_at_1,
+        precision_at_5,
+        mrr,
+        failures,
+    }
+}
@@ -0,0 +1,208 @@
+//! Accounting for how scores are reached, for each result and across all
+//! the candidates for a query.
+
+use std::collections::BTreeMap;
+
+use schemars::JsonSchema;
+use serde::Serialize;
+use ustr::Ustr;
+
+use crate::locations_db::LocationsDb;
+use crate::search::{LevClamp, Score, SearchTerm};
+use crate::SEARCH_INCLUSION_THRESHOLD;
+
+/// A result of `LocationsDb::explain`: the score the location matched the
+/// query with, and each boost on top of it.
+#[derive(Clone, Serialize, JsonSchema)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct Explanation {
+    #[schemars(with = "String")]
+    #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+    pub key: Ustr,
+    // the location the score was reached for, where deduplication put
+    // a preferred location in its place
+    #[schemars(with = "Option<String>")]
+    #[cfg_attr(feature = "utoipa", schema(value_type = Option<String>))]
+    pub scored_key: Option<Ustr>,
+    pub base: i64,
+    pub boosts: Vec<Boost>,
+    pub score: Score,
+    // terms within the matched span that fuzzy matching was narrowed for,
+    // by their length
+    pub lev_clamps: Vec<LevClamp>,
+}
+
+/// A boost from a related result in the results graph. The final score
+/// is the base plus the boosts, or the base alone if that is higher.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct Boost {
+    pub kind: BoostKind,
+    #[schemars(with = "String")]
+    #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+    pub source: Ustr,
+    pub amount: i64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum BoostKind {
+    // the matching state or subdivision of the result
+    Parent,
+    // a matching location within the result, under propagation
+    Child,
+}
+
+/// A result of `LocationsDb::search_with_features`, with the signals an
+/// external model can re-rank it on.
+#[derive(Clone, Serialize, JsonSchema)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct FeatureResult {
+    #[schemars(with = "String")]
+    #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+    pub key: Ustr,
+    pub score: Score,
+    pub features: Features,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct Features {
+    // names and words of the location that the query holds exactly
+    pub exact_matches: usize,
+    // the best similarity of a fuzzy query term to a name, from 0 to 1
+    pub fuzzy_similarity: f64,
+    // the query holds the location's code, as "gb:abc"
+    pub code_match: bool,
+    // the sum of the boosts from related results
+    pub parent_boost: i64,
+    // the length of the primary name less that of the matched span
+    pub name_length_delta: i64,
+    pub base: i64,
+    pub score: i64,
+}
+
+/// The scores of every candidate for a query, before the inclusion
+/// threshold and the limit apply.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ScoreHistogram {
+    pub bucket_width: i64,
+    // candidates by the lowest score of their bucket, ascending
+    pub buckets: Vec<(i64, usize)>,
+    // candidates found in the index that did not match at all
+    pub unmatched: usize,
+    // scores at or below this are not returned by `search`
+    pub inclusion_threshold: i64,
+}
+
+impl ScoreHistogram {
+    /// The number of candidates scoring above `min_score`, to the nearest
+    /// bucket.
+    pub fn count_above(&self, min_score: i64) -> usize {
+        self.buckets
+            .iter()
+            .filter(|(lowest, _)| lowest + self.bucket_width > min_score)
+            .map(|(_, count)| count)
+            .sum()
+    }
+}
+
+impl LocationsDb {
+    /// Scores every candidate for the query into buckets `bucket_width`
+    /// wide, as a guide to choosing score thresholds for the data.
+    pub fn score_histogram(&self, st: &SearchTerm, bucket_width: i64) -> ScoreHistogram {
+        let st = &*self.prepare(st);
+        let bucket_width = bucket_width.max(1);
+        let mut buckets: BTreeMap<i64, usize> = BTreeMap::new();
+        let mut unmatched = 0;
+        for key in self.pre_filter(st) {
+            match self.all[&key].search(st) {
+                Some(score) => {
+                    let lowest = score.score.div_euclid(bucket_width) * bucket_width;
+                    *buckets.entry(lowest).or_default() += 1;
+                }
+                None => unmatched += 1,
+            }
+        }
+        ScoreHistogram {
+            bucket_width,
+            buckets: buckets.into_iter().collect(),
+            unmatched,
+            inclusion_threshold: SEARCH_INCLUSION_THRESHOLD,
+        }
+    }
+    /// Searches as `search` does, breaking each result's score down.
+    pub fn explain(&self, st: &SearchTerm) -> Vec<Explanation> {
+        let st = &*self.prepare(st);
+        let pre_filtered = self.pre_filter(st);
+        let base = self.score_candidates(st, &pre_filtered);
+        let graph = self.link(st, base.clone());
+        let ranked = self.sorted(st, graph.scores);
+        let clamps = st.lev_clamps();
+        let groups = match &st.config.dedup {
+            Some(policy) => self.dedup_groups(policy, &ranked),
+            None => ranked.iter().enumerate().map(|(n, r)| (n, r.0)).collect(),
+        };
+        groups
+            .into_iter()
+            .take(st.max_results())
+            .map(|(n, key)| {
+                let (scored, score) = ranked[n];
+                Explanation {
+                    key,
+                    scored_key: (scored != key).then_some(scored),
+                    base: base[&scored].score,
+                    boosts: graph.boosts.get(&scored).cloned().unwrap_or_default(),
+                    score,
+                    lev_clamps: clamps
+                        .iter()
+                        .filter(|c| c.offset.start < score.offset.end)
+                        .filter(|c| c.offset.end > score.offset.start)
+                        .cloned()
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+    /// Searches as `explain` does, computing the features of each result
+    /// for learned re-ranking.
+    pub fn search_with_features(&self, st: &SearchTerm) -> Vec<FeatureResult> {
+        let st = &*self.prepare(st);
+        self.explain(st)
+            .into_iter()
+            .map(|e| {
+                let loc = &self.all[&e.scored_key.unwrap_or(e.key)];
+                let strings = loc.searchable.strings.iter().chain(loc.words.iter());
+                let exact_matches = strings
+                    .clone()
+                    .filter(|s| st.matches.exact_terms().any(|m| m.term == **s))
+                    .count();
+                let fuzzy_similarity = strings
+                    .flat_map(|s| {
+                        st.matches
+                            .fuzzy_terms()
+                            .map(|m| st.config.similarity.similarity(s, &m.term))
+                    })
+                    .fold(0., f64::max);
+                let name_len = loc.get_names().first().map_or(0, |n| n.len());
+                let span_len = e.score.offset.end - e.score.offset.start;
+                let features = Features {
+                    exact_matches,
+                    fuzzy_similarity,
+                    code_match: st.codes_match(&loc.searchable.codes, 0).is_some(),
+                    parent_boost: e.boosts.iter().map(|b| b.amount).sum(),
+                    name_length_delta: name_len as i64 - span_len as i64,
+                    base: e.base,
+                    score: e.score.score,
+                };
+                FeatureResult {
+                    key: e.key,
+                    score: e.score,
+                    features,
+                }
+            })
+            .collect()
+    }
+}
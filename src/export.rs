@@ -0,0 +1,146 @@
+//! Writing a database back out for consumers outside Rust.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use csv::WriterBuilder;
+use serde::Serialize;
+use serde_json::Value;
+use ustr::Ustr;
+
+use crate::location::{CsvLocode, CsvRedirect, LocData, Location};
+use crate::locations_db::{LocationsDb, DATA_FILES};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExportFormat {
+    // a data directory in the source layout, read by `parse_data_files`
+    Json,
+    // a single flat table, `locations.csv`
+    Csv,
+}
+
+/// A row of the flat CSV export.
+#[derive(Serialize)]
+struct CsvLocation {
+    key: Ustr,
+    encoding: Ustr,
+    id: Ustr,
+    name: Option<Ustr>,
+    // every code, separated by ';'
+    codes: String,
+    state: Ustr,
+    subdivision: Option<Ustr>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    // separated by ';'
+    related_keys: String,
+}
+
+fn sorted_locations(db: &LocationsDb) -> Vec<&Location> {
+    let mut locations = db.all.values().collect::<Vec<_>>();
+    locations.sort_unstable_by_key(|l| l.key.as_str());
+    locations
+}
+
+impl LocationsDb {
+    /// Writes the database to `dir` in the given format, including the
+    /// coordinates merged in from the code list.
+    ///
+    /// The JSON format is a data directory that loads back into the same
+    /// locations, although custom kinds must be registered again first.
+    /// Coordinates keep their full precision.
+    pub fn export(&self, dir: &Path, format: ExportFormat) -> Result<(), Box<dyn Error>> {
+        std::fs::create_dir_all(dir)?;
+        match format {
+            ExportFormat::Json => self.export_json(dir),
+            ExportFormat::Csv => self.export_csv(dir),
+        }
+    }
+    fn export_json(&self, dir: &Path) -> Result<(), Box<dyn Error>> {
+        let mut files: BTreeMap<String, BTreeMap<&str, Value>> = DATA_FILES
+            .into_iter()
+            .map(|file| (file.to_string(), BTreeMap::new()))
+            .collect();
+        let mut code_list = WriterBuilder::new().from_path(dir.join("code-list_csv.csv"))?;
+        for loc in sorted_locations(self) {
+            let file = match loc.data {
+                LocData::St(_) => "state.json".to_string(),
+                LocData::Subdv(_) => "subdivision.json".to_string(),
+                LocData::Airp(_) => "iata.json".to_string(),
+                LocData::Locd(_) => "locode.json".to_string(),
+                // other standards and custom kinds each get a file of their
+                // own, such as "ISO-3166-2:GB.json"
+                LocData::Gen(_) | LocData::Custom(_) => format!("{}.json", loc.encoding),
+                // regions are loaded with their members by `add_regions`
+                LocData::Regn(_) => continue,
+            };
+            let raw = loc.to_raw(self.custom_kinds.get(&loc.encoding));
+            files.entry(file).or_default().insert(&loc.key, raw);
+            if let LocData::Locd(l) = loc.data {
+                // the JSON carries the coordinates in full, which the code
+                // list's minutes would otherwise override on loading
+                let row = CsvLocode {
+                    coordinates: None,
+                    ..l.to_csv()
+                };
+                code_list.serialize(row)?;
+            }
+        }
+        code_list.flush()?;
+        for (file, locations) in files {
+            let mut writer = BufWriter::new(File::create(dir.join(file))?);
+            serde_json::to_writer_pretty(&mut writer, &locations)?;
+            writer.flush()?;
+        }
+        if !self.redirects.is_empty() {
+            let mut redirects = self
+                .redirects
+                .iter()
+                .map(|(from, to)| CsvRedirect {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                })
+                .collect::<Vec<_>>();
+            redirects.sort_unstable_by(|a, b| a.from.cmp(&b.from));
+            let mut writer = WriterBuilder::new().from_path(dir.join("redirects.csv"))?;
+            for redirect in redirects {
+                writer.serialize(redirect)?;
+            }
+            writer.flush()?;
+        }
+        Ok(())
+    }
+    fn export_csv(&self, dir: &Path) -> Result<(), Box<dyn Error>> {
+        let mut writer = WriterBuilder::new().from_path(dir.join("locations.csv"))?;
+        for loc in sorted_locations(self) {
+            let coordinates = loc.get_coordinates();
+            let codes = loc
+                .get_codes()
+                .iter()
+                .map(|c| c.as_str())
+                .collect::<Vec<_>>();
+            let related_keys = loc
+                .related_keys
+                .iter()
+                .map(|k| k.as_str())
+                .collect::<Vec<_>>();
+            writer.serialize(CsvLocation {
+                key: loc.key,
+                encoding: loc.encoding,
+                id: loc.id,
+                name: loc.get_names().first().copied(),
+                codes: codes.join(";"),
+                state: loc.get_state(),
+                subdivision: loc.get_subdiv(),
+                latitude: coordinates.map(|c| c.lat),
+                longitude: coordinates.map(|c| c.lon),
+                related_keys: related_keys.join(";"),
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+//! Boosting the results users have picked before for the same query.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+use ustr::Ustr;
+
+use crate::locations_db::LocationsDb;
+use crate::search::{rank_order, Score, SearchTerm};
+
+/// The results users picked, by query. Queries are compared by their
+/// searched words, in any order and without stop words, so that "the port
+/// of Abercarn" and "abercarn port" share their picks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeedbackStore {
+    // added to a result's score for each time it was picked
+    pub boost_per_pick: i64,
+    // the most a result's score is raised by
+    pub max_boost: i64,
+    pub picks: BTreeMap<String, BTreeMap<Ustr, u32>>,
+}
+
+impl Default for FeedbackStore {
+    fn default() -> Self {
+        FeedbackStore {
+            boost_per_pick: 20,
+            max_boost: 100,
+            picks: BTreeMap::new(),
+        }
+    }
+}
+
+impl FeedbackStore {
+    /// The words a query is searched on, sorted, as a key of `picks`.
+    pub fn query_key(st: &SearchTerm) -> String {
+        let mut words = st
+            .tokens
+            .iter()
+            .map(|t| &st.normalized[t.start..t.end])
+            .collect::<Vec<_>>();
+        words.sort_unstable();
+        words.join(" ")
+    }
+    /// Notes that the result `key` was picked for the query.
+    pub fn record(&mut self, st: &SearchTerm, key: Ustr) {
+        *self
+            .picks
+            .entry(Self::query_key(st))
+            .or_default()
+            .entry(key)
+            .or_default() += 1;
+    }
+    /// The boost to the score of `key` for the query.
+    pub fn boost(&self, st: &SearchTerm, key: Ustr) -> i64 {
+        self.picks
+            .get(&Self::query_key(st))
+            .and_then(|picks| picks.get(&key))
+            .map_or(0, |n| (self.boost_per_pick * *n as i64).min(self.max_boost))
+    }
+    /// Boosts the results of a search by past picks, and ranks them again.
+    pub fn apply(&self, st: &SearchTerm, results: &mut [(Ustr, Score)]) {
+        for (key, score) in results.iter_mut() {
+            score.score += self.boost(st, *key);
+        }
+        results.sort_by_key(rank_order);
+    }
+    /// Searches, boosting results by past picks. Every result is boosted
+    /// before the limit is applied, so that a pick ranked beyond it may
+    /// still rise into it.
+    pub fn search(&self, db: &LocationsDb, st: &SearchTerm) -> Vec<(Ustr, Score)> {
+        let mut results = db.search(&SearchTerm {
+            limit: 0,
+            ..st.clone()
+        });
+        self.apply(st, &mut results);
+        results.truncate(st.max_results());
+        results
+    }
+    /// Writes the store as JSON.
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+    pub fn read<R: Read>(reader: R) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
@@ -0,0 +1,96 @@
+//! Downloading pinned releases of the datasets into a data directory,
+//! enabled by the `fetch` feature. A release lists each file's URL and
+//! SHA-256 checksum, so a deployment gets the same data every time.
+
+use std::error::Error;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::locations_db::CODE_LIST_FILE;
+
+/// The files of a release, as read from a JSON manifest:
+///
+/// ```json
+/// {"files": [{"name": "state.json", "url": "https://...", "sha256": "..."}]}
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Release {
+    pub files: Vec<DataFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataFile {
+    // the file's name in the data directory
+    pub name: String,
+    pub url: String,
+    // lowercase hex; the download is not verified without one
+    pub sha256: Option<String>,
+}
+
+impl DataFile {
+    /// The UN/LOCODE code list as published in CSV by the datasets/un-locode
+    /// repository, at `rev`, a release tag or commit.
+    pub fn unlocode_code_list(rev: &str, sha256: Option<&str>) -> Self {
+        DataFile {
+            name: CODE_LIST_FILE.to_string(),
+            url: format!(
+                "https://raw.githubusercontent.com/datasets/un-locode/{rev}/data/code-list.csv"
+            ),
+            sha256: sha256.map(str::to_lowercase),
+        }
+    }
+    pub fn verify(&self, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        let Some(expected) = &self.sha256 else {
+            return Ok(());
+        };
+        let actual = format!("{:x}", Sha256::digest(bytes));
+        match actual == expected.to_lowercase() {
+            true => Ok(()),
+            false => Err(format!(
+                "checksum mismatch for {}: expected {expected}, got {actual}",
+                self.name
+            )
+            .into()),
+        }
+    }
+}
+
+impl Release {
+    pub fn read_manifest<R: Read>(reader: R) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+    /// Downloads every file into `data_dir`, creating it if need be, and
+    /// returns their paths. All the files are verified before any is
+    /// written, so a failed download leaves the directory as it was.
+    pub fn fetch(&self, data_dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let mut downloads = vec![];
+        for file in &self.files {
+            let mut bytes = vec![];
+            ureq::get(&file.url)
+                .call()?
+                .into_reader()
+                .read_to_end(&mut bytes)?;
+            file.verify(&bytes)?;
+            downloads.push((data_dir.join(&file.name), bytes));
+        }
+        fs::create_dir_all(data_dir)?;
+        for (path, bytes) in &downloads {
+            let mut partial = path.clone().into_os_string();
+            partial.push(".partial");
+            fs::File::create(&partial)?.write_all(bytes)?;
+            fs::rename(&partial, path)?;
+        }
+        Ok(downloads.into_iter().map(|(path, _)| path).collect())
+    }
+    /// Checks the files already in `data_dir` against the release.
+    pub fn verify(&self, data_dir: &Path) -> Result<(), Box<dyn Error>> {
+        for file in &self.files {
+            file.verify(&fs::read(data_dir.join(&file.name))?)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+//! Character filters a database applies on top of `normalize`, alike to
+//! the names it indexes and to queries, as set in `IndexOptions::filters`.
+
+use std::ops::Range;
+
+use deunicode::AsciiChars;
+use serde::{Deserialize, Serialize};
+use ustr::Ustr;
+
+use crate::config::CaseLocale;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CharFilter {
+    // Unicode case folding, tailored to a locale
+    FoldCase(CaseLocale),
+    Lowercase,
+    // transliteration to ASCII, which drops diacritics
+    FoldDiacritics,
+    Replace { from: String, to: String },
+    // punctuation to spaces, except the ':' of codes such as "gb:abc"
+    StripPunctuation,
+    // runs of whitespace to a single space, trimming the ends
+    CollapseWhitespace,
+}
+
+impl CharFilter {
+    fn apply(&self, s: String, locale: Option<CaseLocale>) -> String {
+        match self {
+            CharFilter::FoldCase(default) => crate::fold_case(&s, locale.unwrap_or(*default)),
+            // a character at a time, as `apply_spans` must
+            CharFilter::Lowercase => s.chars().flat_map(char::to_lowercase).collect(),
+            CharFilter::FoldDiacritics => deunicode::deunicode(&s),
+            CharFilter::Replace { from, to } => s.replace(from.as_str(), to),
+            CharFilter::StripPunctuation => s
+                .chars()
+                .map(|c| match c.is_ascii_punctuation() && c != ':' {
+                    true => ' ',
+                    false => c,
+                })
+                .collect(),
+            CharFilter::CollapseWhitespace => s.split_whitespace().collect::<Vec<_>>().join(" "),
+        }
+    }
+    // As `apply`, carrying over the span of the original text that each
+    // byte of `s`, given in `spans`, came from.
+    fn apply_spans(
+        &self,
+        s: &str,
+        spans: &[Range<usize>],
+        locale: Option<CaseLocale>,
+    ) -> (String, Vec<Range<usize>>) {
+        let mut out = String::with_capacity(s.len());
+        let mut out_spans = Vec::with_capacity(spans.len());
+        let end = spans.last().map_or(0, |span| span.end);
+        // the bytes `from` of `s` became `text`
+        let mut push = |text: &str, from: Range<usize>| {
+            let span = match from.is_empty() {
+                true => {
+                    let at = spans.get(from.start).map_or(end, |span| span.start);
+                    at..at
+                }
+                false => spans[from.start].start..spans[from.end - 1].end,
+            };
+            out.push_str(text);
+            out_spans.extend(std::iter::repeat_n(span, text.len()));
+        };
+        // the bytes `from` of `s` were kept as they are
+        let keep = |push: &mut dyn FnMut(&str, Range<usize>), from: Range<usize>| {
+            for (n, c) in s[from.clone()].char_indices() {
+                let n = from.start + n;
+                push(&s[n..n + c.len_utf8()], n..n + c.len_utf8());
+            }
+        };
+        let per_char = |c: char| -> String {
+            match self {
+                CharFilter::FoldCase(default) => {
+                    crate::fold_case(c.encode_utf8(&mut [0; 4]), locale.unwrap_or(*default))
+                }
+                CharFilter::Lowercase => c.to_lowercase().collect(),
+                CharFilter::StripPunctuation if c.is_ascii_punctuation() && c != ':' => {
+                    " ".to_string()
+                }
+                _ => c.to_string(),
+            }
+        };
+        match self {
+            CharFilter::FoldDiacritics => {
+                for ((n, c), ascii) in s.char_indices().zip(s.ascii_chars()) {
+                    push(ascii.unwrap_or("[?]"), n..n + c.len_utf8());
+                }
+            }
+            CharFilter::Replace { from, to } => {
+                let mut last = 0;
+                for (n, matched) in s.match_indices(from.as_str()) {
+                    keep(&mut push, last..n);
+                    push(to, n..n + matched.len());
+                    last = n + matched.len();
+                }
+                keep(&mut push, last..s.len());
+            }
+            CharFilter::CollapseWhitespace => {
+                let mut last = None;
+                for word in s.split_whitespace() {
+                    let n = word.as_ptr() as usize - s.as_ptr() as usize;
+                    if let Some(end) = last {
+                        push(" ", end..n);
+                    }
+                    keep(&mut push, n..n + word.len());
+                    last = Some(n + word.len());
+                }
+            }
+            _ => {
+                for (n, c) in s.char_indices() {
+                    push(&per_char(c), n..n + c.len_utf8());
+                }
+            }
+        }
+        (out, out_spans)
+    }
+}
+
+/// Filters applied in order; the default chain is empty.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilterChain(pub Vec<CharFilter>);
+
+impl FilterChain {
+    /// The folding `normalize` applies: case folding, then transliteration
+    /// to lowercase ASCII.
+    pub fn standard() -> Self {
+        FilterChain(vec![
+            CharFilter::FoldCase(CaseLocale::Unicode),
+            CharFilter::FoldDiacritics,
+            CharFilter::Lowercase,
+        ])
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    /// Applies the chain, folding case by `locale` in place of the
+    /// chain's own where given.
+    pub fn apply(&self, s: &str, locale: Option<CaseLocale>) -> String {
+        self.0
+            .iter()
+            .fold(s.to_string(), |s, filter| filter.apply(s, locale))
+    }
+    /// Applies the chain as `apply` does, along with the span of `s` each
+    /// byte of the result came from, for mapping matches in a normalized
+    /// query back to the raw one.
+    pub fn apply_spans(&self, s: &str, locale: Option<CaseLocale>) -> (String, Vec<Range<usize>>) {
+        let spans = s
+            .char_indices()
+            .flat_map(|(n, c)| std::iter::repeat_n(n..n + c.len_utf8(), c.len_utf8()))
+            .collect::<Vec<_>>();
+        self.0
+            .iter()
+            .fold((s.to_string(), spans), |(s, spans), filter| {
+                filter.apply_spans(&s, &spans, locale)
+            })
+    }
+    /// The chain followed by another's filters.
+    pub fn then(mut self, other: &FilterChain) -> Self {
+        self.0.extend(other.0.iter().cloned());
+        self
+    }
+    /// Applies the chain to an indexed string, which an empty chain leaves
+    /// as it is.
+    pub fn apply_ustr(&self, s: Ustr) -> Ustr {
+        match self.is_empty() {
+            true => s,
+            false => Ustr::from(&self.apply(&s, None)),
+        }
+    }
+}
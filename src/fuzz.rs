@@ -0,0 +1,178 @@
+//! Entry points for fuzzing, enabled by the `arbitrary` feature: search
+//! terms and raw locations built from arbitrary bytes, and functions
+//! running them through the parsers and search. None of these should
+//! panic, whatever the input; the cargo-fuzz targets in `fuzz/` call them.
+
+use arbitrary::Arbitrary;
+use serde_json::{json, Map, Value};
+use ustr::Ustr;
+
+use crate::config::SearchConfig;
+use crate::coordinates::coordinate_parser;
+use crate::location::{
+    AnyLocation, Location, IATA_ENCODING, LOCODE_ENCODING, REGION_ENCODING, STATE_ENCODING,
+    SUBDIV_ENCODING,
+};
+use crate::locations_db::LocationsDb;
+use crate::search::{Score, SearchTerm};
+
+const ENCODINGS: [&str; 6] = [
+    STATE_ENCODING,
+    SUBDIV_ENCODING,
+    LOCODE_ENCODING,
+    IATA_ENCODING,
+    REGION_ENCODING,
+    "FUZZ-GENERIC",
+];
+
+// The fields the parsers read, so that arbitrary input reaches them rather
+// than only the extra metadata.
+const FIELDS: [&str; 29] = [
+    "name",
+    "short",
+    "alpha2",
+    "alpha3",
+    "continent",
+    "official_en",
+    "name:fr",
+    "supercode",
+    "subcode",
+    "subdivision_code",
+    "subdivision_name",
+    "level",
+    "function_code",
+    "status",
+    "iata",
+    "icao",
+    "type",
+    "city",
+    "country",
+    "region",
+    "x",
+    "y",
+    "c",
+    "lat",
+    "lon",
+    "tags",
+    "weight",
+    "code",
+    "elevation",
+];
+
+/// A query and the parameters of its search. Those the API bounds, the
+/// Levenshtein distance and the limit, are kept within those bounds.
+#[derive(Debug, Clone, Arbitrary)]
+pub struct FuzzQuery {
+    pub query: String,
+    pub state: Option<String>,
+    pub limit: u8,
+    pub lev_distance: u8,
+    pub stem: bool,
+    pub doublets: bool,
+    pub triplets: bool,
+}
+
+impl FuzzQuery {
+    pub fn search_term(&self) -> SearchTerm {
+        let config = SearchConfig {
+            stem: self.stem,
+            doublets: self.doublets,
+            triplets: self.triplets,
+            ..Default::default()
+        };
+        SearchTerm::with_config(
+            self.query.clone(),
+            self.state.clone(),
+            self.limit as usize,
+            (self.lev_distance % 4) as u32,
+            config,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Arbitrary)]
+pub enum FuzzValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    List(Vec<String>),
+}
+
+impl FuzzValue {
+    fn to_json(&self) -> Value {
+        match self {
+            FuzzValue::Null => Value::Null,
+            FuzzValue::Bool(b) => json!(b),
+            FuzzValue::Int(n) => json!(n),
+            FuzzValue::Float(x) => json!(x),
+            FuzzValue::Str(s) => json!(s),
+            FuzzValue::List(l) => json!(l),
+        }
+    }
+}
+
+/// A location in the raw JSON shape of the datasets, with fields drawn
+/// mostly from those the parsers read.
+#[derive(Debug, Clone, Arbitrary)]
+pub struct FuzzLocation {
+    pub encoding: u8,
+    pub id: String,
+    pub fields: Vec<(u8, FuzzValue)>,
+    pub other_fields: Vec<(String, FuzzValue)>,
+}
+
+impl FuzzLocation {
+    pub fn to_json(&self) -> Value {
+        let mut d = Map::new();
+        for (field, value) in &self.fields {
+            d.insert(
+                FIELDS[*field as usize % FIELDS.len()].to_string(),
+                value.to_json(),
+            );
+        }
+        for (field, value) in &self.other_fields {
+            d.insert(field.clone(), value.to_json());
+        }
+        json!({
+            "<c>": ENCODINGS[self.encoding as usize % ENCODINGS.len()],
+            "i": self.id,
+            "d": d,
+        })
+    }
+    pub fn location(&self) -> serde_json::Result<Location> {
+        Location::from_raw(serde_json::from_value::<AnyLocation>(self.to_json())?)
+    }
+}
+
+/// Searches the query.
+pub fn fuzz_search(db: &LocationsDb, query: &FuzzQuery) -> Vec<(Ustr, Score)> {
+    let st = query.search_term();
+    st.parsed();
+    let results = db.search(&st);
+    st.remainder(results.iter().map(|(_, score)| score.offset));
+    results
+}
+
+/// Parses the location, indexes it if it parses, then searches its names
+/// and writes it back out as raw JSON.
+pub fn fuzz_location(location: &FuzzLocation) {
+    let Ok(loc) = location.location() else {
+        return;
+    };
+    let names = loc.get_names();
+    let raw = loc.to_raw(None);
+    let mut db = LocationsDb::default();
+    db.insert(loc);
+    let db = db.mk_fst();
+    for name in names {
+        db.search(&SearchTerm::from_raw_query(name.to_string(), None, 5, 2));
+    }
+    serde_json::to_string(&raw).expect("raw location serializes");
+}
+
+/// Parses UN/LOCODE coordinates, as "5130N 00007W".
+pub fn fuzz_coordinates(input: &str) {
+    let _ = coordinate_parser(input);
+}
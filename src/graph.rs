@@ -1,43 +1,74 @@
 use std::cmp::{max, min};
 
 use petgraph::graphmap::DiGraphMap;
+use tracing::{debug_span, field};
 use ustr::{Ustr, UstrMap};
 
+use crate::config::Propagation;
+use crate::explain::{Boost, BoostKind};
 use crate::locations_db::LocationsDb;
 use crate::search::Score;
 use crate::GRAPH_EDGE_THRESHOLD;
 
 pub struct ResultsGraph {
     pub(crate) scores: UstrMap<Score>,
+    // what each boosted result was lifted by
+    pub(crate) boosts: UstrMap<Vec<Boost>>,
 }
 
 impl ResultsGraph {
+    /// Whether any candidate's state or subdivision is also a candidate,
+    /// without which the graph would leave the scores as they are.
+    pub fn has_relations(results: &UstrMap<Score>, db: &LocationsDb) -> bool {
+        results.len() > 1
+            && results.keys().any(|key| {
+                let (state_key, subdiv_key) = db.all[key].get_parents();
+                [state_key, subdiv_key]
+                    .into_iter()
+                    .flatten()
+                    .any(|superkey| results.contains_key(&superkey))
+            })
+    }
+    /// Leaves the scores as they are.
+    pub fn unlinked(results: UstrMap<Score>) -> Self {
+        ResultsGraph {
+            scores: results,
+            boosts: Default::default(),
+        }
+    }
     pub fn from_results(mut results: UstrMap<Score>, db: &LocationsDb) -> Self {
-        // let start = Instant::now();
+        let span = debug_span!("graph", results = results.len(), edges = field::Empty).entered();
         let mut graph: DiGraphMap<Ustr, _> = DiGraphMap::new();
         results.iter().for_each(|(key, score)| {
             let loc = db.all.get(key).expect("location in db");
             graph.add_node(loc.key);
             let (state_key, subdiv_key) = loc.get_parents();
-            for key in [state_key, subdiv_key] {
-                if let Some(superkey) = key {
-                    if let Some(superkey_score) = results.get(&superkey) {
-                        if min(superkey_score.score, score.score) > GRAPH_EDGE_THRESHOLD {
-                            let weight = (superkey_score.score, score.score);
-                            graph.add_edge(superkey, loc.key, weight);
-                        }
+            for superkey in [state_key, subdiv_key].into_iter().flatten() {
+                if let Some(superkey_score) = results.get(&superkey) {
+                    if min(superkey_score.score, score.score) > GRAPH_EDGE_THRESHOLD {
+                        let weight = (superkey_score.score, score.score);
+                        graph.add_edge(superkey, loc.key, weight);
                     }
                 }
             }
         });
         let mut edges = graph.all_edges().collect::<Vec<_>>();
+        span.record("edges", edges.len());
         edges.sort_unstable_by(|a, b| b.2.cmp(a.2));
-        edges.into_iter().enumerate().for_each(|(_i, edge)| {
+        let mut boosts: UstrMap<Vec<Boost>> = UstrMap::default();
+        edges.into_iter().for_each(|edge| {
             let loc = db.all.get(&edge.1).unwrap();
             let parent = db.all.get(&edge.0).unwrap();
             let parent_boost = parent.parent_boost(edge.2 .0);
             let total_score = parent_boost + edge.2 .1;
             let old = results.get(&loc.key).cloned().unwrap();
+            if total_score > old.score {
+                boosts.entry(loc.key).or_default().push(Boost {
+                    kind: BoostKind::Parent,
+                    source: parent.key,
+                    amount: total_score - old.score,
+                });
+            }
             results.insert(
                 loc.key,
                 Score {
@@ -46,8 +77,66 @@ impl ResultsGraph {
                 },
             );
         });
-        // info!("Graph analysis in {:.3?}", start.elapsed());
-        ResultsGraph { scores: results }
+        ResultsGraph {
+            scores: results,
+            boosts,
+        }
+    }
+    /// Builds the graph as `from_results` does, then, for each iteration,
+    /// rescores every result from its own score plus damped evidence from
+    /// its neighbours: the parent boost of each parent and the margin of
+    /// each child over the edge threshold.
+    pub fn propagated(mut results: UstrMap<Score>, db: &LocationsDb, p: &Propagation) -> Self {
+        let _span =
+            debug_span!("graph", results = results.len(), iterations = p.iterations).entered();
+        let mut edges = vec![];
+        results.iter().for_each(|(key, score)| {
+            let (state_key, subdiv_key) = db.all[key].get_parents();
+            for superkey in [state_key, subdiv_key].into_iter().flatten() {
+                if let Some(superkey_score) = results.get(&superkey) {
+                    if min(superkey_score.score, score.score) > GRAPH_EDGE_THRESHOLD {
+                        edges.push((superkey, *key));
+                    }
+                }
+            }
+        });
+        let base: UstrMap<i64> = results.iter().map(|(k, s)| (*k, s.score)).collect();
+        let damped = |evidence: i64| (evidence as f64 * p.damping).round() as i64;
+        let mut current = base.clone();
+        let mut boosts: UstrMap<Vec<Boost>> = UstrMap::default();
+        for _ in 0..p.iterations {
+            boosts.clear();
+            for (parent, child) in &edges {
+                let down = db.all[parent].parent_boost(current[parent]);
+                let up = current[child] - GRAPH_EDGE_THRESHOLD;
+                boosts.entry(*child).or_default().push(Boost {
+                    kind: BoostKind::Parent,
+                    source: *parent,
+                    amount: damped(down),
+                });
+                boosts.entry(*parent).or_default().push(Boost {
+                    kind: BoostKind::Child,
+                    source: *child,
+                    amount: damped(up),
+                });
+            }
+            current = base
+                .iter()
+                .map(|(k, score)| {
+                    let gained = boosts
+                        .get(k)
+                        .map_or(0, |b| b.iter().map(|b| b.amount).sum());
+                    (*k, score + gained)
+                })
+                .collect();
+        }
+        results.iter_mut().for_each(|(key, score)| {
+            score.score = max(current[key], score.score);
+        });
+        ResultsGraph {
+            scores: results,
+            boosts,
+        }
     }
 }
 
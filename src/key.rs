@@ -0,0 +1,159 @@
+use std::cmp::Reverse;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use ustr::Ustr;
+
+use crate::location::{
+    IATA_ENCODING, LOCODE_ENCODING, REGION_ENCODING, STATE_ENCODING, SUBDIV_ENCODING,
+};
+
+// the encodings every key may start with
+const STANDARD_ENCODINGS: [&str; 5] = [
+    STATE_ENCODING,
+    SUBDIV_ENCODING,
+    LOCODE_ENCODING,
+    IATA_ENCODING,
+    REGION_ENCODING,
+];
+
+/// A location key, `{encoding}-{id}`, e.g. `UN-LOCODE-gb:abc`. Ids within a
+/// country are `{country}:{code}`; states are keyed by their country code
+/// alone.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LocationKey {
+    pub encoding: Ustr,
+    pub id: Ustr,
+}
+
+#[derive(Debug)]
+pub struct InvalidKey(pub String);
+
+impl fmt::Display for InvalidKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid location key: {}", self.0)
+    }
+}
+
+impl Error for InvalidKey {}
+
+impl LocationKey {
+    pub fn new(encoding: Ustr, id: Ustr) -> Self {
+        LocationKey { encoding, id }
+    }
+    /// Parses a key by its standard encoding, or as `parse_with` does for
+    /// any other.
+    pub fn parse(key: &str) -> Result<Self, InvalidKey> {
+        Self::parse_with(key, [])
+    }
+    /// Parses a key by the longest of `prefixes` or the standard encodings
+    /// it starts with, such as the key namespaces and custom kinds of a
+    /// database. Failing those, the id is taken to start after the last dash
+    /// before any country separator.
+    pub fn parse_with<'a>(
+        key: &str,
+        prefixes: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self, InvalidKey> {
+        let mut prefixes = prefixes
+            .into_iter()
+            .chain(STANDARD_ENCODINGS)
+            .collect::<Vec<_>>();
+        prefixes.sort_unstable_by_key(|p| Reverse(p.len()));
+        let known = prefixes.into_iter().find_map(|prefix| {
+            let id = key.strip_prefix(prefix)?.strip_prefix('-')?;
+            (!id.is_empty()).then(|| LocationKey::new(prefix.into(), id.into()))
+        });
+        if let Some(key) = known {
+            return Ok(key);
+        }
+        let head = key.split(':').next().unwrap_or(key);
+        match head.rfind('-') {
+            Some(split) if split > 0 && split + 1 < key.len() => Ok(LocationKey {
+                encoding: key[..split].into(),
+                id: key[split + 1..].into(),
+            }),
+            _ => Err(InvalidKey(key.to_string())),
+        }
+    }
+    /// The interned key string, as used by `LocationsDb`.
+    pub fn as_ustr(&self) -> Ustr {
+        Ustr::from(&self.to_string())
+    }
+    /// The key string if it is already interned, as it is for any location
+    /// loaded, without interning it otherwise.
+    pub fn existing(&self) -> Option<Ustr> {
+        Ustr::from_existing(&self.to_string())
+    }
+    pub fn country(&self) -> Option<&str> {
+        match self.id.split_once(':') {
+            Some((country, _)) => Some(country),
+            None if self.encoding == STATE_ENCODING => Some(self.id.as_str()),
+            None => None,
+        }
+    }
+    pub fn code(&self) -> &str {
+        match self.id.split_once(':') {
+            Some((_, code)) => code,
+            None => self.id.as_str(),
+        }
+    }
+}
+
+/// A key as given to the lookups of `LocationsDb`: a `LocationKey`, or a
+/// key string. Keys that were never interned are not interned by a lookup.
+pub trait AsKey {
+    fn existing_key(&self) -> Option<Ustr>;
+}
+
+impl AsKey for LocationKey {
+    fn existing_key(&self) -> Option<Ustr> {
+        self.existing()
+    }
+}
+
+impl AsKey for Ustr {
+    fn existing_key(&self) -> Option<Ustr> {
+        Some(*self)
+    }
+}
+
+impl AsKey for str {
+    fn existing_key(&self) -> Option<Ustr> {
+        Ustr::from_existing(self)
+    }
+}
+
+impl AsKey for String {
+    fn existing_key(&self) -> Option<Ustr> {
+        Ustr::from_existing(self)
+    }
+}
+
+impl fmt::Display for LocationKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.encoding, self.id)
+    }
+}
+
+impl FromStr for LocationKey {
+    type Err = InvalidKey;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LocationKey::parse(s)
+    }
+}
+
+impl Serialize for LocationKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for LocationKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let key = String::deserialize(deserializer)?;
+        LocationKey::parse(&key).map_err(serde::de::Error::custom)
+    }
+}
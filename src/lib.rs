@@ -3,31 +3,124 @@ extern crate core;
 use std::collections::HashSet;
 use std::hash::Hash;
 
+use crate::config::CaseLocale;
+
 pub use deunicode;
+#[cfg(feature = "metrics")]
+pub use opentelemetry;
+#[cfg(feature = "parallel")]
 pub use rayon;
 pub use smallvec;
 pub use ustr;
 
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "async")]
+pub mod async_api;
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod bulk;
+pub mod config;
 pub mod coordinates;
+pub mod diff;
+#[cfg(feature = "embedded-data")]
+pub mod embedded;
+pub mod eval;
+pub mod explain;
+#[cfg(feature = "fs")]
+pub mod export;
+pub mod feedback;
+#[cfg(feature = "fetch")]
+pub mod fetch;
+pub mod filters;
+#[cfg(feature = "arbitrary")]
+pub mod fuzz;
 mod graph;
+pub mod key;
+pub mod lint;
 pub mod location;
 pub mod locations_db;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "node")]
+pub mod node;
+pub mod profile;
+#[cfg(feature = "python")]
+mod python;
+pub mod regions;
+pub mod registry;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod reload;
+#[cfg(feature = "object-store")]
+pub mod remote;
+pub mod schema;
 pub mod search;
+pub mod segment;
+pub mod session;
+pub mod shard;
+pub mod similarity;
+pub mod slow_query;
+pub mod snapshot;
+pub mod stats;
+#[cfg(feature = "test_support")]
+pub mod test_support;
+pub mod time;
 
 const SCORE_SOFT_MAX: i64 = 1000;
+const LOCODE_CODE_BOOST: i64 = 48;
 const STATE_CODE_BOOST: i64 = 32;
 const SUBDIV_CODE_BOOST: i64 = 16;
+// for airports named by their code in `LocationsDb::search_airports`
+const AIRPORT_CODE_BOOST: i64 = 48;
+// for the locations a search profile prefers
+const PROFILE_FUNCTION_BOOST: i64 = 50;
 const LEV_3_LENGTH_MAX: usize = 10;
 const LEV_2_LENGTH_MAX: usize = 20;
 const LEV_LENGTH_MAX: usize = 40;
+// query tokens shorter than this are ignored, and those up to the code
+// length matched exactly only
+const MIN_TOKEN_LENGTH: usize = 2;
+const MAX_CODE_LENGTH: usize = 3;
+// the shortest words of names indexed on their own
+const MIN_WORD_LENGTH: usize = 4;
 
 const SINGLE_WORD_MATCH_PENALTY: i64 = 100;
+// for doubtful code list entries, in `standard_status_rules`
+const STATUS_PENALTY: i64 = 100;
 
 const SEARCH_INCLUSION_THRESHOLD: i64 = 400;
 const GRAPH_EDGE_THRESHOLD: i64 = 600;
+// results this close to the best score are as good a match
+const AMBIGUITY_MARGIN: i64 = 50;
 
+/// Folds case, then transliterates to lowercase ASCII, as
+/// `FilterChain::standard` does.
 pub fn normalize(s: &str) -> String {
-    deunicode::deunicode(s).to_lowercase()
+    normalize_with(s, CaseLocale::Unicode)
+}
+
+/// Folds case by the conventions of `locale`, then transliterates to
+/// lowercase ASCII.
+pub fn normalize_with(s: &str, locale: CaseLocale) -> String {
+    deunicode::deunicode(&fold_case(s, locale)).to_lowercase()
+}
+
+/// Unicode case folding, with the tailoring of `locale` where it has one.
+pub fn fold_case(s: &str, locale: CaseLocale) -> String {
+    match locale {
+        CaseLocale::Unicode => caseless::default_case_fold_str(s),
+        CaseLocale::Turkic => {
+            let dotted = s
+                .chars()
+                .map(|c| match c {
+                    'I' => 'ı',
+                    'İ' => 'i',
+                    c => c,
+                })
+                .collect::<String>();
+            caseless::default_case_fold_str(&dotted)
+        }
+    }
 }
 
 pub fn dedup<T: Eq + Hash>(vec: Vec<T>) -> Vec<T> {
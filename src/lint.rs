@@ -0,0 +1,174 @@
+//! Checking the quality of the data before it is shipped: referential
+//! integrity, coordinate ranges, duplicated names and text encoding.
+
+use std::collections::BTreeMap;
+#[cfg(feature = "fs")]
+use std::error::Error;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use serde::Serialize;
+#[cfg(feature = "fs")]
+use serde_json::Value;
+use ustr::Ustr;
+
+use crate::coordinates::Coordinates;
+#[cfg(feature = "fs")]
+use crate::locations_db::{parse_data_files, CODE_LIST_FILE, DATA_FILES};
+use crate::locations_db::{LocationsDb, ValidationReport};
+
+/// Problems found by `lint` and `lint_data_dir`, each list sorted.
+#[derive(Debug, Default, Serialize)]
+pub struct LintReport {
+    pub validation: ValidationReport,
+    // outside -90 to 90 degrees of latitude or -180 to 180 of longitude
+    pub invalid_coordinates: Vec<Ustr>,
+    // at 0, 0, which usually stands for unknown coordinates
+    pub null_coordinates: Vec<Ustr>,
+    // primary names shared by locations of the same encoding, state and
+    // subdivision, which search cannot tell apart
+    pub duplicate_names: Vec<(Ustr, Vec<Ustr>)>,
+    // as (file, problem), for files that are not valid UTF-8 or hold text
+    // that was mis-decoded
+    pub encoding_problems: Vec<(String, String)>,
+}
+
+impl LintReport {
+    /// Whether there are any problems, other than those
+    /// `ValidationReport::has_errors` leaves aside.
+    pub fn has_errors(&self) -> bool {
+        self.validation.has_errors()
+            || !(self.invalid_coordinates.is_empty()
+                && self.null_coordinates.is_empty()
+                && self.duplicate_names.is_empty()
+                && self.encoding_problems.is_empty())
+    }
+}
+
+/// Lints a loaded database.
+pub fn lint(db: &LocationsDb) -> LintReport {
+    let mut report = LintReport {
+        validation: db.validate(),
+        ..Default::default()
+    };
+    let mut names: BTreeMap<(Ustr, Ustr, Ustr, Option<Ustr>), Vec<Ustr>> = BTreeMap::new();
+    for loc in db.all.values() {
+        match loc.get_coordinates() {
+            Some(Coordinates { lat, .. }) if !(-90. ..=90.).contains(&lat) => {
+                report.invalid_coordinates.push(loc.key)
+            }
+            Some(Coordinates { lon, .. }) if !(-180. ..=180.).contains(&lon) => {
+                report.invalid_coordinates.push(loc.key)
+            }
+            Some(Coordinates { lat, lon }) if lat == 0. && lon == 0. => {
+                report.null_coordinates.push(loc.key)
+            }
+            _ => {}
+        }
+        if let Some(name) = loc.get_names().first() {
+            let group = (*name, loc.encoding, loc.get_state(), loc.get_subdiv());
+            names.entry(group).or_default().push(loc.key);
+        }
+    }
+    report.duplicate_names = names
+        .into_iter()
+        .filter(|(_, keys)| keys.len() > 1)
+        .map(|((name, ..), mut keys)| {
+            keys.sort_unstable_by_key(|k| k.as_str());
+            (name, keys)
+        })
+        .collect();
+    report
+        .invalid_coordinates
+        .sort_unstable_by_key(|k| k.as_str());
+    report.null_coordinates.sort_unstable_by_key(|k| k.as_str());
+    report
+}
+
+/// Lints a data directory laid out as for `parse_data_files`. The files
+/// are checked for their encoding first, and only loaded and linted as a
+/// database if they are all readable.
+#[cfg(feature = "fs")]
+pub fn lint_data_dir(data_dir: &Path) -> Result<LintReport, Box<dyn Error>> {
+    let mut problems = vec![];
+    for file in DATA_FILES.iter().chain([&CODE_LIST_FILE]) {
+        let bytes = std::fs::read(data_dir.join(file))?;
+        let text = match String::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(err) => {
+                problems.push((file.to_string(), format!("not UTF-8: {}", err.utf8_error())));
+                continue;
+            }
+        };
+        match *file == CODE_LIST_FILE {
+            true => lint_text(file, &text, &mut problems),
+            false => match serde_json::from_str::<Value>(&text) {
+                Ok(json) => lint_json(file, &json, &mut problems),
+                Err(err) => problems.push((file.to_string(), format!("not JSON: {err}"))),
+            },
+        }
+    }
+    if problems.iter().any(|(_, p)| p.starts_with("not ")) {
+        return Ok(LintReport {
+            encoding_problems: problems,
+            ..Default::default()
+        });
+    }
+    let mut report = lint(&parse_data_files(data_dir.to_path_buf())?);
+    report.encoding_problems = problems;
+    Ok(report)
+}
+
+// Text that was decoded as Latin-1 when it was UTF-8 shows sequences such
+// as "Ã©" for "é"; replacement and control characters are lost text.
+#[cfg(feature = "fs")]
+fn encoding_problem(s: &str) -> Option<&'static str> {
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\u{fffd}' => return Some("replacement character"),
+            '\t' | '\n' | '\r' => {}
+            c if c.is_control() => return Some("control character"),
+            'Ã' | 'Â'
+                if chars
+                    .peek()
+                    .is_some_and(|n| ('\u{80}'..='\u{bf}').contains(n)) =>
+            {
+                return Some("mis-decoded UTF-8")
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(feature = "fs")]
+fn lint_json(file: &str, json: &Value, problems: &mut Vec<(String, String)>) {
+    let Value::Object(locations) = json else {
+        return;
+    };
+    for (id, loc) in locations {
+        let mut strings = vec![loc];
+        while let Some(value) = strings.pop() {
+            match value {
+                Value::String(s) => {
+                    if let Some(problem) = encoding_problem(s) {
+                        problems.push((file.to_string(), format!("{id}: {problem} in {s:?}")));
+                    }
+                }
+                Value::Array(values) => strings.extend(values),
+                Value::Object(fields) => strings.extend(fields.values()),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(feature = "fs")]
+fn lint_text(file: &str, text: &str, problems: &mut Vec<(String, String)>) {
+    for (n, line) in text.lines().enumerate() {
+        if let Some(problem) = encoding_problem(line) {
+            problems.push((file.to_string(), format!("line {}: {problem}", n + 1)));
+        }
+    }
+}
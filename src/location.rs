@@ -1,17 +1,22 @@
 use std::cmp::max;
 use std::collections::HashMap;
+use std::mem::size_of;
 
 use serde::de::Error;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use smallvec::{smallvec, SmallVec};
 use tracing::error;
 use ustr::{Ustr, UstrSet};
 
+use crate::config::{IndexOptions, StatusRule};
 use crate::coordinates::Coordinates;
-use crate::search::{Score, SearchTerm};
+use crate::key::LocationKey;
+use crate::locations_db::LocationsDb;
+use crate::search::{respell_punctuation, Score, SearchTerm};
 use crate::{
-    coordinates, normalize, SCORE_SOFT_MAX, SINGLE_WORD_MATCH_PENALTY, STATE_CODE_BOOST,
-    SUBDIV_CODE_BOOST,
+    coordinates, normalize, LOCODE_CODE_BOOST, SCORE_SOFT_MAX, SINGLE_WORD_MATCH_PENALTY,
+    STATE_CODE_BOOST, SUBDIV_CODE_BOOST,
 };
 
 #[derive(Debug, Deserialize)]
@@ -22,81 +27,320 @@ pub struct AnyLocation {
     d: serde_json::Value,
 }
 
-const STATE_ENCODING: &str = "ISO-3166-1";
+pub const STATE_ENCODING: &str = "ISO-3166-1";
 
 pub fn state_key(state_code: Ustr) -> Option<Ustr> {
-    let str = format!("{}-{}", STATE_ENCODING, state_code.as_str());
-    Ustr::from_existing(str.as_str())
+    LocationKey::new(STATE_ENCODING.into(), state_code).existing()
 }
 
-const SUBDIV_ENCODING: &str = "ISO-3166-2";
+pub const SUBDIV_ENCODING: &str = "ISO-3166-2";
 
 pub fn subdiv_key(state_code: Ustr, subdiv_code: Ustr) -> Option<Ustr> {
-    let str = format!(
-        "{}-{}:{}",
-        SUBDIV_ENCODING,
-        state_code.as_str(),
-        subdiv_code.as_str()
-    );
-    Ustr::from_existing(str.as_str())
+    let id = Ustr::from_existing(&format!("{state_code}:{subdiv_code}"))?;
+    LocationKey::new(SUBDIV_ENCODING.into(), id).existing()
 }
 
 pub const LOCODE_ENCODING: &str = "UN-LOCODE";
-const IATA_ENCODING: &str = "IATA";
+pub const IATA_ENCODING: &str = "IATA";
+pub const REGION_ENCODING: &str = "REGION";
 
-#[derive(Debug, Serialize, Clone)]
+/// A language tag, e.g. `fr` or `de`.
+pub type LangTag = Ustr;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Location {
     pub key: Ustr,
     pub encoding: Ustr,
     pub id: Ustr,
     pub words: SmallVec<[Ustr; 3]>,
     pub data: LocData,
+    // translations and other alternative names, by language
+    #[serde(default)]
+    pub alt_names: Vec<(LangTag, Ustr)>,
+    // codes of retired locations redirected here
+    #[serde(default)]
+    pub former_codes: SmallVec<[Ustr; 1]>,
+    // the same place under other code systems, e.g. an airport's LOCODE
+    #[serde(default)]
+    pub related_keys: SmallVec<[Ustr; 1]>,
+    // the regions, such as the EU, this location is a member of, as
+    // virtual parents set by `add_regions`
+    #[serde(default)]
+    pub regions: SmallVec<[Ustr; 2]>,
+    // categories given by the data or the application, such as "port" or
+    // "customer-region-a", for `SearchTerm::with_tags_any` and `_all`
+    #[serde(default)]
+    pub tags: SmallVec<[Ustr; 2]>,
+    // added to the score of every match, so that data owners can rank
+    // important records, such as major hubs, above obscure namesakes
+    #[serde(default)]
+    pub weight: i64,
+    // dataset fields not otherwise recognised, kept as they were
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+    // populated by `LocationsDb::mk_fst`
+    #[serde(skip)]
+    pub(crate) searchable: SearchableFields,
+}
+
+/// Flattened, normalized strings a location is scored against, so the
+/// scoring loop does not re-derive them from `LocData` on every query.
+#[derive(Debug, Clone, Default)]
+pub struct SearchableFields {
+    // codes that resolve directly to this location, along with their boost
+    pub codes: SmallVec<[Ustr; 3]>,
+    pub code_boost: i64,
+    // names and codes that are matched as free text
+    pub strings: SmallVec<[Ustr; 2]>,
+}
+
+impl SearchableFields {
+    pub fn from_data(data: &LocData) -> Self {
+        match data {
+            LocData::St(d) => SearchableFields {
+                codes: d.get_codes().into_iter().collect(),
+                code_boost: STATE_CODE_BOOST,
+                strings: smallvec![d.name],
+            },
+            LocData::Subdv(d) => SearchableFields {
+                codes: smallvec![d.subcode],
+                code_boost: SUBDIV_CODE_BOOST,
+                strings: smallvec![d.name],
+            },
+            LocData::Locd(d) => SearchableFields {
+                // the official "GB ABC" notation, as "gb:abc"
+                codes: smallvec![Ustr::from(&format!("{}:{}", d.supercode, d.subcode))],
+                code_boost: LOCODE_CODE_BOOST,
+                strings: smallvec![d.name, d.subcode],
+            },
+            LocData::Gen(d) => SearchableFields {
+                strings: smallvec![d.name, d.subcode],
+                ..Default::default()
+            },
+            LocData::Airp(d) => SearchableFields {
+                strings: [d.name, d.iata].into_iter().chain(d.icao).collect(),
+                ..Default::default()
+            },
+            LocData::Custom(d) => SearchableFields {
+                strings: smallvec![d.name, d.code],
+                ..Default::default()
+            },
+            LocData::Regn(d) => SearchableFields {
+                strings: smallvec![d.name, d.code],
+                ..Default::default()
+            },
+        }
+    }
+    pub fn search(&self, t: &SearchTerm) -> Option<Score> {
+        let code_match = match self.codes.is_empty() {
+            true => None,
+            false => t.codes_match(&self.codes, SCORE_SOFT_MAX + self.code_boost),
+        };
+        match code_match {
+            Some(c) => Some(c),
+            None => self.strings.iter().map(|s| t.match_str(s)).max().flatten(),
+        }
+    }
 }
 
 impl Location {
     pub fn from_raw(r: AnyLocation) -> serde_json::Result<Self> {
+        let alt_names = alt_names_from_raw(&r.d);
+        let coordinates = coordinates_from_raw(&r.d);
+        let tags = tags_from_raw(&r.d);
+        let weight = weight_from_raw(&r.d);
         let encoding: Ustr = r.c.as_str().into();
-        let data = match encoding.as_str() {
-            STATE_ENCODING => LocData::St(State::from_raw(r.d)?),
-            SUBDIV_ENCODING => LocData::Subdv(Subdivision::from_raw(r.d)?),
-            LOCODE_ENCODING => LocData::Locd(Locode::from_raw(r.d)?),
-            IATA_ENCODING => LocData::Airp(Airport::from_raw(r.d)?),
-            other => LocData::Gen(Generic::from_raw(r.d, other)?),
+        let known = match encoding.as_str() {
+            STATE_ENCODING => State::FIELDS,
+            SUBDIV_ENCODING => Subdivision::FIELDS,
+            LOCODE_ENCODING => Locode::FIELDS,
+            IATA_ENCODING => Airport::FIELDS,
+            REGION_ENCODING => Region::FIELDS,
+            _ => Generic::FIELDS,
         };
-        let id: Ustr = normalize(r.i.as_str()).into();
-        let key = format!("{}-{}", encoding.as_str(), id.as_str());
+        let (d, extra) = split_raw(r.d, known);
+        let mut data = match encoding.as_str() {
+            STATE_ENCODING => LocData::St(State::from_raw(d)?),
+            SUBDIV_ENCODING => LocData::Subdv(Subdivision::from_raw(d)?),
+            LOCODE_ENCODING => LocData::Locd(Locode::from_raw(d)?),
+            IATA_ENCODING => LocData::Airp(Airport::from_raw(d)?),
+            REGION_ENCODING => LocData::Regn(Region::from_raw(d)?),
+            other => LocData::Gen(Generic::from_raw(d, other)?),
+        };
+        if let Some(coordinates) = coordinates {
+            data.set_coordinates(coordinates);
+        }
+        let mut loc = Self::from_data(encoding, &r.i, data).with_alt_names(alt_names);
+        loc.tags = tags;
+        loc.weight = weight;
+        loc.extra = extra;
+        Ok(loc)
+    }
+    /// Builds a location of a user-defined kind, with `kind` describing
+    /// where its fields are found in the raw data.
+    pub fn from_raw_custom(r: AnyLocation, kind: &CustomKind) -> serde_json::Result<Self> {
+        let alt_names = alt_names_from_raw(&r.d);
+        let tags = tags_from_raw(&r.d);
+        let weight = weight_from_raw(&r.d);
+        let coordinates = coordinates_from_raw(&r.d);
+        let (d, extra) = split_raw(r.d, &kind.fields());
+        let mut record = kind.record_from_raw(d)?;
+        record.coordinates = record.coordinates.or(coordinates);
+        let data = LocData::Custom(record);
+        let mut loc = Self::from_data(kind.encoding, &r.i, data).with_alt_names(alt_names);
+        loc.tags = tags;
+        loc.weight = weight;
+        loc.extra = extra;
+        Ok(loc)
+    }
+    pub fn from_data(encoding: Ustr, id: &str, data: LocData) -> Self {
+        let id: Ustr = normalize(id).into();
         let mut loc = Self {
-            key: Ustr::from(&key),
+            key: LocationKey::new(encoding, id).as_ustr(),
             id,
             encoding,
             data,
             words: Default::default(),
+            alt_names: vec![],
+            former_codes: Default::default(),
+            related_keys: Default::default(),
+            regions: Default::default(),
+            tags: Default::default(),
+            weight: 0,
+            extra: Default::default(),
+            searchable: Default::default(),
         };
-        loc.words = loc
-            .get_names()
+        loc.words = loc.derive_words(&IndexOptions::default());
+        loc
+    }
+    /// Keys the location under another prefix than its encoding.
+    pub fn with_key_namespace(mut self, namespace: Ustr) -> Self {
+        self.key = LocationKey::new(namespace, self.id).as_ustr();
+        self
+    }
+    /// Sets the alternative names, skipping any that repeat a primary name.
+    pub fn with_alt_names(mut self, alt_names: Vec<(LangTag, Ustr)>) -> Self {
+        let names = self.get_names();
+        self.alt_names = alt_names
+            .into_iter()
+            .filter(|(_, n)| !names.contains(n))
+            .collect();
+        self.words = self.derive_words(&IndexOptions::default());
+        self
+    }
+    /// Merges a row of the code list into a LOCODE. Non-empty columns take
+    /// precedence over the JSON dataset.
+    pub fn merge_code_list_row(&mut self, row: &CsvLocode) -> MergeOutcome {
+        let LocData::Locd(d) = &mut self.data else {
+            return MergeOutcome::NotLocode;
+        };
+        let before = *d;
+        let name = match row.name_wo_diacritics.trim() {
+            "" => row.name.trim(),
+            name => name,
+        };
+        if !name.is_empty() {
+            d.name = normalize(name).into();
+        }
+        if !row.function.trim().is_empty() {
+            d.function_code = normalize(row.function.trim()).into();
+        }
+        if !row.subdivision_code.trim().is_empty() {
+            d.subdivision_code = Some(normalize(row.subdivision_code.trim()).into());
+        }
+        if !row.status.trim().is_empty() {
+            d.status = Some(normalize(row.status.trim()).into());
+        }
+        d.coordinates = row.parse_coordinates().or(d.coordinates);
+        d.iata = row.parse_iata().or(d.iata);
+        d.changed = ChangeDate::from_code_list(&row.date).or(d.changed);
+        d.change = CodeListChange::parse(&row.change).or(d.change);
+        if *d == before {
+            return MergeOutcome::Unchanged;
+        }
+        if d.name != before.name {
+            self.words = self.derive_words(&IndexOptions::default());
+        }
+        MergeOutcome::Updated
+    }
+    /// The words of the names as the options filter them, other than those
+    /// shorter than their minimum word length.
+    pub(crate) fn derive_words(&self, options: &IndexOptions) -> SmallVec<[Ustr; 3]> {
+        self.get_names()
             .iter()
-            .map(|n| {
+            .chain(self.alt_names.iter().map(|(_, n)| n))
+            .map(|n| options.filters.apply_ustr(*n))
+            .flat_map(|n| {
                 let words = n
                     .split(" ")
-                    .map(|s| Ustr::from(s))
+                    .map(Ustr::from)
                     .collect::<SmallVec<[Ustr; 4]>>();
-                words.into_iter().filter_map(|w| match w.len() > 3 {
-                    true => Some(w.into()),
-                    false => None,
-                })
+                words
+                    .into_iter()
+                    .filter(|w| w.len() >= options.min_word_length)
             })
-            .flatten()
             .collect::<UstrSet>()
             .into_iter()
-            .collect();
-        Ok(loc)
+            .collect()
+    }
+    /// The transport functions of the location: those of a LOCODE's
+    /// function code, and `Airport` for an airport.
+    pub fn functions(&self) -> SmallVec<[LocodeFunction; 2]> {
+        match self.data {
+            LocData::Locd(l) => LocodeFunction::parse(&l.function_code),
+            LocData::Airp(_) => smallvec![LocodeFunction::Airport],
+            _ => smallvec![],
+        }
+    }
+    /// The month the code list last changed a LOCODE, from its "Date"
+    /// column.
+    pub fn last_changed(&self) -> Option<ChangeDate> {
+        match self.data {
+            LocData::Locd(l) => l.changed,
+            _ => None,
+        }
+    }
+    /// What the code list marks a LOCODE as, from its "Change" column.
+    pub fn code_list_change(&self) -> Option<CodeListChange> {
+        match self.data {
+            LocData::Locd(l) => l.change,
+            _ => None,
+        }
+    }
+    /// The code list status of a LOCODE, such as "aa" or "rq".
+    pub fn get_status(&self) -> Option<Ustr> {
+        match self.data {
+            LocData::Locd(l) => l.status,
+            _ => None,
+        }
     }
+    /// Scores the location against the term. The fields scored are those
+    /// cached by `LocationsDb::mk_fst`, or derived afresh for a location
+    /// that has not been indexed.
     pub fn search(&self, t: &SearchTerm) -> Option<Score> {
         if let Some(sf) = &t.state_filter {
             if self.get_state() != *sf {
                 return None;
             }
         }
+        if !t.tags_match(&self.tags) {
+            return None;
+        }
+        let mut penalty = match self
+            .get_status()
+            .and_then(|s| t.config.status_rules.get(&s))
+        {
+            Some(StatusRule::Exclude) => return None,
+            Some(StatusRule::Penalty(penalty)) => *penalty,
+            None => 0,
+        };
+        if !t.negative_terms.is_empty() {
+            let names = self.get_names();
+            let alt_names = self.alt_names.iter().map(|(_, n)| n.as_str());
+            if t.negative_match(names.iter().map(|n| n.as_str()).chain(alt_names)) {
+                penalty += t.negative_penalty?;
+            }
+        }
         let words_score = self
             .words
             .iter()
@@ -108,27 +352,165 @@ impl Location {
             })
             .max()
             .flatten();
-        let score: Option<Score> = match &self.data {
-            LocData::St(d) => {
-                let codes = d.get_codes();
-                let code_match = t.codes_match(codes.as_slice(), SCORE_SOFT_MAX + STATE_CODE_BOOST);
-                match code_match {
-                    Some(c) => Some(c.clone()),
-                    None => t.match_str(&d.name),
+        let score = match self.searchable.strings.is_empty() {
+            true => SearchableFields::from_data(&self.data).search(t),
+            false => self.searchable.search(t),
+        };
+        let boost = t.profile.map_or(0, |p| p.boost(self));
+        max(words_score, score).map(|s| Score {
+            score: s.score - penalty + boost + self.weight,
+            ..s
+        })
+    }
+    /// A human-readable description, with parent names resolved through
+    /// the database.
+    pub fn display_name(&self, db: &LocationsDb, style: DisplayStyle) -> String {
+        let name = self
+            .get_names()
+            .first()
+            .map(|n| title_case(n))
+            .unwrap_or_default();
+        let state = self.get_state().to_uppercase();
+        match style {
+            DisplayStyle::Name => name,
+            DisplayStyle::Hierarchy => {
+                let mut parts = vec![name];
+                let subdiv = self.get_parents().1.and_then(|k| db.all.get(&k));
+                if let Some(subdiv_name) = subdiv.and_then(|sd| sd.get_names().first().copied()) {
+                    parts.push(title_case(&subdiv_name));
+                }
+                if !matches!(self.data, LocData::St(_)) {
+                    parts.push(state);
                 }
+                parts.join(", ")
             }
-            LocData::Subdv(d) => {
-                let code_match = t.codes_match(&[d.subcode], SCORE_SOFT_MAX + SUBDIV_CODE_BOOST);
-                match code_match {
-                    Some(c) => Some(c),
-                    None => t.match_str(&d.name),
+            DisplayStyle::Code => {
+                let code = self.location_key().code().to_uppercase();
+                match code == state {
+                    true => format!("{name} ({state})"),
+                    false => format!("{name} ({state} {code})"),
                 }
             }
-            LocData::Locd(d) => max(t.match_str(&d.name), t.match_str(&d.subcode)),
-            LocData::Gen(d) => max(t.match_str(&d.name), t.match_str(&d.subcode)),
-            LocData::Airp(d) => max(t.match_str(&d.name), t.match_str(&d.iata)),
+        }
+    }
+    /// The location in the raw form of the datasets, as read by `from_raw`
+    /// or, given its kind, `from_raw_custom`. Coordinates are written in
+    /// full as decimal "lat" and "lon" fields; LOCODE IATA codes are left to
+    /// the code list, which takes precedence on loading.
+    pub fn to_raw(&self, kind: Option<&CustomKind>) -> serde_json::Value {
+        let mut d = match &self.data {
+            LocData::St(s) => s.to_raw(),
+            LocData::Subdv(sd) => sd.to_raw(),
+            LocData::Locd(l) => l.to_raw(),
+            LocData::Airp(a) => a.to_raw(),
+            LocData::Gen(g) => g.to_raw(),
+            LocData::Regn(r) => r.to_raw(),
+            LocData::Custom(c) => match kind {
+                Some(kind) => kind.record_to_raw(c),
+                None => CustomKind::new(&self.encoding).record_to_raw(c),
+            },
+        };
+        if let serde_json::Value::Object(fields) = &mut d {
+            fields.extend(self.extra.clone());
+            for (lang, name) in &self.alt_names {
+                fields.insert(format!("name:{lang}"), json!(name));
+            }
+            if !self.tags.is_empty() {
+                fields.insert(TAGS_FIELD.to_string(), json!(self.tags));
+            }
+            if self.weight != 0 {
+                fields.insert(WEIGHT_FIELD.to_string(), json!(self.weight));
+            }
+            // airports carry theirs as "y" and "x"
+            if let (Some(c), false) = (
+                self.get_coordinates(),
+                matches!(self.data, LocData::Airp(_)),
+            ) {
+                fields.insert("lat".to_string(), json!(c.lat));
+                fields.insert("lon".to_string(), json!(c.lon));
+            }
+        }
+        json!({ "<c>": self.encoding, "i": self.id, "d": d })
+    }
+    pub fn location_key(&self) -> LocationKey {
+        // the key is the id under its encoding or a namespace
+        match self.key.strip_suffix(self.id.as_str()) {
+            Some(prefix) if prefix.len() > 1 => {
+                LocationKey::new(prefix[..prefix.len() - 1].into(), self.id)
+            }
+            _ => LocationKey::new(self.encoding, self.id),
+        }
+    }
+    /// Caches the fields `search` scores, along with alternate names and
+    /// former codes; called for each location by `LocationsDb::mk_fst`.
+    pub fn cache_searchable(&mut self) {
+        self.searchable = SearchableFields::from_data(&self.data);
+        let alt_names = self.alt_names.iter().map(|(_, n)| *n);
+        self.searchable.strings.extend(alt_names);
+        self.searchable
+            .strings
+            .extend(self.former_codes.iter().copied());
+    }
+    /// Approximate bytes allocated by the location beyond its own size.
+    pub fn heap_size(&self) -> usize {
+        let spilled = |spilled: bool, capacity: usize| match spilled {
+            true => capacity * size_of::<Ustr>(),
+            false => 0,
         };
-        max(words_score, score)
+        let extra = match self.extra.is_empty() {
+            true => 0,
+            false => serde_json::to_string(&self.extra).map_or(0, |s| s.len()),
+        };
+        self.alt_names.capacity() * size_of::<(LangTag, Ustr)>()
+            + spilled(self.words.spilled(), self.words.capacity())
+            + spilled(self.former_codes.spilled(), self.former_codes.capacity())
+            + spilled(self.related_keys.spilled(), self.related_keys.capacity())
+            + spilled(self.regions.spilled(), self.regions.capacity())
+            + spilled(self.tags.spilled(), self.tags.capacity())
+            + spilled(
+                self.searchable.codes.spilled(),
+                self.searchable.codes.capacity(),
+            )
+            + spilled(
+                self.searchable.strings.spilled(),
+                self.searchable.strings.capacity(),
+            )
+            + extra
+    }
+    /// The words the location is found by in the word index, once its
+    /// searchable fields are cached.
+    pub fn indexed_words(&self) -> Vec<Ustr> {
+        let codes = self.get_codes();
+        let names = self.get_names();
+        self.words
+            .iter()
+            .chain(codes.iter())
+            .chain(names.iter())
+            .chain(self.alt_names.iter().map(|(_, n)| n))
+            .chain(self.former_codes.iter())
+            .chain(self.searchable.codes.iter())
+            .chain(self.searchable.strings.iter())
+            .copied()
+            .collect()
+    }
+    /// Respellings of the names that are indexed alongside them, after the
+    /// further filters of the options.
+    pub fn name_variants(&self, options: &IndexOptions) -> Vec<Ustr> {
+        let names = self.get_names();
+        let names = names
+            .iter()
+            .chain(self.alt_names.iter().map(|(_, n)| n))
+            .map(|n| options.filters.apply_ustr(*n));
+        let mut variants = vec![];
+        for name in names {
+            if options.compounds && name.contains(' ') {
+                variants.push(Ustr::from(&name.replace(' ', "")));
+            }
+            if options.punctuation {
+                variants.extend(respell_punctuation(&name).map(|v| Ustr::from(&v)));
+            }
+        }
+        variants
     }
     pub fn get_names(&self) -> SmallVec<[Ustr; 1]> {
         match &self.data {
@@ -137,6 +519,8 @@ impl Location {
             LocData::Locd(locd) => locd.get_names(),
             LocData::Gen(gn) => gn.get_names(),
             LocData::Airp(ap) => ap.get_names(),
+            LocData::Custom(cr) => smallvec![cr.name],
+            LocData::Regn(r) => smallvec![r.name],
         }
     }
     pub fn get_codes(&self) -> SmallVec<[Ustr; 1]> {
@@ -146,25 +530,38 @@ impl Location {
             LocData::Locd(lc) => lc.get_codes(),
             LocData::Gen(gn) => gn.get_codes(),
             LocData::Airp(ap) => ap.get_codes(),
+            LocData::Custom(cr) => smallvec![cr.code],
+            LocData::Regn(r) => smallvec![r.code],
+        }
+    }
+    pub fn get_coordinates(&self) -> Option<Coordinates> {
+        match self.data {
+            LocData::Locd(l) => l.coordinates,
+            LocData::Gen(g) => g.coordinates,
+            LocData::Custom(c) => c.coordinates,
+            LocData::St(s) => s.coordinates,
+            LocData::Subdv(sd) => sd.coordinates,
+            LocData::Airp(a) => Some(Coordinates { lat: a.y, lon: a.x }),
+            LocData::Regn(_) => None,
         }
     }
     pub fn get_parents(&self) -> (Option<Ustr>, Option<Ustr>) {
         match self.data {
-            LocData::St(_) => (None, None),
+            LocData::St(_) | LocData::Regn(_) => (None, None),
             LocData::Subdv(sd) => (state_key(sd.supercode), None),
             LocData::Gen(l) => (
                 state_key(l.supercode),
-                l.subdivision_code
-                    .map(|c| subdiv_key(l.supercode, c))
-                    .flatten(),
+                l.subdivision_code.and_then(|c| subdiv_key(l.supercode, c)),
             ),
             LocData::Locd(l) => (
                 state_key(l.supercode),
-                l.subdivision_code
-                    .map(|c| subdiv_key(l.supercode, c))
-                    .flatten(),
+                l.subdivision_code.and_then(|c| subdiv_key(l.supercode, c)),
             ),
             LocData::Airp(a) => (state_key(a.country), None),
+            LocData::Custom(c) => (
+                state_key(c.state),
+                c.subdivision_code.and_then(|sd| subdiv_key(c.state, sd)),
+            ),
         }
     }
     pub fn parent_boost(&self, score: i64) -> i64 {
@@ -172,8 +569,9 @@ impl Location {
             LocData::St(_) => score / 2,
             LocData::Subdv(_) => score / 3,
             LocData::Locd(_) => score / 4,
-            LocData::Gen(_) => score / 8,
-            LocData::Airp(_) => 0,
+            LocData::Gen(_) | LocData::Custom(_) => score / 8,
+            // members are not placed beneath regions in the hierarchy
+            LocData::Airp(_) | LocData::Regn(_) => 0,
         }
     }
     pub fn get_state(&self) -> Ustr {
@@ -183,38 +581,136 @@ impl Location {
             LocData::Locd(d) => d.supercode,
             LocData::Gen(d) => d.supercode,
             LocData::Airp(d) => d.country,
+            LocData::Custom(d) => d.state,
+            LocData::Regn(_) => Ustr::default(),
         }
     }
     pub fn get_subdiv(&self) -> Option<Ustr> {
         match self.data {
-            LocData::St(_st) => None,
+            LocData::St(_) | LocData::Regn(_) => None,
             LocData::Subdv(sd) => Some(sd.subcode),
             LocData::Locd(loc) => loc.subdivision_code,
             LocData::Gen(gen) => gen.subdivision_code,
+            LocData::Custom(c) => c.subdivision_code,
             LocData::Airp(a) => {
                 let sd = a
                     .region
                     .split("-")
                     .collect::<Vec<_>>()
                     .get(1)
-                    .map(|s| Ustr::from_existing(s))
-                    .flatten();
+                    .and_then(|s| Ustr::from_existing(s));
                 sd
             }
         }
     }
 }
 
-#[derive(Debug, Serialize, Clone, Copy)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DisplayStyle {
+    // "Abercarn"
+    Name,
+    // "Abercarn, Caerphilly, GB"
+    Hierarchy,
+    // "Abercarn (GB ABC)"
+    Code,
+}
+
+/// Capitalises the words of a normalized name, other than short joining
+/// words after the first, dropping any bracketed remarks such as the
+/// alternative names in "caerphilly [caerffili gb-caf]".
+fn title_case(name: &str) -> String {
+    const LOWER: [&str; 9] = ["of", "and", "the", "de", "du", "la", "le", "des", "et"];
+    let name = name.split('[').next().unwrap_or_default().trim();
+    name.split(' ')
+        .enumerate()
+        .map(|(i, word)| match i > 0 && LOWER.contains(&word) {
+            true => word.to_string(),
+            false => {
+                let mut chars = word.chars();
+                chars
+                    .next()
+                    .map(|c| c.to_uppercase().chain(chars).collect())
+                    .unwrap_or_default()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A function of a LOCODE, as marked by its function code, e.g. "1--4----"
+/// for a port with an airport.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocodeFunction {
+    Port,
+    Rail,
+    Road,
+    Airport,
+    Postal,
+    // an inland clearance depot or other multimodal terminal
+    Multimodal,
+    // a fixed transport installation, such as a pipeline terminal
+    FixedTransport,
+    BorderCrossing,
+}
+
+impl LocodeFunction {
+    /// The functions of a function code, from the digit in each position
+    /// ("0" is unknown) and "b" for a border crossing.
+    pub fn parse(function_code: &str) -> SmallVec<[Self; 2]> {
+        function_code
+            .chars()
+            .filter_map(|c| match c.to_ascii_lowercase() {
+                '1' => Some(LocodeFunction::Port),
+                '2' => Some(LocodeFunction::Rail),
+                '3' => Some(LocodeFunction::Road),
+                '4' => Some(LocodeFunction::Airport),
+                '5' => Some(LocodeFunction::Postal),
+                '6' => Some(LocodeFunction::Multimodal),
+                '7' => Some(LocodeFunction::FixedTransport),
+                'b' => Some(LocodeFunction::BorderCrossing),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 pub enum LocData {
     St(State),
     Subdv(Subdivision),
     Locd(Locode),
     Airp(Airport),
     Gen(Generic),
+    Custom(CustomRecord),
+    Regn(Region),
 }
 
 impl LocData {
+    /// Sets the coordinates of kinds that hold them apart from their other
+    /// fields; airports keep their own.
+    pub fn set_coordinates(&mut self, coordinates: Coordinates) {
+        match self {
+            LocData::St(s) => s.coordinates = Some(coordinates),
+            LocData::Subdv(sd) => sd.coordinates = Some(coordinates),
+            LocData::Locd(l) => l.coordinates = Some(coordinates),
+            LocData::Gen(g) => g.coordinates = Some(coordinates),
+            LocData::Custom(c) => c.coordinates = Some(coordinates),
+            LocData::Airp(_) | LocData::Regn(_) => {}
+        }
+    }
+    /// The name of the variant, as in the serialized data.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LocData::St(_) => "St",
+            LocData::Subdv(_) => "Subdv",
+            LocData::Locd(_) => "Locd",
+            LocData::Airp(_) => "Airp",
+            LocData::Gen(_) => "Gen",
+            LocData::Custom(_) => "Custom",
+            LocData::Regn(_) => "Regn",
+        }
+    }
     pub fn get_state(&self) -> Ustr {
         match self {
             LocData::St(s) => s.alpha2,
@@ -222,29 +718,36 @@ impl LocData {
             LocData::Locd(l) => l.supercode,
             LocData::Gen(g) => g.supercode,
             LocData::Airp(a) => a.country,
+            LocData::Custom(c) => c.state,
+            LocData::Regn(_) => Ustr::default(),
         }
     }
     pub fn get_subdiv(&self) -> Option<Ustr> {
         match self {
-            LocData::St(_) => None,
+            LocData::St(_) | LocData::Regn(_) => None,
             LocData::Subdv(sd) => Some(sd.subcode),
             LocData::Locd(l) => l.subdivision_code,
             LocData::Gen(g) => g.subdivision_code,
+            LocData::Custom(c) => c.subdivision_code,
             LocData::Airp(_) => None,
         }
     }
 }
 
-#[derive(Debug, Copy, Clone, Serialize)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct State {
     pub(crate) name: Ustr,
     short: Ustr,
     pub(crate) alpha2: Ustr,
     alpha3: Ustr,
-    continent: Ustr,
+    pub(crate) continent: Ustr,
+    #[serde(default)]
+    pub(crate) coordinates: Option<Coordinates>,
 }
 
 impl State {
+    const FIELDS: &'static [&'static str] = &["name", "short", "alpha2", "alpha3", "continent"];
+
     fn get_names(&self) -> SmallVec<[Ustr; 1]> {
         match self.short.len() > 3 {
             true if self.short != self.name => smallvec![self.name, self.short],
@@ -258,6 +761,15 @@ impl State {
         }
         codes
     }
+    fn to_raw(self) -> serde_json::Value {
+        json!({
+            "name": self.name,
+            "short": self.short,
+            "alpha2": self.alpha2,
+            "alpha3": self.alpha3,
+            "continent": self.continent,
+        })
+    }
     fn from_raw(r: serde_json::Value) -> serde_json::Result<Self> {
         let r = serde_json::from_value::<HashMap<String, String>>(r)?;
         Ok(Self {
@@ -266,6 +778,7 @@ impl State {
             alpha2: normalize(extract_field(&r, "alpha2")?).into(),
             alpha3: normalize(extract_field(&r, "alpha3")?).into(),
             continent: normalize(extract_field(&r, "continent")?).into(),
+            coordinates: None,
         })
     }
 }
@@ -276,15 +789,19 @@ pub struct SubDivKey {
     pub(crate) subcode: Ustr,
 }
 
-#[derive(Debug, Copy, Clone, Serialize)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Subdivision {
     pub(crate) name: Ustr,
     pub(crate) supercode: Ustr,
     pub(crate) subcode: Ustr,
     level: Ustr,
+    #[serde(default)]
+    pub(crate) coordinates: Option<Coordinates>,
 }
 
 impl Subdivision {
+    const FIELDS: &'static [&'static str] = &["name", "supercode", "subcode", "level"];
+
     pub fn subdiv_key(&self) -> SubDivKey {
         SubDivKey {
             state: self.supercode,
@@ -297,6 +814,14 @@ impl Subdivision {
     fn get_codes(&self) -> SmallVec<[Ustr; 1]> {
         smallvec![self.subcode]
     }
+    fn to_raw(self) -> serde_json::Value {
+        json!({
+            "name": self.name,
+            "supercode": self.supercode,
+            "subcode": self.subcode,
+            "level": self.level,
+        })
+    }
     fn from_raw(r: serde_json::Value) -> serde_json::Result<Self> {
         let r = serde_json::from_value::<HashMap<String, String>>(r)?;
         Ok(Self {
@@ -304,11 +829,12 @@ impl Subdivision {
             supercode: normalize(extract_field(&r, "supercode")?).into(),
             subcode: normalize(extract_field(&r, "subcode")?).into(),
             level: normalize(extract_field(&r, "level")?).into(),
+            coordinates: None,
         })
     }
 }
 
-#[derive(Debug, Copy, Clone, Serialize)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Generic {
     name: Ustr,
     standard: Ustr,
@@ -318,7 +844,7 @@ pub struct Generic {
     pub(crate) coordinates: Option<Coordinates>,
 }
 
-#[derive(Debug, Copy, Clone, Serialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Locode {
     name: Ustr,
     pub(crate) supercode: Ustr,
@@ -327,15 +853,41 @@ pub struct Locode {
     pub(crate) subdivision_code: Option<Ustr>,
     pub(crate) function_code: Ustr,
     pub(crate) coordinates: Option<Coordinates>,
+    // the IATA column of the code list, which is blank unless the
+    // airport's code differs from the subcode; see `airport_iata`
+    pub(crate) iata: Option<Ustr>,
+    // the code list's status of the entry, such as "rq" for a request
+    // under consideration
+    #[serde(default)]
+    pub(crate) status: Option<Ustr>,
+    // when the code list last changed the entry, and how
+    #[serde(default)]
+    pub(crate) changed: Option<ChangeDate>,
+    #[serde(default)]
+    pub(crate) change: Option<CodeListChange>,
 }
 
 impl Generic {
+    const FIELDS: &'static [&'static str] =
+        &["name", "supercode", "subcode", "subdivision_code", "c"];
+
     fn get_names(&self) -> SmallVec<[Ustr; 1]> {
         smallvec![self.name]
     }
     fn get_codes(&self) -> SmallVec<[Ustr; 1]> {
         smallvec![self.subcode]
     }
+    fn to_raw(self) -> serde_json::Value {
+        let mut d = json!({
+            "name": self.name,
+            "supercode": self.supercode,
+            "subcode": self.subcode,
+        });
+        if let Some(subdivision_code) = self.subdivision_code {
+            d["subdivision_code"] = json!(subdivision_code);
+        }
+        d
+    }
     fn from_raw(r: serde_json::Value, standard: &str) -> serde_json::Result<Self> {
         let r = serde_json::from_value::<HashMap<String, String>>(r)?;
         Ok(Self {
@@ -343,7 +895,7 @@ impl Generic {
             supercode: normalize(extract_field(&r, "supercode")?).into(),
             subcode: normalize(extract_field(&r, "subcode")?).into(),
             subdivision_code: r.get("subdivision_code").map(|sd| normalize(sd).into()),
-            standard: Ustr::from(&standard),
+            standard: Ustr::from(standard),
             coordinates: match r.get("c") {
                 Some(coords) => match coordinates::coordinate_parser(coords) {
                     Ok(coords) => Some(coords.1),
@@ -356,12 +908,71 @@ impl Generic {
 }
 
 impl Locode {
+    /// The IATA code of the airport at this location, if it has one.
+    pub fn airport_iata(&self) -> Option<Ustr> {
+        match self.iata {
+            Some(iata) => Some(iata),
+            None if self.function_code.contains('4') => Some(self.subcode),
+            None => None,
+        }
+    }
+    const FIELDS: &'static [&'static str] = &[
+        "name",
+        "supercode",
+        "subcode",
+        "subdivision_name",
+        "subdivision_code",
+        "function_code",
+        "status",
+    ];
+
     fn get_names(&self) -> SmallVec<[Ustr; 1]> {
         smallvec![self.name]
     }
     fn get_codes(&self) -> SmallVec<[Ustr; 1]> {
         smallvec![self.subcode]
     }
+    fn to_raw(self) -> serde_json::Value {
+        let mut d = json!({
+            "name": self.name,
+            "supercode": self.supercode,
+            "subcode": self.subcode,
+            "function_code": self.function_code,
+        });
+        if let Some(subdivision_name) = self.subdivision_name {
+            d["subdivision_name"] = json!(subdivision_name);
+        }
+        if let Some(subdivision_code) = self.subdivision_code {
+            d["subdivision_code"] = json!(subdivision_code);
+        }
+        if let Some(status) = self.status {
+            d["status"] = json!(status);
+        }
+        d
+    }
+    /// The row of the code list this LOCODE's coordinates and IATA code
+    /// are read from.
+    pub fn to_csv(self) -> CsvLocode {
+        CsvLocode {
+            country: self.supercode.to_uppercase(),
+            subcode: self.subcode.to_uppercase(),
+            name: self.name.to_string(),
+            name_wo_diacritics: self.name.to_string(),
+            subdivision_code: self
+                .subdivision_code
+                .map(|sd| sd.to_uppercase())
+                .unwrap_or_default(),
+            status: self.status.map(|s| s.to_uppercase()).unwrap_or_default(),
+            function: self.function_code.to_string(),
+            date: self.changed.map(|d| d.to_code_list()).unwrap_or_default(),
+            change: self
+                .change
+                .map(|c| c.mark().to_string())
+                .unwrap_or_default(),
+            iata_code: self.iata.map(|i| i.to_uppercase()).unwrap_or_default(),
+            coordinates: self.coordinates.map(|c| c.to_unlocode_string()),
+        }
+    }
     fn from_raw(r: serde_json::Value) -> serde_json::Result<Self> {
         let r = serde_json::from_value::<HashMap<String, String>>(r)?;
         Ok(Self {
@@ -374,14 +985,48 @@ impl Locode {
             subdivision_code: r.get("subdivision_code").map(|sd| normalize(sd).into()),
             function_code: normalize(extract_field(&r, "function_code")?).into(),
             coordinates: None,
+            iata: None,
+            status: r.get("status").map(|s| normalize(s).into()),
+            changed: None,
+            change: None,
         })
     }
 }
 
+/// A grouping of states or subdivisions, such as the EU or Scotland,
+/// whose members list it in `Location::regions`.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Region {
+    pub(crate) name: Ustr,
+    pub(crate) code: Ustr,
+}
+
+impl Region {
+    const FIELDS: &'static [&'static str] = &["name", "code"];
+
+    pub fn new(name: &str, code: &str) -> Self {
+        Region {
+            name: normalize(name).into(),
+            code: normalize(code).into(),
+        }
+    }
+    fn to_raw(self) -> serde_json::Value {
+        json!({ "name": self.name, "code": self.code })
+    }
+    fn from_raw(r: serde_json::Value) -> serde_json::Result<Self> {
+        let r = serde_json::from_value::<HashMap<String, String>>(r)?;
+        Ok(Self::new(
+            extract_field(&r, "name")?,
+            extract_field(&r, "code")?,
+        ))
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct AirportRaw {
     name: String,
     iata: String,
+    icao: Option<String>,
     #[serde(rename = "type")]
     airport_type: String,
     city: Option<String>,
@@ -392,10 +1037,13 @@ pub struct AirportRaw {
     elevation: Option<String>,
 }
 
-#[derive(Debug, Copy, Clone, Serialize)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Airport {
     name: Ustr,
-    iata: Ustr,
+    pub(crate) iata: Ustr,
+    // absent from snapshots written before it was read
+    #[serde(default)]
+    pub(crate) icao: Option<Ustr>,
     airport_type: Ustr,
     city: Option<Ustr>,
     pub(crate) country: Ustr,
@@ -406,11 +1054,40 @@ pub struct Airport {
 }
 
 impl Airport {
+    const FIELDS: &'static [&'static str] = &[
+        "name",
+        "iata",
+        "icao",
+        "type",
+        "city",
+        "country",
+        "region",
+        "y",
+        "x",
+        "elevation",
+    ];
+
     fn get_names(&self) -> SmallVec<[Ustr; 1]> {
         smallvec![self.name]
     }
     fn get_codes(&self) -> SmallVec<[Ustr; 1]> {
-        smallvec![self.iata]
+        let mut codes = smallvec![self.iata];
+        codes.extend(self.icao);
+        codes
+    }
+    fn to_raw(self) -> serde_json::Value {
+        json!({
+            "name": self.name,
+            "iata": self.iata,
+            "icao": self.icao,
+            "type": self.airport_type,
+            "city": self.city,
+            "country": self.country,
+            "region": self.region,
+            "y": self.y,
+            "x": self.x,
+            "elevation": self.elevation.map(|e| e.to_string()),
+        })
     }
     fn from_raw(r: serde_json::Value) -> serde_json::Result<Self> {
         let raw = serde_json::from_value::<AirportRaw>(r)?;
@@ -418,10 +1095,13 @@ impl Airport {
         let elevation = raw
             .elevation
             .as_ref()
-            .map(|e| e.parse::<i16>().expect("parse elevation"));
+            .map(|e| e.parse::<i16>())
+            .transpose()
+            .map_err(serde::de::Error::custom)?;
         Ok(Self {
             name: normalize(&raw.name).into(),
             iata: normalize(&raw.iata).into(),
+            icao: raw.icao.map(|c| normalize(&c).into()),
             city: raw.city.map(|c| normalize(&c).into()),
             airport_type,
             country: normalize(&raw.country).into(),
@@ -433,6 +1113,213 @@ impl Airport {
     }
 }
 
+/// Describes a user-defined kind of location, such as warehouses or
+/// offices, and the fields of its raw data that hold each attribute. Raw
+/// locations whose `<c>` matches `encoding` are read with it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomKind {
+    pub encoding: Ustr,
+    pub name_field: String,
+    pub code_field: String,
+    pub state_field: String,
+    pub subdivision_field: Option<String>,
+    pub coordinates_field: Option<String>,
+}
+
+impl CustomKind {
+    /// A kind using the same field names as generic locations: `name`,
+    /// `subcode`, `supercode`, `subdivision_code` and `c`.
+    pub fn new(encoding: &str) -> Self {
+        CustomKind {
+            encoding: encoding.into(),
+            name_field: "name".to_string(),
+            code_field: "subcode".to_string(),
+            state_field: "supercode".to_string(),
+            subdivision_field: Some("subdivision_code".to_string()),
+            coordinates_field: Some("c".to_string()),
+        }
+    }
+    fn fields(&self) -> Vec<&str> {
+        let optional = [&self.subdivision_field, &self.coordinates_field];
+        [&self.name_field, &self.code_field, &self.state_field]
+            .into_iter()
+            .chain(optional.into_iter().flatten())
+            .map(|f| f.as_str())
+            .collect()
+    }
+    pub fn record_to_raw(&self, record: &CustomRecord) -> serde_json::Value {
+        let mut d = json!({
+            &self.name_field: record.name,
+            &self.code_field: record.code,
+            &self.state_field: record.state,
+        });
+        if let (Some(field), Some(subdivision_code)) =
+            (&self.subdivision_field, record.subdivision_code)
+        {
+            d[field] = json!(subdivision_code);
+        }
+        d
+    }
+    pub fn record_from_raw(&self, r: serde_json::Value) -> serde_json::Result<CustomRecord> {
+        let r = serde_json::from_value::<HashMap<String, String>>(r)?;
+        let optional = |field: &Option<String>| field.as_ref().and_then(|f| r.get(f));
+        Ok(CustomRecord {
+            kind: self.encoding,
+            name: normalize(extract_field(&r, &self.name_field)?).into(),
+            code: normalize(extract_field(&r, &self.code_field)?).into(),
+            state: normalize(extract_field(&r, &self.state_field)?).into(),
+            subdivision_code: optional(&self.subdivision_field).map(|sd| normalize(sd).into()),
+            coordinates: optional(&self.coordinates_field)
+                .and_then(|c| coordinates::coordinate_parser(c).ok())
+                .map(|(_, c)| c),
+        })
+    }
+}
+
+/// A location of a user-defined kind, placed in the hierarchy beneath its
+/// state and, if given, subdivision.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct CustomRecord {
+    pub kind: Ustr,
+    pub name: Ustr,
+    pub code: Ustr,
+    pub state: Ustr,
+    pub subdivision_code: Option<Ustr>,
+    pub coordinates: Option<Coordinates>,
+}
+
+impl CustomRecord {
+    /// A record of the kind with encoding `kind`, such as a company site,
+    /// in the state of ISO 3166-1 alpha-2 code `state`.
+    pub fn new(kind: &str, name: &str, code: &str, state: &str) -> Self {
+        CustomRecord {
+            kind: kind.into(),
+            name: normalize(name).into(),
+            code: normalize(code).into(),
+            state: normalize(state).into(),
+            subdivision_code: None,
+            coordinates: None,
+        }
+    }
+    /// Places the record beneath a subdivision of its state, by the
+    /// subdivision's code without the country, as "cay" for "GB-CAY".
+    pub fn with_subdivision(mut self, subdivision_code: &str) -> Self {
+        self.subdivision_code = Some(normalize(subdivision_code).into());
+        self
+    }
+    pub fn with_coordinates(mut self, coordinates: Coordinates) -> Self {
+        self.coordinates = Some(coordinates);
+        self
+    }
+}
+
+/// Collects alternative names from raw fields named `name:<lang>`, as in
+/// OpenStreetMap, or `official_<lang>`, as in the ISO-3166-1 dataset.
+fn alt_names_from_raw(d: &serde_json::Value) -> Vec<(LangTag, Ustr)> {
+    let Some(fields) = d.as_object() else {
+        return vec![];
+    };
+    let mut alt_names = fields
+        .iter()
+        .filter_map(|(k, v)| {
+            let lang = k
+                .strip_prefix("name:")
+                .or_else(|| k.strip_prefix("official_"))?;
+            let name = v.as_str().filter(|n| !n.is_empty())?;
+            Some((
+                Ustr::from(&lang.to_lowercase()),
+                Ustr::from(&normalize(name)),
+            ))
+        })
+        .collect::<Vec<_>>();
+    // one entry per name, under the first language giving it
+    alt_names.sort_unstable_by_key(|(l, n)| (n.as_str(), l.as_str()));
+    alt_names.dedup_by_key(|(_, n)| *n);
+    alt_names.sort_unstable_by_key(|(l, n)| (l.as_str(), n.as_str()));
+    alt_names
+}
+
+fn is_alt_name_field(field: &str) -> bool {
+    field.starts_with("name:") || field.starts_with("official_")
+}
+
+const COORDINATE_FIELDS: &[&str] = &["c", "lat", "lon"];
+
+// the raw field holding tags, as a list or a comma-separated string
+const TAGS_FIELD: &str = "tags";
+
+fn tags_from_raw(d: &serde_json::Value) -> SmallVec<[Ustr; 2]> {
+    let tags: Vec<&str> = match d.get(TAGS_FIELD) {
+        Some(serde_json::Value::Array(tags)) => tags.iter().filter_map(|t| t.as_str()).collect(),
+        Some(serde_json::Value::String(tags)) => tags.split(',').collect(),
+        _ => vec![],
+    };
+    let mut tags = tags
+        .into_iter()
+        .map(|t| normalize(t.trim()))
+        .filter(|t| !t.is_empty())
+        .map(|t| Ustr::from(&t))
+        .collect::<SmallVec<[Ustr; 2]>>();
+    tags.sort_unstable_by_key(|t| t.as_str());
+    tags.dedup();
+    tags
+}
+
+// the raw field holding the weight, as a number or a string
+const WEIGHT_FIELD: &str = "weight";
+
+fn weight_from_raw(d: &serde_json::Value) -> i64 {
+    match d.get(WEIGHT_FIELD) {
+        Some(serde_json::Value::Number(n)) => n.as_i64().unwrap_or_default(),
+        Some(serde_json::Value::String(s)) => s.trim().parse().unwrap_or_default(),
+        _ => 0,
+    }
+}
+
+/// Coordinates given in a raw location, either in UN/LOCODE notation as `c`
+/// or in decimal degrees as `lat` and `lon`.
+fn coordinates_from_raw(d: &serde_json::Value) -> Option<Coordinates> {
+    if let Some(c) = d.get("c").and_then(|c| c.as_str()) {
+        return coordinates::coordinate_parser(c).ok().map(|(_, c)| c);
+    }
+    let degrees = |field| match d.get(field)? {
+        serde_json::Value::Number(n) => n.as_f64(),
+        serde_json::Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    };
+    Some(Coordinates {
+        lat: degrees("lat")?,
+        lon: degrees("lon")?,
+    })
+}
+
+/// Separates the fields a parser reads from any others, which are returned
+/// as extra metadata. Alternative name and coordinate fields belong to
+/// neither.
+fn split_raw(
+    d: serde_json::Value,
+    known: &[&str],
+) -> (
+    serde_json::Value,
+    serde_json::Map<String, serde_json::Value>,
+) {
+    let serde_json::Value::Object(fields) = d else {
+        return (d, Default::default());
+    };
+    let (known, other): (serde_json::Map<_, _>, serde_json::Map<_, _>) = fields
+        .into_iter()
+        .partition(|(k, _)| known.contains(&k.as_str()));
+    let extra = other
+        .into_iter()
+        .filter(|(k, _)| {
+            !is_alt_name_field(k)
+                && !COORDINATE_FIELDS.contains(&k.as_str())
+                && ![TAGS_FIELD, WEIGHT_FIELD].contains(&k.as_str())
+        })
+        .collect();
+    (serde_json::Value::Object(known), extra)
+}
+
 fn extract_field<'a>(hm: &'a HashMap<String, String>, field: &str) -> serde_json::Result<&'a str> {
     let val = hm.get(field);
     match val {
@@ -444,8 +1331,83 @@ fn extract_field<'a>(hm: &'a HashMap<String, String>, field: &str) -> serde_json
     }
 }
 
-#[derive(Deserialize, Debug)]
+/// What merging a row of the code list did to the location it names.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MergeOutcome {
+    Updated,
+    Unchanged,
+    // no location has the row's key
+    NotFound,
+    // the key belongs to a location of another kind
+    NotLocode,
+}
+
+/// The month of a change to the code list, e.g. 2007-01, written "0701" in
+/// its "Date" column.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ChangeDate {
+    pub year: u16,
+    pub month: u8,
+}
+
+impl ChangeDate {
+    pub fn new(year: u16, month: u8) -> Option<Self> {
+        (1..=12)
+            .contains(&month)
+            .then_some(ChangeDate { year, month })
+    }
+    /// Reads the "YYMM" of the code list, which began in the 1980s, so
+    /// that years from 70 are taken as the 1900s.
+    pub fn from_code_list(date: &str) -> Option<Self> {
+        let date = date.trim();
+        if date.len() != 4 || !date.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let (year, month) = (date[..2].parse::<u16>().ok()?, date[2..].parse().ok()?);
+        let century = if year >= 70 { 1900 } else { 2000 };
+        ChangeDate::new(century + year, month)
+    }
+    pub fn to_code_list(&self) -> String {
+        format!("{:02}{:02}", self.year % 100, self.month)
+    }
+}
+
+/// The change marked in the "Change" column of the code list.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CodeListChange {
+    Added,
+    // changed in name ("#")
+    Renamed,
+    // changed otherwise ("|")
+    Changed,
+    // marked for removal
+    Removed,
+}
+
+impl CodeListChange {
+    pub fn parse(mark: &str) -> Option<Self> {
+        match mark.trim() {
+            "+" => Some(CodeListChange::Added),
+            "#" => Some(CodeListChange::Renamed),
+            "|" => Some(CodeListChange::Changed),
+            "X" | "x" => Some(CodeListChange::Removed),
+            _ => None,
+        }
+    }
+    pub fn mark(&self) -> &'static str {
+        match self {
+            CodeListChange::Added => "+",
+            CodeListChange::Renamed => "#",
+            CodeListChange::Changed => "|",
+            CodeListChange::Removed => "X",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct CsvLocode {
+    #[serde(rename = "Change", default)]
+    pub change: String,
     #[serde(rename = "Country")]
     pub country: String,
     #[serde(rename = "Location")]
@@ -469,28 +1431,38 @@ pub struct CsvLocode {
 }
 
 impl CsvLocode {
+    /// The key of the LOCODE under the standard encoding; see `key_in`.
     pub fn key(&self) -> Ustr {
-        let k = format!("{}:{}", normalize(&self.country), normalize(&self.subcode));
-        let key = format!("{}-{}", LOCODE_ENCODING, k);
-        key.into()
+        self.key_in(LOCODE_ENCODING.into())
+    }
+    /// The key of the LOCODE in a database keying LOCODEs under
+    /// `namespace`, as given by `LocationsDb::key_namespace`.
+    pub fn key_in(&self, namespace: Ustr) -> Ustr {
+        let id = format!("{}:{}", normalize(&self.country), normalize(&self.subcode));
+        LocationKey::new(namespace, id.into()).as_ustr()
     }
     pub fn subdiv_key(&self) -> Ustr {
-        let k = format!(
+        let id = format!(
             "{}:{}",
             normalize(&self.country),
             normalize(&self.subdivision_code)
         );
-        let key = format!("{}-{}", SUBDIV_ENCODING, k);
-        key.into()
+        LocationKey::new(SUBDIV_ENCODING.into(), id.into()).as_ustr()
     }
     pub fn country_key(&self) -> Ustr {
-        let k = format!("{}-{}", STATE_ENCODING, normalize(&self.country));
-        k.into()
+        let id = normalize(&self.country);
+        LocationKey::new(STATE_ENCODING.into(), id.into()).as_ustr()
+    }
+    pub fn parse_iata(&self) -> Option<Ustr> {
+        match self.iata_code.trim() {
+            "" => None,
+            iata => Some(normalize(iata).into()),
+        }
     }
     pub fn parse_coordinates(&self) -> Option<Coordinates> {
         self.coordinates
             .as_ref()
-            .map(|c| match coordinates::coordinate_parser(c) {
+            .and_then(|c| match coordinates::coordinate_parser(c) {
                 Ok((_, coord)) => Some(coord),
                 Err(e) => {
                     error!(
@@ -500,6 +1472,36 @@ impl CsvLocode {
                     None
                 }
             })
-            .flatten()
     }
 }
+
+/// A row of a weights table, setting the weight of a location by key.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CsvWeight {
+    pub key: String,
+    pub weight: i64,
+}
+
+/// A row of a redirects table, pointing a retired key at its successor.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CsvRedirect {
+    pub from: String,
+    pub to: String,
+}
+
+impl CsvRedirect {
+    /// The code of the retired location, e.g. `xyz` for `UN-LOCODE-gb:xyz`.
+    pub fn former_code(&self) -> Ustr {
+        former_code(&self.from)
+    }
+}
+
+/// The code a retired key is kept under among its successor's
+/// `former_codes`.
+pub fn former_code(key: &str) -> Ustr {
+    let code = match LocationKey::parse(key) {
+        Ok(key) => normalize(key.code()),
+        Err(_) => normalize(key),
+    };
+    code.into()
+}
@@ -1,28 +1,54 @@
+use std::borrow::Cow;
 use std::boxed::Box;
-use std::cmp::min;
+use std::cmp::{min, Reverse};
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
+#[cfg(feature = "fs")]
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{Read, Write};
+use std::mem::size_of;
+#[cfg(feature = "fs")]
 use std::path::PathBuf;
-use std::sync::RwLock;
-use std::time::Instant;
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread::JoinHandle;
+use std::time::Duration;
 
 use csv::ReaderBuilder;
-use fst::{Automaton, Streamer};
+use fst::{Automaton, IntoStreamer, Streamer};
 use indextree::{Arena, NodeId};
+#[cfg(feature = "parallel")]
 use rayon::iter::{
     IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelBridge,
     ParallelIterator,
 };
-use serde_json::Value;
-use tracing::{debug, info};
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::{debug, debug_span, field, info, info_span};
 use ustr::{Ustr, UstrMap, UstrSet};
 
+use crate::config::{DedupBy, DedupPolicy, IndexOptions, LoadOptions};
+use crate::coordinates::{BoundingBox, Coordinates};
 use crate::graph::ResultsGraph;
-use crate::location::{AnyLocation, CsvLocode, LocData, Location};
-use crate::search::{Score, SearchTerm};
-use crate::LEV_2_LENGTH_MAX;
-use crate::LEV_3_LENGTH_MAX;
+use crate::key::{AsKey, InvalidKey, LocationKey};
+use crate::location::{
+    former_code, state_key, subdiv_key, AnyLocation, CsvLocode, CsvRedirect, CsvWeight, CustomKind,
+    CustomRecord, LocData, Location, MergeOutcome, IATA_ENCODING, LOCODE_ENCODING, STATE_ENCODING,
+    SUBDIV_ENCODING,
+};
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::normalize;
+use crate::regions::add_regions;
+use crate::search::{rank_order, Offset, Score, SearchTerm, STOP_WORDS};
+use crate::segment::{Compaction, FstSegment, SharedAutomaton};
+use crate::slow_query::{SlowQuery, SlowQueryLog};
+use crate::stats::SearchStats;
+use crate::time::Instant;
+use crate::AIRPORT_CODE_BOOST;
+use crate::AMBIGUITY_MARGIN;
 use crate::SEARCH_INCLUSION_THRESHOLD;
 
 #[derive(Default)]
@@ -34,21 +60,318 @@ pub struct LocationsDb {
     // key is in format "gb:lon", value is name
     pub subdiv_by_code: UstrMap<Ustr>,
     pub by_word_map: UstrMap<UstrSet>,
-    pub by_word_vec: Vec<(Ustr, UstrSet)>,
-    pub fst: fst::Map<Vec<u8>>,
+    pub segments: Vec<FstSegment>,
     pub arena: Arena<Ustr>,
+    // user-defined location kinds, by encoding
+    pub custom_kinds: UstrMap<CustomKind>,
+    // successors of retired keys
+    pub redirects: UstrMap<Ustr>,
+    pub index_options: IndexOptions,
+    // removed locations that FST segments may still list, until compacted
+    pub tombstones: UstrSet,
+    // keys inserted more than once, of which the last was kept
+    pub duplicate_keys: UstrSet,
+    // key prefixes used in place of the encoding, by encoding
+    pub key_namespaces: UstrMap<Ustr>,
+    // the locations listing each region in their `regions`
+    pub members_by_region: UstrMap<UstrSet>,
+    // counters of the searches run, if attached
+    pub stats: Option<Arc<SearchStats>>,
+    // where searches slower than a threshold are reported, if one is set
+    pub slow_query_log: SlowQueryLog,
+}
+
+/// A location found by key, noting the retired key that was asked for if
+/// it had been redirected.
+#[derive(Serialize)]
+pub struct Resolution<'a> {
+    #[serde(flatten)]
+    pub location: &'a Location,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirected_from: Option<Ustr>,
+}
+
+/// Search results grouped by primary name, best group first.
+#[derive(Serialize)]
+pub struct GroupedResults {
+    pub groups: Vec<NameGroup>,
+    // several places share the name of the best match, and match as well
+    pub ambiguous: bool,
+}
+
+#[derive(Serialize)]
+pub struct NameGroup {
+    pub name: Ustr,
+    pub results: Vec<GroupedResult>,
+}
+
+#[derive(Serialize)]
+pub struct GroupedResult {
+    pub key: Ustr,
+    pub score: Score,
+    // state and subdivision keys, which tell apart places of the same name
+    pub parents: Vec<Ustr>,
+}
+
+/// A location found in a query, as a span of bytes of the raw query, as
+/// returned by `LocationsDb::annotate`.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct Annotation {
+    pub start: usize,
+    pub end: usize,
+    #[schemars(with = "String")]
+    #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+    pub key: Ustr,
+    pub score: i64,
+    pub kind: String,
+}
+
+/// A search result with the location's details inlined, as returned by
+/// `LocationsDb::search_detailed`.
+#[derive(Clone, Serialize, JsonSchema)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct DetailedResult {
+    #[schemars(with = "String")]
+    #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+    pub key: Ustr,
+    pub score: Score,
+    pub kind: String,
+    #[schemars(with = "Vec<String>")]
+    #[cfg_attr(feature = "utoipa", schema(value_type = Vec<String>))]
+    pub names: Vec<Ustr>,
+    #[schemars(with = "Vec<String>")]
+    #[cfg_attr(feature = "utoipa", schema(value_type = Vec<String>))]
+    pub codes: Vec<Ustr>,
+    pub coordinates: Option<Coordinates>,
+    // the state, then the subdivision, where the location has them
+    pub parents: Vec<ParentSummary>,
+    // the keys of the regions, such as the EU, the location lies within
+    #[schemars(with = "Vec<String>")]
+    #[cfg_attr(feature = "utoipa", schema(value_type = Vec<String>))]
+    pub regions: Vec<Ustr>,
+    // the keys of the same place under other encodings, such as the
+    // airport of a LOCODE
+    #[schemars(with = "Vec<String>")]
+    #[cfg_attr(feature = "utoipa", schema(value_type = Vec<String>))]
+    pub related_keys: Vec<Ustr>,
+    // the retired key the query named, if the location was found by it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    #[cfg_attr(feature = "utoipa", schema(value_type = Option<String>))]
+    pub redirected_from: Option<Ustr>,
+}
+
+#[derive(Clone, Serialize, JsonSchema)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ParentSummary {
+    #[schemars(with = "String")]
+    #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+    pub key: Ustr,
+    #[schemars(with = "Option<String>")]
+    #[cfg_attr(feature = "utoipa", schema(value_type = Option<String>))]
+    pub name: Option<Ustr>,
+}
+
+/// Counts describing a database, for dashboards and for checking data
+/// updates.
+#[derive(Debug, Serialize)]
+pub struct DbStats {
+    // locations by `LocData` variant
+    pub by_kind: BTreeMap<&'static str, usize>,
+    // locations by state code
+    pub by_country: BTreeMap<Ustr, usize>,
+    pub indexed_words: usize,
+    pub fst_bytes: usize,
+    // the most levels in the hierarchy below a root
+    pub arena_depth: usize,
+}
+
+/// Referential problems in a database, each list sorted by key.
+#[derive(Debug, Default, Serialize)]
+pub struct ValidationReport {
+    // subdivisions whose state is missing
+    pub orphan_subdivisions: Vec<Ustr>,
+    // locations and the missing subdivisions they refer to
+    pub unknown_subdivisions: Vec<(Ustr, Ustr)>,
+    pub duplicate_keys: Vec<Ustr>,
+    // locations of kinds that carry coordinates, without them
+    pub missing_coordinates: Vec<Ustr>,
+}
+
+impl ValidationReport {
+    /// Whether there are any problems besides missing coordinates, which
+    /// are common in the source data.
+    pub fn has_errors(&self) -> bool {
+        !(self.orphan_subdivisions.is_empty()
+            && self.unknown_subdivisions.is_empty()
+            && self.duplicate_keys.is_empty())
+    }
+}
+
+/// What `LocationsDb::warm_up` went through, and how long it took.
+#[derive(Debug, Serialize)]
+pub struct WarmUp {
+    pub fst_keys: usize,
+    pub queries: usize,
+    pub elapsed: Duration,
+}
+
+/// Estimated bytes held by each part of a database. Estimates count
+/// collection capacities but not allocator overhead.
+#[derive(Debug, Serialize)]
+pub struct MemoryReport {
+    pub locations: usize,
+    // the word map, including the location set of each word
+    pub word_sets: usize,
+    // FST segments and the location sets they point to
+    pub fst: usize,
+    pub arena: usize,
+    // all interned strings, which are shared by every database in the
+    // process
+    pub interned_strings: usize,
+}
+
+impl MemoryReport {
+    /// The total, without the interned strings.
+    pub fn total(&self) -> usize {
+        self.locations + self.word_sets + self.fst + self.arena
+    }
 }
 
 impl LocationsDb {
     pub fn retrieve(&self, matchable: &str) -> Option<Location> {
-        match Ustr::from_existing(matchable) {
-            Some(u) => match matchable.len() {
-                0 | 1 => None,
-                _ => self.all.get(&u).cloned(),
-            },
+        match matchable.len() {
+            0 | 1 => None,
+            _ => self.resolve(matchable).map(|r| r.location.clone()),
+        }
+    }
+    pub fn get<K: AsKey + ?Sized>(&self, key: &K) -> Option<&Location> {
+        self.all.get(&key.existing_key()?)
+    }
+    /// Looks up a location by key, following redirects from retired keys.
+    pub fn resolve<K: AsKey + ?Sized>(&self, key: &K) -> Option<Resolution<'_>> {
+        let mut key = key.existing_key()?;
+        let mut redirected_from = None;
+        // bounded, in case the redirects table has a cycle
+        for _ in 0..=self.redirects.len() {
+            if let Some(location) = self.all.get(&key) {
+                return Some(Resolution {
+                    location,
+                    redirected_from,
+                });
+            }
+            redirected_from = redirected_from.or(Some(key));
+            key = *self.redirects.get(&key)?;
+        }
+        None
+    }
+    /// The retired key a search result was found by, if the query matched
+    /// one of the location's former codes rather than the location itself.
+    pub fn redirected_from(&self, st: &SearchTerm, key: Ustr, score: &Score) -> Option<Ustr> {
+        let st = self.prepare(st);
+        let loc = self.all.get(&key)?;
+        let matched = st.normalized.get(score.offset.start..score.offset.end)?;
+        let code = loc.former_codes.iter().find(|c| c.as_str() == matched)?;
+        if loc.get_codes().contains(code) {
+            return None;
+        }
+        self.redirects
+            .iter()
+            .filter(|(from, to)| **to == key && self.former_code(from) == *code)
+            .map(|(from, _)| *from)
+            .min_by_key(|from| from.as_str())
+    }
+    /// Looks up a location by a bare code, without searching: a country
+    /// ("GB"), a subdivision ("GB-CAY"), a LOCODE ("GBABC" or "GB ABC") or an
+    /// IATA code ("BFS"). Anything else, including codes not in the
+    /// database, gives `None`, for the caller to fall back to `search`.
+    pub fn resolve_code(&self, code: &str) -> Option<Resolution<'_>> {
+        let code = code.trim().to_ascii_lowercase();
+        let alpha = |s: &str| s.bytes().all(|b| b.is_ascii_alphabetic());
+        let alnum = |s: &str| s.bytes().all(|b| b.is_ascii_alphanumeric());
+        let by_key = |encoding: &str, id: &str| {
+            let prefix = self.key_namespace(encoding);
+            self.resolve(&LocationKey::new(prefix, Ustr::from_existing(id)?))
+        };
+        // by key when there is no such code, for retired codes to redirect
+        let subdiv =
+            |id: &str| match Ustr::from_existing(id).and_then(|c| self.subdiv_by_code.get(&c)) {
+                Some(key) => self.resolve(key),
+                None => by_key(SUBDIV_ENCODING, id),
+            };
+        match code.split_once(['-', ':', ' ']) {
+            // ISO 3166-2 codes are written with a dash, LOCODEs without
+            Some((state, sub)) if state.len() == 2 && alpha(state) && alnum(sub) => {
+                let id = format!("{state}:{sub}");
+                match code.as_bytes()[2] {
+                    b'-' => subdiv(&id).or_else(|| by_key(LOCODE_ENCODING, &id)),
+                    _ => by_key(LOCODE_ENCODING, &id).or_else(|| subdiv(&id)),
+                }
+            }
+            Some(_) => None,
+            None if code.len() == 2 && alpha(&code) => {
+                match Ustr::from_existing(&code).and_then(|c| self.state_by_code.get(&c)) {
+                    Some(key) => self.resolve(key),
+                    None => by_key(STATE_ENCODING, &code),
+                }
+            }
+            None if code.len() == 3 && alpha(&code) => by_key(IATA_ENCODING, &code),
+            None if code.len() == 5 && alpha(&code[..2]) && alnum(&code) => {
+                by_key(LOCODE_ENCODING, &format!("{}:{}", &code[..2], &code[2..]))
+            }
             None => None,
         }
     }
+    /// An empty database, set up to load data with the given options.
+    pub fn with_options(options: &LoadOptions) -> Self {
+        let mut db = LocationsDb::default();
+        for kind in &options.custom_kinds {
+            db.register_kind(kind.clone());
+        }
+        for (encoding, namespace) in &options.key_namespaces {
+            db.set_key_namespace(encoding, namespace);
+        }
+        db
+    }
+    /// Keys locations of an encoding parsed by `parse_data_block` under
+    /// another prefix, so that user datasets do not collide. Parents are
+    /// found by the standard state and subdivision keys, so those two
+    /// encodings should keep their own.
+    pub fn set_key_namespace(&mut self, encoding: &str, namespace: &str) {
+        self.key_namespaces
+            .insert(encoding.into(), namespace.into());
+    }
+    /// The prefix locations of `encoding` are keyed under: its namespace,
+    /// if one is set, or the encoding itself.
+    pub fn key_namespace(&self, encoding: &str) -> Ustr {
+        let encoding = Ustr::from(encoding);
+        *self.key_namespaces.get(&encoding).unwrap_or(&encoding)
+    }
+    /// Parses a key by the prefixes this database knows: the standard
+    /// encodings, its key namespaces and its custom kinds.
+    pub fn parse_key(&self, key: &str) -> Result<LocationKey, InvalidKey> {
+        let prefixes = self
+            .key_namespaces
+            .values()
+            .chain(self.custom_kinds.keys())
+            .map(|p| p.as_str());
+        LocationKey::parse_with(key, prefixes)
+    }
+    /// The code a retired key is kept under among its successor's
+    /// `former_codes`, as `former_code` gives it for keys under the
+    /// prefixes of this database.
+    pub fn former_code(&self, key: &str) -> Ustr {
+        match self.parse_key(key) {
+            Ok(key) => normalize(key.code()).into(),
+            Err(_) => former_code(key),
+        }
+    }
+    /// Registers a user-defined location kind, so that raw locations with
+    /// its encoding are parsed by `parse_data_block` as `LocData::Custom`.
+    pub fn register_kind(&mut self, kind: CustomKind) {
+        self.custom_kinds.insert(kind.encoding, kind);
+    }
     pub fn insert(&mut self, l: Location) {
         match &l.data {
             LocData::St(s) => {
@@ -60,79 +383,595 @@ impl LocationsDb {
             LocData::Locd(_) => {}
             LocData::Airp(_) => {}
             LocData::Gen(_) => {}
+            LocData::Custom(_) => {}
+            LocData::Regn(_) => {}
+        }
+        for region in &l.regions {
+            self.members_by_region
+                .entry(*region)
+                .or_default()
+                .insert(l.key);
         }
         let node_id = self.arena.new_node(l.key);
         self.indices.insert(l.key, node_id);
-        self.all.insert(l.key, l);
+        self.tombstones.remove(&l.key);
+        if let Some(previous) = self.all.insert(l.key, l) {
+            self.duplicate_keys.insert(previous.key);
+        }
     }
     pub fn mk_fst(mut self) -> Self {
+        let mut keys = self.all.keys().copied().collect::<Vec<_>>();
+        keys.sort_unstable_by_key(|k| k.as_str());
+        // Rebuild the hierarchy in key order, so that it does not depend
+        // on the (possibly parallel) order of insertion.
+        self.arena = Arena::new();
+        self.indices = UstrMap::default();
+        for key in &keys {
+            self.indices.insert(*key, self.arena.new_node(*key));
+        }
+        let words_map = self.index_locations(&keys);
+        self.segments = vec![FstSegment::from_words(&words_map)];
+        self
+    }
+    /// Attaches the given (already inserted) locations to the hierarchy and
+    /// the global word map, returning the word map for just these keys.
+    fn index_locations(&mut self, keys: &[Ustr]) -> UstrMap<UstrSet> {
         let mut words_map: UstrMap<UstrSet> = UstrMap::default();
-        let arena = &mut self.arena;
-        self.all.iter().for_each(|(key, loc)| {
-            let node_id: &NodeId = self.indices.get(key).unwrap();
-            match loc.get_parents() {
-                (_, Some(subdiv)) => self.indices.get(&subdiv).unwrap().append(*node_id, arena),
-                (Some(st), None) => self.indices.get(&st).unwrap().append(*node_id, arena),
-                (None, None) => (),
+        for key in keys {
+            let loc = self.all.get_mut(key).expect("location in db");
+            let filters = &self.index_options.filters;
+            loc.words = loc.derive_words(&self.index_options);
+            loc.cache_searchable();
+            if !filters.is_empty() {
+                for s in loc.searchable.strings.iter_mut() {
+                    *s = filters.apply_ustr(*s);
+                }
+            }
+            let variants = loc.name_variants(&self.index_options);
+            loc.searchable.strings.extend(variants);
+            let node_id = self.indices[key];
+            let parent = match loc.get_parents() {
+                (_, Some(subdiv)) => self.indices.get(&subdiv),
+                (Some(st), None) => self.indices.get(&st),
+                (None, None) => None,
             };
-
-            let codes = loc.get_codes();
-            let names = loc.get_names();
-            let words_iter = loc.words.iter().chain(codes.iter()).chain(names.iter());
-            words_iter.for_each(|w| {
-                let old = match words_map.get_mut(w) {
-                    None => {
-                        let new = UstrSet::default();
-                        words_map.insert(*w, new);
-                        words_map.get_mut(w).unwrap()
+            if let Some(parent) = parent {
+                parent.append(node_id, &mut self.arena);
+            }
+            for word in loc.indexed_words() {
+                words_map.entry(word).or_default().insert(*key);
+                self.by_word_map.entry(word).or_default().insert(*key);
+            }
+        }
+        words_map
+    }
+    /// Removes a location from a running database, detaching it from the
+    /// hierarchy and dropping it from the word map. Its FST entries are
+    /// tombstoned and ignored until the next compaction.
+    pub fn remove<K: AsKey + ?Sized>(&mut self, key: &K) -> Option<Location> {
+        let key = key.existing_key()?;
+        let loc = self.all.remove(&key)?;
+        if let Some(node_id) = self.indices.remove(&key) {
+            node_id.remove(&mut self.arena);
+        }
+        let by_code = match &loc.data {
+            LocData::St(s) => Some((&mut self.state_by_code, s.alpha2)),
+            LocData::Subdv(_) => Some((&mut self.subdiv_by_code, loc.id)),
+            _ => None,
+        };
+        // unless a later location with the same code replaced it
+        if let Some((by_code, code)) = by_code {
+            if by_code.get(&code) == Some(&key) {
+                by_code.remove(&code);
+            }
+        }
+        for word in loc.indexed_words() {
+            if let Some(locs) = self.by_word_map.get_mut(&word) {
+                locs.remove(&key);
+                if locs.is_empty() {
+                    self.by_word_map.remove(&word);
+                }
+            }
+        }
+        for related in &loc.related_keys {
+            if let Some(other) = self.all.get_mut(related) {
+                other.related_keys.retain(|k| *k != key);
+            }
+        }
+        for region in &loc.regions {
+            if let Some(members) = self.members_by_region.get_mut(region) {
+                members.remove(&key);
+            }
+        }
+        self.tombstones.insert(key);
+        Some(loc)
+    }
+    /// Inserts locations into an indexed database without rebuilding it,
+    /// by adding a new FST segment holding only their words. Call
+    /// `compact` periodically to merge segments back together.
+    pub fn add_locations<I>(&mut self, locations: I)
+    where
+        I: IntoIterator<Item = Location>,
+    {
+        let keys = locations
+            .into_iter()
+            .map(|l| {
+                let key = l.key;
+                self.insert(l);
+                key
+            })
+            .collect::<Vec<_>>();
+        let words_map = self.index_locations(&keys);
+        self.segments.push(FstSegment::from_words(&words_map));
+    }
+    /// Attaches a tag to a location, returning false if there is no such
+    /// location. Tags are normalized like names.
+    pub fn tag<K: AsKey + ?Sized>(&mut self, key: &K, tag: &str) -> bool {
+        let Some(loc) = key.existing_key().and_then(|k| self.all.get_mut(&k)) else {
+            return false;
+        };
+        let tag = Ustr::from(&normalize(tag));
+        if !loc.tags.contains(&tag) {
+            loc.tags.push(tag);
+        }
+        true
+    }
+    /// Adds application-specific locations, such as company sites, to a
+    /// running database, registering their kinds if need be. Each is
+    /// placed beneath its state and, if given, subdivision, which must
+    /// already be loaded. Returns the new keys.
+    pub fn add_custom<I>(&mut self, records: I) -> Result<Vec<Ustr>, Box<dyn Error>>
+    where
+        I: IntoIterator<Item = CustomRecord>,
+    {
+        let locations = records
+            .into_iter()
+            .map(|record| {
+                let known = |key: Option<Ustr>| key.is_some_and(|k| self.all.contains_key(&k));
+                if !known(state_key(record.state)) {
+                    return Err(format!(
+                        "unknown state for {}: {}",
+                        record.code, record.state
+                    ));
+                }
+                if let Some(subdiv) = record.subdivision_code {
+                    if !known(subdiv_key(record.state, subdiv)) {
+                        return Err(format!(
+                            "unknown subdivision for {}: {}:{subdiv}",
+                            record.code, record.state
+                        ));
                     }
-                    Some(set) => set,
-                };
-                old.insert(*key);
+                }
+                let loc = Location::from_data(record.kind, &record.code, LocData::Custom(record));
+                Ok(match self.key_namespaces.get(&record.kind) {
+                    Some(namespace) => loc.with_key_namespace(*namespace),
+                    None => loc,
+                })
             })
-        });
-        let mut words_vec = words_map
-            .iter()
-            .map(|(k, v)| (*k, v.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+        for loc in &locations {
+            if !self.custom_kinds.contains_key(&loc.encoding) {
+                self.register_kind(CustomKind::new(&loc.encoding));
+            }
+        }
+        let keys = locations.iter().map(|l| l.key).collect();
+        self.add_locations(locations);
+        Ok(keys)
+    }
+    /// Reverse geocodes a point: the `limit` nearest locations with
+    /// coordinates, nearest first, with their distances in kilometres.
+    pub fn nearest(&self, point: Coordinates, limit: usize) -> Vec<(Ustr, f64)> {
+        let mut distances = self
+            .all
+            .values()
+            .filter_map(|loc| Some((loc.key, loc.get_coordinates()?.distance_km(point))))
             .collect::<Vec<_>>();
-        words_vec.sort_unstable_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
-        let fst = fst::Map::from_iter(
-            words_vec
-                .iter()
-                .enumerate()
-                .map(|(i, (word, _))| (word.as_str(), i as u64)),
-        )
-        .expect("Build FST");
-        LocationsDb {
-            all: self.all,
-            arena: self.arena,
-            indices: self.indices,
-            state_by_code: self.state_by_code,
-            subdiv_by_code: self.subdiv_by_code,
-            by_word_map: words_map,
-            by_word_vec: words_vec,
+        distances.sort_unstable_by(|a, b| a.1.total_cmp(&b.1).then(a.0.as_str().cmp(b.0.as_str())));
+        distances.truncate(limit);
+        distances
+    }
+    /// Builds a single segment covering every current segment. This only
+    /// needs read access, so it can run in the background while searches
+    /// continue, before being applied with `install_compaction`.
+    pub fn prepare_compaction(&self) -> Compaction {
+        Compaction {
+            segment: FstSegment::from_words(&self.by_word_map),
+            replaced: self.segments.len(),
+            tombstones: self.tombstones.clone(),
+        }
+    }
+    pub fn install_compaction(&mut self, compaction: Compaction) {
+        let replaced = min(compaction.replaced, self.segments.len());
+        self.segments.splice(0..replaced, [compaction.segment]);
+        self.tombstones
+            .retain(|key| !compaction.tombstones.contains(key));
+    }
+    pub fn compact(&mut self) {
+        let compaction = self.prepare_compaction();
+        self.install_compaction(compaction);
+    }
+    /// The bounding box of a location and everything beneath it, such as
+    /// a state or subdivision, from the coordinates they have.
+    pub fn bbox<K: AsKey + ?Sized>(&self, key: &K) -> Option<BoundingBox> {
+        let node_id = self.indices.get(&key.existing_key()?)?;
+        let points = node_id
+            .descendants(&self.arena)
+            .filter_map(|id| self.all.get(self.arena[id].get()))
+            .filter_map(|loc| loc.get_coordinates());
+        BoundingBox::from_points(points)
+    }
+    pub fn stats(&self) -> DbStats {
+        let mut by_kind = BTreeMap::new();
+        let mut by_country = BTreeMap::new();
+        for loc in self.all.values() {
+            *by_kind.entry(loc.data.kind()).or_default() += 1;
+            *by_country.entry(loc.get_state()).or_default() += 1;
+        }
+        let arena_depth = self
+            .indices
+            .values()
+            .map(|node_id| node_id.ancestors(&self.arena).count() - 1)
+            .max()
+            .unwrap_or_default();
+        DbStats {
+            by_kind,
+            by_country,
+            indexed_words: self.by_word_map.len(),
+            fst_bytes: self.segments.iter().map(|s| s.fst.as_fst().size()).sum(),
+            arena_depth,
+        }
+    }
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport {
+            duplicate_keys: self.duplicate_keys.iter().copied().collect(),
+            ..Default::default()
+        };
+        let known = |key: &str| Ustr::from_existing(key).is_some_and(|k| self.all.contains_key(&k));
+        for loc in self.all.values() {
+            let state = loc.get_state();
+            match (&loc.data, loc.get_subdiv()) {
+                (LocData::St(_), _) => {}
+                (LocData::Subdv(_), _) => {
+                    if !known(&format!("{STATE_ENCODING}-{state}")) {
+                        report.orphan_subdivisions.push(loc.key)
+                    }
+                }
+                (_, Some(subdiv)) => {
+                    let subdiv = format!("{SUBDIV_ENCODING}-{state}:{subdiv}");
+                    if !known(&subdiv) {
+                        report.unknown_subdivisions.push((loc.key, subdiv.into()))
+                    }
+                }
+                (_, None) => {}
+            }
+            let carries_coordinates = matches!(
+                loc.data,
+                LocData::Locd(_) | LocData::Gen(_) | LocData::Custom(_)
+            );
+            if carries_coordinates && loc.get_coordinates().is_none() {
+                report.missing_coordinates.push(loc.key);
+            }
+        }
+        report.orphan_subdivisions.sort_unstable();
+        report.unknown_subdivisions.sort_unstable();
+        report.duplicate_keys.sort_unstable();
+        report.missing_coordinates.sort_unstable();
+        report
+    }
+    /// Readies a freshly loaded database for its first queries: reads every
+    /// FST segment through, paging it in, then searches each sample query,
+    /// which also starts the thread pool and builds the automata the
+    /// queries need.
+    pub fn warm_up<Q: AsRef<str>>(&self, sample_queries: impl IntoIterator<Item = Q>) -> WarmUp {
+        let start = Instant::now();
+        let mut fst_keys = 0;
+        for segment in &self.segments {
+            let mut stream = segment.fst.stream();
+            while stream.next().is_some() {
+                fst_keys += 1;
+            }
+        }
+        let mut queries = 0;
+        for query in sample_queries {
+            let st = SearchTerm::from_raw_query(query.as_ref().to_string(), None, 10, 2);
+            self.search(&st);
+            queries += 1;
+        }
+        let elapsed = start.elapsed();
+        info!("warmed up on {queries} queries in: {elapsed:.2?}");
+        WarmUp {
+            fst_keys,
+            queries,
+            elapsed,
+        }
+    }
+    /// Estimates the memory used by the database, for capacity planning.
+    pub fn memory_report(&self) -> MemoryReport {
+        let set_bytes = |set: &UstrSet| set.capacity() * size_of::<Ustr>();
+        let entry = size_of::<Ustr>() + size_of::<UstrSet>();
+        let locations = self.all.capacity() * (size_of::<Ustr>() + size_of::<Location>())
+            + self.all.values().map(|l| l.heap_size()).sum::<usize>()
+            + self.indices.capacity() * (size_of::<Ustr>() + size_of::<NodeId>());
+        let word_sets = self.by_word_map.capacity() * entry
+            + self.by_word_map.values().map(set_bytes).sum::<usize>();
+        let fst = self
+            .segments
+            .iter()
+            .map(|s| {
+                s.fst.as_fst().size()
+                    + s.words.capacity() * entry
+                    + s.words.iter().map(|(_, set)| set_bytes(set)).sum::<usize>()
+            })
+            .sum();
+        MemoryReport {
+            locations,
+            word_sets,
             fst,
+            arena: self.arena.capacity() * size_of::<indextree::Node<Ustr>>(),
+            interned_strings: ustr::total_allocated(),
+        }
+    }
+    /// Words in the index within `max_dist` edits of `term`, with the
+    /// number of locations carrying each, closest and most common first.
+    /// Useful for did-you-mean prompts and for correcting bulk input.
+    pub fn suggest_words(&self, term: &str, max_dist: u32) -> Vec<(Ustr, usize)> {
+        let term = self
+            .index_options
+            .filters
+            .apply(&crate::normalize(term), None);
+        let Ok(autom) = fst::automaton::Levenshtein::new(&term, max_dist) else {
+            return vec![];
+        };
+        let mut suggestions: UstrMap<usize> = UstrMap::default();
+        for segment in &self.segments {
+            let mut stream = segment.fst.search(&autom).into_stream();
+            while let Some((word, _)) = stream.next() {
+                let word = Ustr::from(&String::from_utf8_lossy(word));
+                let frequency = self.by_word_map.get(&word).map_or(0, |locs| locs.len());
+                suggestions.insert(word, frequency);
+            }
+        }
+        let mut suggestions = suggestions.into_iter().collect::<Vec<_>>();
+        suggestions.sort_unstable_by_key(|(word, frequency)| {
+            (
+                strsim::levenshtein(&term, word),
+                Reverse(*frequency),
+                word.as_str(),
+            )
+        });
+        suggestions
+    }
+    /// Writes every location as an Elasticsearch/OpenSearch `_bulk` index
+    /// action followed by its document, ordered by key. Coordinates are a
+    /// `geo_point`-compatible `location` object.
+    pub fn export_bulk_ndjson<W: Write>(&self, mut writer: W) -> Result<(), Box<dyn Error>> {
+        let mut locations = self.all.values().collect::<Vec<_>>();
+        locations.sort_unstable_by_key(|l| l.key.as_str());
+        for loc in locations {
+            let (state, subdiv) = loc.get_parents();
+            let parents = [state, subdiv].into_iter().flatten().collect::<Vec<_>>();
+            let action = json!({ "index": { "_id": loc.key } });
+            let doc = json!({
+                "key": loc.key,
+                "encoding": loc.encoding,
+                "id": loc.id,
+                "names": loc.get_names(),
+                "codes": loc.get_codes(),
+                "state": loc.get_state(),
+                "subdiv": loc.get_subdiv(),
+                "parents": parents,
+                "location": loc.get_coordinates().map(|c| json!({ "lat": c.lat, "lon": c.lon })),
+            });
+            serde_json::to_writer(&mut writer, &action)?;
+            writer.write_all(b"\n")?;
+            serde_json::to_writer(&mut writer, &doc)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+    /// The query normalized with the further filters of the index options,
+    /// as names are indexed. The searches prepare their queries themselves;
+    /// prepare a query before passing it to `pre_filter`, `score_candidates`
+    /// or `rank` directly.
+    pub fn prepare<'a>(&self, st: &'a SearchTerm) -> Cow<'a, SearchTerm> {
+        match st.filters == self.index_options.filters {
+            true => Cow::Borrowed(st),
+            false => Cow::Owned(st.with_filters(&self.index_options.filters)),
+        }
+    }
+    /// Searches, first within a country named in the query if the config
+    /// detects one. Stats, metrics and slow queries are recorded once for
+    /// the whole search.
+    pub fn search(&self, st: &SearchTerm) -> Vec<(Ustr, Score)> {
+        let st = &*self.prepare(st);
+        let _span = info_span!("search", terms = st.term_count(), limit = st.limit).entered();
+        let start = Instant::now();
+        let mut query = SlowQuery {
+            term: st,
+            candidates: 0,
+            scored: 0,
+            pre_filter: Duration::ZERO,
+            score: Duration::ZERO,
+            rank: Duration::ZERO,
+            total: Duration::ZERO,
+        };
+        let results = match self.detect_state(st) {
+            Some(narrowed) => {
+                let results = self.run_search(&narrowed, &mut query);
+                match results.iter().any(|(key, _)| !self.is_state(*key)) {
+                    true => results,
+                    false => self.run_search(st, &mut query),
+                }
+            }
+            None => self.run_search(st, &mut query),
+        };
+        query.total = start.elapsed();
+        if let Some(stats) = &self.stats {
+            stats.record_search(query.candidates);
+        }
+        #[cfg(feature = "metrics")]
+        metrics::record_search(&query);
+        self.slow_query_log.report(&query);
+        results
+    }
+    // Runs one pass of a search, adding its stage timings to `query` and
+    // noting its counts.
+    fn run_search(&self, st: &SearchTerm, query: &mut SlowQuery) -> Vec<(Ustr, Score)> {
+        let start = Instant::now();
+        let pre_filtered = self.pre_filter(st);
+        let pre_filter_time = start.elapsed();
+        let scores = self.score_candidates(st, &pre_filtered);
+        let score_time = start.elapsed();
+        (query.candidates, query.scored) = (pre_filtered.len(), scores.len());
+        let results = self.rank(st, scores);
+        query.pre_filter += pre_filter_time;
+        query.score += score_time - pre_filter_time;
+        query.rank += start.elapsed() - score_time;
+        results
+    }
+    /// Searches, inlining the details of each result and its parents.
+    pub fn search_detailed(&self, st: &SearchTerm) -> Vec<DetailedResult> {
+        self.search(st)
+            .into_iter()
+            .map(|(key, score)| {
+                let loc = &self.all[&key];
+                let (state, subdiv) = loc.get_parents();
+                let parents = [state, subdiv]
+                    .into_iter()
+                    .flatten()
+                    .map(|key| ParentSummary {
+                        key,
+                        name: self
+                            .all
+                            .get(&key)
+                            .and_then(|p| p.get_names().first().copied()),
+                    })
+                    .collect();
+                DetailedResult {
+                    key,
+                    score,
+                    kind: loc.data.kind().to_string(),
+                    names: loc.get_names().into_vec(),
+                    codes: loc.get_codes().into_vec(),
+                    coordinates: loc.get_coordinates(),
+                    parents,
+                    regions: self.regions_of(key),
+                    related_keys: loc.related_keys.to_vec(),
+                    redirected_from: self.redirected_from(st, key, &score),
+                }
+            })
+            .collect()
+    }
+    /// Searches airports alone, ranking those whose IATA or ICAO code is
+    /// in the query above those matched only by name.
+    pub fn search_airports(&self, st: &SearchTerm) -> Vec<(Ustr, Score)> {
+        let st = &*self.prepare(st);
+        let mut candidates = self.pre_filter(st);
+        candidates.retain(|key| {
+            self.all
+                .get(key)
+                .is_some_and(|l| matches!(l.data, LocData::Airp(_)))
+        });
+        let mut scores = self.score_candidates(st, &candidates);
+        for (key, score) in scores.iter_mut() {
+            let codes = self.all[key].get_codes();
+            if let Some(code) = st.matches.exact_terms().find(|m| codes.contains(&m.term)) {
+                score.score += AIRPORT_CODE_BOOST;
+                score.offset = code.offset;
+            }
         }
+        self.rank(st, scores)
     }
-    pub fn search<'c>(&'c self, st: &'c SearchTerm) -> Vec<(Ustr, Score)> {
-        let fst = &self.fst;
-        let search_action = |op: fst::map::OpBuilder<'c>, term: &'c str| match term.len() > 3 {
+    /// Annotates the raw query with the locations found in it, best first
+    /// where they overlap, as non-overlapping spans sorted by offset.
+    pub fn annotate(&self, st: &SearchTerm) -> Vec<Annotation> {
+        let st = &*self.prepare(st);
+        let mut annotations: Vec<Annotation> = vec![];
+        for (key, score) in self.search(st) {
+            let Offset { start, end } = st.raw_offset(score.offset);
+            if start == end || annotations.iter().any(|a| start < a.end && a.start < end) {
+                continue;
+            }
+            annotations.push(Annotation {
+                start,
+                end,
+                key,
+                score: score.score,
+                kind: self.all[&key].data.kind().to_string(),
+            });
+        }
+        annotations.sort_by_key(|a| a.start);
+        annotations
+    }
+    /// Searches, then groups the results by primary name so that places of
+    /// the same name can be told apart by their parents.
+    pub fn search_grouped(&self, st: &SearchTerm) -> GroupedResults {
+        let results = self.search(st);
+        let best = results.first().map(|(_, s)| s.score).unwrap_or_default();
+        let mut groups: Vec<NameGroup> = vec![];
+        for (key, score) in results {
+            let loc = &self.all[&key];
+            let name = loc.get_names().first().copied().unwrap_or(key);
+            let (state, subdiv) = loc.get_parents();
+            let result = GroupedResult {
+                key,
+                score,
+                parents: [state, subdiv].into_iter().flatten().collect(),
+            };
+            match groups.iter_mut().find(|g| g.name == name) {
+                Some(group) => group.results.push(result),
+                None => groups.push(NameGroup {
+                    name,
+                    results: vec![result],
+                }),
+            }
+        }
+        let ambiguous = groups.first().is_some_and(|g| {
+            let close = g
+                .results
+                .iter()
+                .filter(|r| best - r.score.score <= AMBIGUITY_MARGIN)
+                .map(|r| &r.parents)
+                .collect::<Vec<_>>();
+            close.iter().any(|p| *p != close[0])
+        });
+        GroupedResults { groups, ambiguous }
+    }
+    /// Resolves the search term to candidate locations, by direct lookup
+    /// where the term is known and by FST search otherwise.
+    pub fn pre_filter<'c>(&'c self, st: &'c SearchTerm) -> UstrSet {
+        let span = debug_span!("pre_filter", candidates = field::Empty).entered();
+        let segments = &self.segments;
+        let search_action = |op: fst::map::OpBuilder<'c>, term: &'c str| match term.len()
+            > st.config.max_code_length
+        {
             true => {
                 let prefix_matcher = fst::automaton::Str::new(term).starts_with();
-                let lev_dist = match term.chars().count() {
-                    count if count < LEV_3_LENGTH_MAX => st.lev_dist,
-                    count if count < LEV_2_LENGTH_MAX => min(st.lev_dist, 2),
-                    _ => min(st.lev_dist, 1),
-                };
+                let lev_dist = st
+                    .config
+                    .lev_breakpoints
+                    .distance(term.chars().count(), st.lev_dist);
                 let autom = fst::automaton::Levenshtein::new(term, lev_dist)
                     .expect("build automaton")
                     .union(prefix_matcher);
-                op.add(fst.search(autom))
+                let autom = SharedAutomaton(Rc::new(autom));
+                segments
+                    .iter()
+                    .fold(op, |op, segment| op.add(segment.fst.search(autom.clone())))
             }
             false => op,
         };
 
-        let grab_action = |term: &Ustr| self.by_word_map.get(term);
+        let grab_action = |term: &Ustr| {
+            let locs = self.by_word_map.get(term);
+            if let Some(stats) = &self.stats {
+                stats.record_word_lookup(locs.is_some());
+            }
+            #[cfg(feature = "metrics")]
+            metrics::record_word_lookup(locs.is_some());
+            locs
+        };
 
         // Grab is for strings we believe we know, searches for those
         // we do not. This allows fast resolution, without searching,
@@ -142,33 +981,268 @@ impl LocationsDb {
 
         // Finalize and consume the search, extending the prefiltered
         // locations that we wish to apply to.
-        let mut stream = builder.union();
-        while let Some((_, v)) = stream.next() {
-            let (_, locs) = self.by_word_vec.get(v[0].value as usize).unwrap();
-            pre_filtered.extend(locs);
-        }
-
-        // Search then properly qualifies and quantifies the preliminary
-        // matching above.
-        let res = pre_filtered
-            .par_iter()
-            .filter_map(|key| {
-                let loc = self.all.get(key).unwrap();
-                loc.search(st)
-                    .map(|score| match score.score > SEARCH_INCLUSION_THRESHOLD {
-                        true => Some((*key, score)),
-                        false => None,
-                    })
-            })
-            .flatten()
+        // Each term adds one stream per segment, in segment order.
+        let union_span = debug_span!("fst_union", segments = segments.len(), words = 0);
+        let mut words = 0;
+        union_span.in_scope(|| {
+            let mut stream = builder.union();
+            while let Some((_, v)) = stream.next() {
+                words += 1;
+                for iv in v {
+                    let segment = &segments[iv.index % segments.len()];
+                    let locs = segment.locations(iv.value).expect("word in segment");
+                    pre_filtered.extend(locs.iter().filter(|k| !self.tombstones.contains(k)));
+                }
+            }
+        });
+        union_span.record("words", words);
+        span.record("candidates", pre_filtered.len());
+        pre_filtered
+    }
+    /// Search then properly qualifies and quantifies the preliminary
+    /// matching of `pre_filter`.
+    pub fn score_candidates(&self, st: &SearchTerm, candidates: &UstrSet) -> UstrMap<Score> {
+        let span = debug_span!(
+            "scoring",
+            candidates = candidates.len(),
+            scored = field::Empty
+        );
+        let _entered = span.enter();
+        #[cfg(feature = "parallel")]
+        let candidates = candidates.par_iter();
+        #[cfg(not(feature = "parallel"))]
+        let candidates = candidates.iter();
+        let scores = candidates
+            .filter_map(|key| self.score_candidate(st, *key, SEARCH_INCLUSION_THRESHOLD))
             .collect::<UstrMap<_>>();
-
-        let res_graph = ResultsGraph::from_results(res, &self);
-        let mut res = res_graph.scores.into_iter().collect::<Vec<_>>();
-        res.sort_unstable_by(|a, b| b.1.cmp(&a.1));
-        res.truncate(st.limit);
+        span.record("scored", scores.len());
+        scores
+    }
+    // the candidate and its country-boosted score, if it passes the
+    // filters and scores above `min_score` before the boost
+    fn score_candidate(&self, st: &SearchTerm, key: Ustr, min_score: i64) -> Option<(Ustr, Score)> {
+        let loc = self.all.get(&key)?;
+        if st.config.region.is_some_and(|r| !self.in_region(key, r)) {
+            return None;
+        }
+        if let Some(boolean) = &st.boolean {
+            if !boolean.admits(|term| self.mentions(key, term)) {
+                return None;
+            }
+        }
+        let score = loc.search(st).filter(|s| s.score > min_score)?;
+        Some((key, st.country_boosted(loc, score)))
+    }
+    /// Every candidate for the query scoring above `min_score`, by default
+    /// the threshold `search` applies, with neither the limit nor the
+    /// ranking, for exports of large result sets. Scores are the
+    /// candidates' own, without boosts from related results. Candidates
+    /// are found up front but scored only as the iterator is advanced, in
+    /// order of key, so that a slow consumer holds back the work rather
+    /// than results piling up.
+    pub fn search_all<'a>(
+        &'a self,
+        st: &'a SearchTerm,
+        min_score: Option<i64>,
+    ) -> impl Iterator<Item = (Ustr, Score)> + 'a {
+        let st = self.prepare(st);
+        let mut candidates = self.pre_filter(&st).into_iter().collect::<Vec<_>>();
+        candidates.sort_unstable_by_key(|key| key.as_str());
+        let min_score = min_score.unwrap_or(SEARCH_INCLUSION_THRESHOLD);
+        candidates.into_iter().filter_map(move |key| {
+            self.score_candidate(&st, key, min_score)
+                .filter(|(key, _)| st.profile_admits(&self.all[key]))
+        })
+    }
+    /// Whether a location, its state or subdivision, or a region it lies
+    /// within has a name or code of which `term` is the whole or whole
+    /// words, as "west sussex" of Bognor Regis.
+    pub fn mentions(&self, key: Ustr, term: &str) -> bool {
+        let Some(loc) = self.all.get(&key) else {
+            return false;
+        };
+        let (state, subdiv) = loc.get_parents();
+        let term = format!(" {term} ");
+        [Some(key), state, subdiv]
+            .into_iter()
+            .flatten()
+            .chain(self.regions_of(key))
+            .filter_map(|k| self.all.get(&k))
+            .flat_map(|l| {
+                let alt_names = l.alt_names.iter().map(|(_, name)| *name);
+                l.get_names()
+                    .into_iter()
+                    .chain(l.get_codes())
+                    .chain(alt_names)
+            })
+            .any(|name| format!(" {name} ").contains(&term))
+    }
+    /// The query filtered to the one country it names, if the config asks
+    /// for it and there is such a country. Countries are named by their
+    /// names or codes as whole words, leaving other words that are not stop
+    /// words, and a name shared by a place other than a country, as
+    /// "georgia" is, does not count.
+    pub fn detect_state(&self, st: &SearchTerm) -> Option<SearchTerm> {
+        if !st.config.detect_state || st.state_filter.is_some() {
+            return None;
+        }
+        let words = split_words(&st.normalized);
+        let mut found = None;
+        for key in self.state_by_code.values() {
+            let Some(state) = self.all.get(key) else {
+                continue;
+            };
+            let alt_names = state.alt_names.iter().map(|(_, n)| *n);
+            let names = state.get_names().into_iter().chain(alt_names);
+            let codes = state.get_codes().into_iter();
+            let named = names
+                .filter(|name| !self.names_a_place(*name))
+                .chain(codes.filter(|code| !STOP_WORDS.contains(&code.as_str())))
+                .any(|name| {
+                    let name = split_words(&name);
+                    !name.is_empty()
+                        && words.windows(name.len()).enumerate().any(|(i, w)| {
+                            w == name.as_slice()
+                                && words[..i]
+                                    .iter()
+                                    .chain(&words[i + name.len()..])
+                                    .any(|w| !STOP_WORDS.contains(w))
+                        })
+                });
+            match (named, found) {
+                (false, _) => {}
+                (true, None) => found = Some(state.get_state()),
+                (true, Some(_)) => return None,
+            }
+        }
+        let mut narrowed = st.clone();
+        narrowed.state_filter = Some(found?);
+        Some(narrowed)
+    }
+    // whether a location other than a country is named `name`
+    fn names_a_place(&self, name: Ustr) -> bool {
+        self.by_word_map.get(&name).is_some_and(|keys| {
+            keys.iter().filter_map(|k| self.all.get(k)).any(|l| {
+                !matches!(l.data, LocData::St(_))
+                    && (l.get_names().contains(&name)
+                        || l.alt_names.iter().any(|(_, n)| *n == name))
+            })
+        })
+    }
+    fn is_state(&self, key: Ustr) -> bool {
+        self.all
+            .get(&key)
+            .is_some_and(|l| matches!(l.data, LocData::St(_)))
+    }
+    /// Links scored candidates hierarchically, unless the config skips it
+    /// or none are related, then orders and truncates them.
+    pub fn rank(&self, st: &SearchTerm, scores: UstrMap<Score>) -> Vec<(Ustr, Score)> {
+        let mut res = self.sorted(st, self.link(st, scores).scores);
+        if let Some(policy) = &st.config.dedup {
+            res = self.dedup(policy, res);
+        }
+        if st.config.expand_regions {
+            res = self.expand_regions(res);
+        }
+        if res.len() > st.max_results() {
+            if let Some(stats) = &self.stats {
+                stats.record_truncated();
+            }
+            res.truncate(st.max_results());
+        }
         res
     }
+    pub(crate) fn link(&self, st: &SearchTerm, scores: UstrMap<Score>) -> ResultsGraph {
+        match &st.config.propagation {
+            _ if st.config.skip_graph || !ResultsGraph::has_relations(&scores, self) => {
+                ResultsGraph::unlinked(scores)
+            }
+            Some(p) => ResultsGraph::propagated(scores, self, p),
+            None => ResultsGraph::from_results(scores, self),
+        }
+    }
+    /// Drops results the profile rules out or short of the required
+    /// coverage, and orders the rest.
+    pub(crate) fn sorted(&self, st: &SearchTerm, scores: UstrMap<Score>) -> Vec<(Ustr, Score)> {
+        let mut res = scores.into_iter().collect::<Vec<_>>();
+        if st.profile.is_some() {
+            res.retain(|(key, _)| st.profile_admits(&self.all[key]));
+        }
+        if let Some(min_coverage) = st.config.min_coverage {
+            res.retain(|(_, score)| st.coverage(score.offset) >= min_coverage);
+        }
+        res.sort_unstable_by_key(rank_order);
+        res
+    }
+    /// Collapses duplicates in ranked results to the preferred location of
+    /// each group, keeping the group's position.
+    fn dedup(&self, policy: &DedupPolicy, ranked: Vec<(Ustr, Score)>) -> Vec<(Ustr, Score)> {
+        self.dedup_groups(policy, &ranked)
+            .into_iter()
+            .map(|(i, key)| (key, ranked[i].1))
+            .collect()
+    }
+    /// The groups of duplicates in ranked results, in order, as the index
+    /// of the group's first result and the key preferred for the group.
+    pub(crate) fn dedup_groups(
+        &self,
+        policy: &DedupPolicy,
+        ranked: &[(Ustr, Score)],
+    ) -> Vec<(usize, Ustr)> {
+        #[derive(Hash, PartialEq, Eq)]
+        enum Group {
+            Name(Ustr, Ustr, Option<Ustr>),
+            Key(Ustr),
+        }
+        let mut deduped: Vec<(usize, Ustr)> = vec![];
+        let mut groups: HashMap<Group, usize> = HashMap::new();
+        for (n, &(key, _)) in ranked.iter().enumerate() {
+            let loc = &self.all[&key];
+            let mut members = vec![];
+            for by in &policy.by {
+                match by {
+                    DedupBy::NameAndSubdivision => members.extend(
+                        loc.get_names()
+                            .first()
+                            .map(|n| Group::Name(*n, loc.get_state(), loc.get_subdiv())),
+                    ),
+                    DedupBy::CrossReference => {
+                        members.push(Group::Key(key));
+                        members.extend(loc.related_keys.iter().map(|k| Group::Key(*k)));
+                    }
+                }
+            }
+            let index = match members.iter().find_map(|g| groups.get(g)) {
+                Some(&i) => {
+                    let current = &self.all[&deduped[i].1];
+                    if policy.preference(loc.encoding) < policy.preference(current.encoding) {
+                        deduped[i].1 = key;
+                    }
+                    i
+                }
+                None => {
+                    deduped.push((n, key));
+                    deduped.len() - 1
+                }
+            };
+            members.into_iter().for_each(|g| {
+                groups.entry(g).or_insert(index);
+            });
+        }
+        deduped
+    }
+}
+
+/// Compacts a shared database on a background thread, holding the write
+/// lock only to swap the merged segment in.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn compact_in_background(db: Arc<RwLock<LocationsDb>>) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let compaction = db.read().expect("cannot aquire lock").prepare_compaction();
+        db.write()
+            .expect("cannot aquire lock")
+            .install_compaction(compaction);
+    })
 }
 
 pub fn parse_data_list<I>(mut db: LocationsDb, iter: I) -> Result<LocationsDb, Box<dyn Error>>
@@ -176,34 +1250,158 @@ where
     I: Iterator,
     I::Item: Into<CsvLocode>,
 {
-    let mut nerrs = 1;
-    for csv_loc in iter {
+    let outcomes = merge_data_list(&mut db, iter);
+    let unmerged = outcomes
+        .iter()
+        .filter(|(_, o)| matches!(o, MergeOutcome::NotFound | MergeOutcome::NotLocode));
+    for (n, (key, outcome)) in unmerged.enumerate() {
+        debug!("#{} code list row not merged: {} {:?}", n + 1, key, outcome);
+    }
+    Ok(db)
+}
+
+/// Merges the rows of a code list into the LOCODEs they name, in place,
+/// returning the key of each row with what merging it did.
+pub fn merge_data_list<I>(db: &mut LocationsDb, iter: I) -> Vec<(Ustr, MergeOutcome)>
+where
+    I: Iterator,
+    I::Item: Into<CsvLocode>,
+{
+    iter.map(|csv_loc| {
         let csv_loc: CsvLocode = csv_loc.into();
-        let key = &csv_loc.key();
-        match db.all.get_mut(key) {
-            None => {
-                debug!("#{} LOCODE not found in db: {} {:?}", nerrs, key, csv_loc);
-                nerrs += 1;
-            }
-            Some(loc) => {
-                let coord = csv_loc.parse_coordinates();
-                match loc.data {
-                    LocData::Locd(mut d) => d.coordinates = coord,
-                    _ => {
-                        return Err("should not happen".into());
-                    }
-                }
+        let key = csv_loc.key_in(db.key_namespace(LOCODE_ENCODING));
+        let outcome = match db.all.get_mut(&key) {
+            Some(loc) => loc.merge_code_list_row(&csv_loc),
+            None => MergeOutcome::NotFound,
+        };
+        (key, outcome)
+    })
+    .collect()
+}
+
+/// Links each airport to the LOCODE of the same place, recording each
+/// in the other's `related_keys`. A LOCODE names an airport when its
+/// function includes `4`, by its subcode unless the code list gives a
+/// different IATA code.
+pub fn link_related(mut db: LocationsDb) -> LocationsDb {
+    let airports = db
+        .all
+        .values()
+        .filter_map(|loc| match loc.data {
+            LocData::Airp(a) => Some(((a.country, a.iata), loc.key)),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+    let mut links = db
+        .all
+        .values()
+        .filter_map(|loc| match loc.data {
+            LocData::Locd(l) => l
+                .airport_iata()
+                .and_then(|iata| airports.get(&(l.supercode, iata)))
+                .map(|airport| (loc.key, *airport)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    links.sort_unstable_by_key(|(locode, airport)| (locode.as_str(), airport.as_str()));
+    for (locode, airport) in links {
+        for (from, to) in [(locode, airport), (airport, locode)] {
+            let loc = db.all.get_mut(&from).expect("location in db");
+            if !loc.related_keys.contains(&to) {
+                loc.related_keys.push(to);
+            }
+        }
+    }
+    db
+}
+
+/// Adds the regions and tags of the options to a loaded database, before
+/// `mk_fst`.
+pub fn apply_load_options(mut db: LocationsDb, options: &LoadOptions) -> LocationsDb {
+    db = add_regions(db, &options.regions);
+    for (key, tag) in &options.tags {
+        db.tag(key, tag);
+    }
+    if let Some(cutoff) = options.code_list_cutoff {
+        let changed = db
+            .all
+            .values()
+            .filter(|l| l.last_changed().is_some_and(|date| date > cutoff))
+            .map(|l| l.key)
+            .collect::<Vec<_>>();
+        for key in changed {
+            db.remove(&key);
+        }
+    }
+    db
+}
+
+/// Sets the weights of locations from a weights table, overriding those
+/// of the datasets. Rows for unknown keys are skipped.
+pub fn parse_weights<I>(mut db: LocationsDb, iter: I) -> LocationsDb
+where
+    I: Iterator,
+    I::Item: Into<CsvWeight>,
+{
+    for row in iter {
+        let row: CsvWeight = row.into();
+        if let Some(loc) = Ustr::from_existing(&row.key).and_then(|k| db.all.get_mut(&k)) {
+            loc.weight = row.weight;
+        }
+    }
+    db
+}
+
+/// Records retired keys from a redirects table, so that they resolve to
+/// their successors, removing any location still held under a retired key.
+/// Run before `mk_fst`, so that the retired codes are indexed against the
+/// successors.
+pub fn parse_redirects<I>(mut db: LocationsDb, iter: I) -> Result<LocationsDb, Box<dyn Error>>
+where
+    I: Iterator,
+    I::Item: Into<CsvRedirect>,
+{
+    for redirect in iter {
+        let redirect: CsvRedirect = redirect.into();
+        let from = Ustr::from(&redirect.from);
+        let to = Ustr::from(&redirect.to);
+        db.remove(&from);
+        let code = db.former_code(&redirect.from);
+        if let Some(successor) = db.all.get_mut(&to) {
+            if !successor.former_codes.contains(&code) {
+                successor.former_codes.push(code);
             }
         }
+        db.redirects.insert(from, to);
     }
     Ok(db)
 }
 
+/// Reads a redirects table in CSV from `reader` into the database, as
+/// `parse_redirects` does.
+pub fn parse_redirects_reader<R: Read>(
+    db: LocationsDb,
+    reader: R,
+) -> Result<LocationsDb, Box<dyn Error>> {
+    let mut csv_reader = ReaderBuilder::new().from_reader(reader);
+    let redirects = csv_reader
+        .deserialize::<CsvRedirect>()
+        .collect::<Result<Vec<_>, _>>()?;
+    parse_redirects(db, redirects.into_iter())
+}
+
 pub fn parse_data_block(
     db: &RwLock<LocationsDb>,
     obj: serde_json::Map<std::string::String, serde_json::Value>,
 ) -> Result<&RwLock<LocationsDb>, Box<dyn Error>> {
+    let (kinds, namespaces) = {
+        let db = db.read().expect("cannot aquire lock");
+        (db.custom_kinds.clone(), db.key_namespaces.clone())
+    };
+    #[cfg(feature = "parallel")]
     let iter = obj.into_iter().par_bridge();
+    #[cfg(not(feature = "parallel"))]
+    let iter = obj.into_iter();
     let errors: Vec<String> = iter
         .map(|(id, val)| {
             let raw_any = match serde_json::from_value::<AnyLocation>(val) {
@@ -212,9 +1410,15 @@ pub fn parse_data_block(
                     return Err(format!("\t{id} Cannot decode location code: {:?}", err));
                 }
             };
-            let loc = Location::from_raw(raw_any);
+            let loc = match Ustr::from_existing(&raw_any.c).and_then(|c| kinds.get(&c)) {
+                Some(kind) => Location::from_raw_custom(raw_any, kind),
+                None => Location::from_raw(raw_any),
+            };
             match loc {
-                Ok(loc) => Ok(loc),
+                Ok(loc) => match namespaces.get(&loc.encoding) {
+                    Some(namespace) => Ok(loc.with_key_namespace(*namespace)),
+                    None => Ok(loc),
+                },
                 Err(err) => Err(format!("\t{id} {:?}", err)),
             }
         })
@@ -227,43 +1431,160 @@ pub fn parse_data_block(
             Err(err) => Some(err),
         })
         .collect();
-    if errors.len() > 0 {
+    if !errors.is_empty() {
         Err(format!("Parsing errors:\n{}", errors.join("\n")).into())
     } else {
         Ok(db)
     }
 }
 
+/// Decode a JSON document, using simd-json when the `simd-json` feature is
+/// enabled. The buffer may be modified in place by the SIMD parser.
+pub fn decode_json(bytes: &mut [u8]) -> Result<Value, Box<dyn Error>> {
+    #[cfg(feature = "simd-json")]
+    let json = simd_json::serde::from_slice::<Value>(bytes)?;
+    #[cfg(not(feature = "simd-json"))]
+    let json = serde_json::from_slice::<Value>(bytes)?;
+    Ok(json)
+}
+
+/// The JSON datasets every data directory has; any other JSON files in the
+/// directory, such as those `LocationsDb::export` writes for other
+/// standards and custom kinds, are read as well.
+pub const DATA_FILES: [&str; 5] = [
+    "state.json",
+    "subdivision.json",
+    "locode.json",
+    "iata.json",
+    "ISO-3166-2:GB.json",
+];
+/// The UN/LOCODE code list of a data directory.
+pub const CODE_LIST_FILE: &str = "code-list_csv.csv";
+/// The optional redirects of retired keys in a data directory.
+pub const REDIRECTS_FILE: &str = "redirects.csv";
+/// The optional weights of locations by key in a data directory.
+pub const WEIGHTS_FILE: &str = "weights.csv";
+
+/// Parses a JSON dataset read from `reader`, such as an embedded asset, a
+/// network stream or an archive entry, into the database. Finish loading
+/// with `link_related` and `LocationsDb::mk_fst`.
+pub fn parse_json_reader<R: Read>(
+    db: LocationsDb,
+    mut reader: R,
+) -> Result<LocationsDb, Box<dyn Error>> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    match decode_json(&mut bytes)? {
+        Value::Object(obj) => {
+            let db = RwLock::new(db);
+            parse_data_block(&db, obj)?;
+            Ok(db.into_inner().expect("rw lock extract"))
+        }
+        _ => Err("Expected a JSON object of locations".into()),
+    }
+}
+
+/// Merges a UN/LOCODE code list in CSV read from `reader` into the
+/// database, as `parse_data_list` does.
+pub fn parse_csv_reader<R: Read>(
+    db: LocationsDb,
+    reader: R,
+) -> Result<LocationsDb, Box<dyn Error>> {
+    let mut csv_reader = ReaderBuilder::new().from_reader(reader);
+    let records = csv_reader
+        .deserialize::<CsvLocode>()
+        .collect::<Result<Vec<_>, _>>()?;
+    parse_data_list(db, records.into_iter())
+}
+
+#[cfg(feature = "fs")]
 pub fn parse_data_files(data_dir: PathBuf) -> Result<LocationsDb, Box<dyn Error>> {
-    let files = vec![
-        "state.json",
-        "subdivision.json",
-        "locode.json",
-        "iata.json",
-        "ISO-3166-2:GB.json",
-    ];
+    parse_data_files_with(data_dir, &LoadOptions::default())
+}
+
+#[cfg(feature = "fs")]
+pub fn parse_data_files_with(
+    data_dir: PathBuf,
+    options: &LoadOptions,
+) -> Result<LocationsDb, Box<dyn Error>> {
+    let mut others = std::fs::read_dir(&data_dir)?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|file| file.ends_with(".json") && !DATA_FILES.contains(&file.as_str()))
+        .collect::<Vec<_>>();
+    others.sort_unstable();
+    let files = DATA_FILES
+        .iter()
+        .map(|file| file.to_string())
+        .chain(others)
+        .collect::<Vec<_>>();
     let start = Instant::now();
-    let json_blocks = files.into_par_iter().map(|file| {
-        let path = data_dir.join(file);
+    #[cfg(feature = "parallel")]
+    let files = files.into_par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let files = files.into_iter();
+    let json_blocks = files.map(|file| {
+        let path = data_dir.join(&file);
         info!("Path {path:?}");
-        let fo = File::open(path).expect("cannot open json file");
-        let reader = BufReader::new(fo);
-        let json: serde_json::Value = serde_json::from_reader(reader).expect("cannot decode json");
+        let mut bytes = std::fs::read(path).expect("cannot open json file");
+        let json = decode_json(&mut bytes).expect("cannot decode json");
         info!("Decode json file {file}: {:.2?}", start.elapsed());
-        (file.to_string(), json)
+        (file, json)
     });
-    let mut db = parse_data_blocks(json_blocks, Some(start))?;
-    let csv_file = data_dir.join("code-list_csv.csv");
+    let db = LocationsDb::with_options(options);
+    let mut db = parse_data_blocks_into(db, json_blocks, Some(start))?;
+    let csv_file = data_dir.join(CODE_LIST_FILE);
     let csv_file_open = File::open(csv_file).expect("Read CSV File");
     let mut csv_reader = ReaderBuilder::new().from_reader(csv_file_open);
     let iter = csv_reader.deserialize::<CsvLocode>();
     db = parse_data_list(db, iter.map(|rec| rec.expect("CSV Locode decode")))?;
+    db = apply_load_options(link_related(db), options);
+    let weights_file = data_dir.join(WEIGHTS_FILE);
+    if weights_file.exists() {
+        let mut csv_reader = ReaderBuilder::new().from_path(weights_file)?;
+        let weights = csv_reader
+            .deserialize::<CsvWeight>()
+            .collect::<Result<Vec<_>, _>>()?;
+        db = parse_weights(db, weights.into_iter());
+    }
+    let redirects_file = data_dir.join(REDIRECTS_FILE);
+    if redirects_file.exists() {
+        db = parse_redirects_reader(db, File::open(redirects_file)?)?;
+    }
     let count = db.all.len();
     info!("parsed {} locations in: {:.2?}", count, start.elapsed());
     Ok(db.mk_fst())
 }
 
-pub fn parse_data_blocks<'a, I>(
+/// Builds an indexed database from in-memory JSON datasets and, optionally,
+/// a UN/LOCODE code list and a redirects table in CSV, without touching the
+/// filesystem.
+pub fn parse_data_slices(
+    json_blocks: &[(&str, &[u8])],
+    code_list: Option<&[u8]>,
+    redirects: Option<&[u8]>,
+) -> Result<LocationsDb, Box<dyn Error>> {
+    let blocks = json_blocks
+        .iter()
+        .map(|(name, bytes)| {
+            let mut bytes = bytes.to_vec();
+            decode_json(&mut bytes).map(|json| (name.to_string(), json))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    #[cfg(feature = "parallel")]
+    let blocks = blocks.into_par_iter();
+    let mut db = parse_data_blocks(blocks, None)?;
+    if let Some(code_list) = code_list {
+        db = parse_csv_reader(db, code_list)?;
+    }
+    db = link_related(db);
+    if let Some(redirects) = redirects {
+        db = parse_redirects_reader(db, redirects)?;
+    }
+    Ok(db.mk_fst())
+}
+
+#[cfg(feature = "parallel")]
+pub fn parse_data_blocks<I>(
     json_blocks: I,
     start: Option<Instant>,
 ) -> Result<LocationsDb, Box<dyn Error>>
@@ -271,30 +1592,93 @@ where
     I: IndexedParallelIterator,
     I::Item: Into<(String, serde_json::Value)>,
 {
-    let start = match start {
-        Some(start) => start,
-        None => Instant::now(),
-    };
-    let db = LocationsDb::default();
+    parse_data_blocks_into(LocationsDb::default(), json_blocks, start)
+}
+
+/// Parses the blocks into a database set up beforehand, as by
+/// `LocationsDb::with_options`.
+#[cfg(feature = "parallel")]
+pub fn parse_data_blocks_into<I>(
+    db: LocationsDb,
+    json_blocks: I,
+    start: Option<Instant>,
+) -> Result<LocationsDb, Box<dyn Error>>
+where
+    I: IndexedParallelIterator,
+    I::Item: Into<(String, serde_json::Value)>,
+{
+    let start = start.unwrap_or_else(Instant::now);
     let db = RwLock::new(db);
     let errors = json_blocks
         .into_par_iter()
-        .filter_map(|rf| -> Option<String> {
-            let (loc, json): (String, serde_json::Value) = rf.into();
-            match json {
-                Value::Object(obj) => {
-                    if let Err(e) = parse_data_block(&db, obj) {
-                        return Some(format!("{loc}: {}", e.to_string()));
-                    }
-                    info!("file decoded to native structs: {:.2?}", start.elapsed());
-                    None
-                }
-                other => Some(format!("{loc}: Expected a JSON object: {:?}", other)),
-            }
-        })
+        .filter_map(|rf| parse_json_block(&db, rf.into(), start))
+        .collect::<Vec<String>>();
+    blocks_result(db, errors)
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn parse_data_blocks<I>(
+    json_blocks: I,
+    start: Option<Instant>,
+) -> Result<LocationsDb, Box<dyn Error>>
+where
+    I: IntoIterator,
+    I::Item: Into<(String, serde_json::Value)>,
+{
+    parse_data_blocks_into(LocationsDb::default(), json_blocks, start)
+}
+
+/// Parses the blocks into a database set up beforehand, as by
+/// `LocationsDb::with_options`.
+#[cfg(not(feature = "parallel"))]
+pub fn parse_data_blocks_into<I>(
+    db: LocationsDb,
+    json_blocks: I,
+    start: Option<Instant>,
+) -> Result<LocationsDb, Box<dyn Error>>
+where
+    I: IntoIterator,
+    I::Item: Into<(String, serde_json::Value)>,
+{
+    let start = start.unwrap_or_else(Instant::now);
+    let db = RwLock::new(db);
+    let errors = json_blocks
+        .into_iter()
+        .filter_map(|rf| parse_json_block(&db, rf.into(), start))
         .collect::<Vec<String>>();
-    if errors.len() > 0 {
+    blocks_result(db, errors)
+}
+
+fn parse_json_block(
+    db: &RwLock<LocationsDb>,
+    (loc, json): (String, serde_json::Value),
+    start: Instant,
+) -> Option<String> {
+    match json {
+        Value::Object(obj) => {
+            if let Err(e) = parse_data_block(db, obj) {
+                return Some(format!("{loc}: {}", e));
+            }
+            info!("file decoded to native structs: {:.2?}", start.elapsed());
+            None
+        }
+        other => Some(format!("{loc}: Expected a JSON object: {:?}", other)),
+    }
+}
+
+fn blocks_result(
+    db: RwLock<LocationsDb>,
+    errors: Vec<String>,
+) -> Result<LocationsDb, Box<dyn Error>> {
+    if !errors.is_empty() {
         return Err(format!("Blocks failed:\n{}", errors.join("\n")).into());
     }
     Ok(db.into_inner().expect("rw lock extract"))
 }
+
+// the alphanumeric words of a normalized string
+fn split_words(s: &str) -> Vec<&str> {
+    s.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect()
+}
@@ -1,6 +1,367 @@
+//! The `berlin` command-line tool, enabled by the `cli` feature.
+
 use std::boxed::Box;
 use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use berlin_core::bulk::{geocode_csv, search_ndjson, GeocodeOptions, NdjsonQuery};
+use berlin_core::eval::{evaluate, EvalOptions, LabelledQuery};
+use berlin_core::explain::BoostKind;
+use berlin_core::lint::lint_data_dir;
+use berlin_core::location::DisplayStyle;
+use berlin_core::locations_db::{parse_data_files, LocationsDb};
+use berlin_core::schema::schema;
+use berlin_core::search::SearchTerm;
+use berlin_core::snapshot::{read_snapshot, write_snapshot};
+
+#[derive(Parser)]
+#[command(name = "berlin", about = "Identify locations in free text")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+struct Source {
+    /// Directory holding the JSON datasets and UN/LOCODE code list
+    #[arg(long, env = "BERLIN_DATA_DIR", conflicts_with = "snapshot")]
+    data: Option<PathBuf>,
+    /// Snapshot written by `build-snapshot`
+    #[arg(long, env = "BERLIN_SNAPSHOT")]
+    snapshot: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct SearchOptions {
+    /// Only match locations in this state (ISO-3166-1 alpha-2)
+    #[arg(long)]
+    state: Option<String>,
+    #[arg(long, default_value_t = 10)]
+    limit: usize,
+    #[arg(long, default_value_t = 2)]
+    lev_distance: u32,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Search for locations matching a query
+    Search {
+        query: String,
+        #[command(flatten)]
+        source: Source,
+        #[command(flatten)]
+        options: SearchOptions,
+    },
+    /// Load the database once and search queries read interactively,
+    /// explaining the score of each result
+    Repl {
+        #[command(flatten)]
+        source: Source,
+        #[command(flatten)]
+        options: SearchOptions,
+    },
+    /// Print a location by key, e.g. UN-LOCODE-gb:abc
+    Lookup {
+        key: String,
+        #[command(flatten)]
+        source: Source,
+    },
+    /// Geocode a CSV column, writing the rows with the best match appended
+    Bulk {
+        csv: PathBuf,
+        /// Name of the column to geocode
+        #[arg(long)]
+        column: String,
+        /// Output file, standard output if omitted
+        #[arg(long)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        source: Source,
+        #[command(flatten)]
+        options: SearchOptions,
+    },
+    /// Search JSON Lines queries, {"q": ..., "state": ...}, writing a line
+    /// of results for each
+    Ndjson {
+        /// Input file, standard input if omitted
+        input: Option<PathBuf>,
+        /// Output file, standard output if omitted
+        #[arg(long)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        source: Source,
+        #[command(flatten)]
+        options: SearchOptions,
+    },
+    /// Measure the ranking against a CSV of `query` and `expected_key`
+    /// columns, printing precision at 1 and 5, the mean reciprocal rank and
+    /// the queries that missed
+    Eval {
+        corpus: PathBuf,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+        #[command(flatten)]
+        source: Source,
+        #[arg(long, default_value_t = 2)]
+        lev_distance: u32,
+    },
+    /// Check a data directory for referential problems, coordinates out of
+    /// range, duplicated names and mis-encoded text, printing the problems
+    /// as JSON and failing if there are any
+    LintData { data_dir: PathBuf },
+    /// Parse a data directory and write a snapshot that loads faster
+    BuildSnapshot { data_dir: PathBuf, out: PathBuf },
+    /// Print the JSON Schema of the query and result documents
+    Schema,
+    /// Download the files of a release manifest into a data directory,
+    /// verifying their checksums
+    #[cfg(feature = "fetch")]
+    Fetch {
+        manifest: PathBuf,
+        data_dir: PathBuf,
+    },
+}
+
+impl Source {
+    fn load(&self) -> Result<LocationsDb, Box<dyn Error>> {
+        match (&self.data, &self.snapshot) {
+            (_, Some(snapshot)) => read_snapshot(BufReader::new(File::open(snapshot)?)),
+            (Some(data), None) => parse_data_files(data.clone()),
+            #[cfg(feature = "embedded-data")]
+            (None, None) => LocationsDb::builtin(),
+            #[cfg(not(feature = "embedded-data"))]
+            (None, None) => Err("either --data or --snapshot is required".into()),
+        }
+    }
+}
+
+impl SearchOptions {
+    fn search_term(&self, query: String) -> SearchTerm {
+        SearchTerm::from_raw_query(query, self.state.clone(), self.limit, self.lev_distance)
+    }
+}
+
+const REPL_HELP: &str = "\
+Enter a query to search, or a command:
+  :state <code>   only match locations in this state, or none with :state
+  :limit <n>      the most results shown
+  :lev <n>        the Levenshtein distance of inexact matches
+  :help           this message
+  :quit           leave, as does end of input";
+
+/// Reads queries from standard input until it ends, printing each result
+/// with its base score and the boosts it received.
+fn repl(db: &LocationsDb, mut options: SearchOptions) -> Result<(), Box<dyn Error>> {
+    let mut out = std::io::stdout().lock();
+    writeln!(out, "{} locations loaded; :help for commands", db.all.len())?;
+    let mut lines = std::io::stdin().lock().lines();
+    loop {
+        write!(out, "berlin> ")?;
+        out.flush()?;
+        let Some(line) = lines.next().transpose()? else {
+            writeln!(out)?;
+            break;
+        };
+        let line = line.trim();
+        let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let arg = arg.trim();
+        match command {
+            "" => continue,
+            ":quit" | ":q" => break,
+            ":help" => writeln!(out, "{REPL_HELP}")?,
+            ":state" => options.state = (!arg.is_empty()).then(|| arg.to_lowercase()),
+            ":limit" | ":lev" => match arg.parse() {
+                Ok(n) if command == ":limit" => options.limit = n as usize,
+                Ok(n) => options.lev_distance = n,
+                Err(_) => writeln!(out, "{command} takes a number")?,
+            },
+            _ if command.starts_with(':') => writeln!(out, "unknown command {command}")?,
+            _ => {
+                let st = options.search_term(line.to_string());
+                let parsed = st.parsed();
+                let terms = |terms: &[berlin_core::search::ParsedTerm]| {
+                    let terms = terms.iter().map(|t| t.term.as_str());
+                    terms.collect::<Vec<_>>().join(", ")
+                };
+                writeln!(
+                    out,
+                    "codes: [{}] exact: [{}] fuzzy: [{}]",
+                    terms(&parsed.codes),
+                    terms(&parsed.exact),
+                    terms(&parsed.fuzzy)
+                )?;
+                for clamp in st.lev_clamps() {
+                    writeln!(
+                        out,
+                        "narrowed: {} to distance {} by length",
+                        clamp.term, clamp.distance
+                    )?;
+                }
+                for (n, e) in db.explain(&st).into_iter().enumerate() {
+                    let name = db.all[&e.key].display_name(db, DisplayStyle::Hierarchy);
+                    writeln!(
+                        out,
+                        "{:>3}. {:>5}  {}  {}",
+                        n + 1,
+                        e.score.score,
+                        e.key,
+                        name
+                    )?;
+                    write!(out, "            base {}", e.base)?;
+                    for boost in &e.boosts {
+                        let kind = match boost.kind {
+                            BoostKind::Parent => "parent",
+                            BoostKind::Child => "child",
+                        };
+                        write!(out, ", {kind} {:+} from {}", boost.amount, boost.source)?;
+                    }
+                    if let Some(scored) = e.scored_key {
+                        write!(out, ", scored as {scored}")?;
+                    }
+                    writeln!(out)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn output(path: &Option<PathBuf>) -> Result<Box<dyn Write>, Box<dyn Error>> {
+    Ok(match path {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(std::io::stdout().lock()),
+    })
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+    match Cli::parse().command {
+        Command::Search {
+            query,
+            source,
+            options,
+        } => {
+            let db = source.load()?;
+            let st = options.search_term(query);
+            let mut out = std::io::stdout().lock();
+            for (key, score) in db.search(&st) {
+                let name = db.all[&key].display_name(&db, DisplayStyle::Hierarchy);
+                writeln!(out, "{}\t{}\t{}", score.score, key, name)?;
+            }
+        }
+        Command::Repl { source, options } => {
+            let db = source.load()?;
+            repl(&db, options)?;
+        }
+        Command::Lookup { key, source } => {
+            let db = source.load()?;
+            match db.resolve(&key) {
+                Some(resolution) => println!("{}", serde_json::to_string_pretty(&resolution)?),
+                None => return Err(format!("no location with key {key}").into()),
+            }
+        }
+        Command::Bulk {
+            csv,
+            column,
+            output: path,
+            source,
+            options,
+        } => {
+            let db = source.load()?;
+            let options = GeocodeOptions {
+                state: options.state,
+                lev_distance: options.lev_distance,
+            };
+            geocode_csv(&db, File::open(csv)?, &column, &options, output(&path)?)?;
+        }
+        Command::Ndjson {
+            input,
+            output: path,
+            source,
+            options,
+        } => {
+            let db = source.load()?;
+            let defaults = NdjsonQuery {
+                q: String::new(),
+                state: options.state,
+                limit: Some(options.limit),
+                lev_distance: Some(options.lev_distance),
+            };
+            let out = output(&path)?;
+            match input {
+                Some(input) => {
+                    let reader = BufReader::new(File::open(input)?);
+                    search_ndjson(&db, reader, out, &defaults)?
+                }
+                None => search_ndjson(&db, std::io::stdin().lock(), out, &defaults)?,
+            }
+        }
+        Command::Eval {
+            corpus,
+            json,
+            source,
+            lev_distance,
+        } => {
+            let db = source.load()?;
+            let corpus = LabelledQuery::read_csv(File::open(corpus)?)?;
+            let options = EvalOptions {
+                lev_distance,
+                ..Default::default()
+            };
+            let report = evaluate(&db, &corpus, &options);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+                return Ok(());
+            }
+            let mut out = std::io::stdout().lock();
+            for failure in &report.failures {
+                let rank = failure.rank.map_or("-".to_string(), |r| r.to_string());
+                let top = failure.top.iter().map(|(k, s)| format!("{k} ({s})"));
+                let top = match failure.top.is_empty() {
+                    true => "nothing".to_string(),
+                    false => top.collect::<Vec<_>>().join(", "),
+                };
+                let unknown = if failure.known { "" } else { " (unknown key)" };
+                writeln!(
+                    out,
+                    "{:?}: expected {}{unknown} at {rank}; got {top}",
+                    failure.query, failure.expected_key
+                )?;
+            }
+            writeln!(
+                out,
+                "queries {}\tp@1 {:.3}\tp@5 {:.3}\tmrr {:.3}",
+                report.queries, report.precision_at_1, report.precision_at_5, report.mrr
+            )?;
+        }
+        Command::LintData { data_dir } => {
+            let report = lint_data_dir(&data_dir)?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            if report.has_errors() {
+                return Err(format!("{} has data problems", data_dir.display()).into());
+            }
+        }
+        Command::BuildSnapshot { data_dir, out } => {
+            let db = parse_data_files(data_dir)?;
+            write_snapshot(&db, BufWriter::new(File::create(out)?))?;
+        }
+        Command::Schema => {
+            println!("{}", serde_json::to_string_pretty(&schema())?);
+        }
+        #[cfg(feature = "fetch")]
+        Command::Fetch { manifest, data_dir } => {
+            let release = berlin_core::fetch::Release::read_manifest(File::open(manifest)?)?;
+            for path in release.fetch(&data_dir)? {
+                println!("{}", path.display());
+            }
+        }
+    }
     Ok(())
 }
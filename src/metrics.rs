@@ -0,0 +1,88 @@
+//! Search metrics, recorded with the OpenTelemetry metrics API under the
+//! meter "berlin".
+//!
+//! The instruments are created from the global meter provider by the first
+//! search, so install one with `opentelemetry::global::set_meter_provider`
+//! before searching; without one, OpenTelemetry records nothing.
+
+use std::sync::OnceLock;
+
+use opentelemetry::global;
+use opentelemetry::metrics::{Counter, Histogram};
+
+use crate::slow_query::SlowQuery;
+
+pub const METER: &str = "berlin";
+
+/// Searches run.
+pub const SEARCHES: &str = "berlin.search.count";
+/// Seconds spent in each stage of a search.
+pub const PRE_FILTER_SECONDS: &str = "berlin.search.pre_filter.duration";
+pub const SCORE_SECONDS: &str = "berlin.search.score.duration";
+pub const RANK_SECONDS: &str = "berlin.search.rank.duration";
+pub const SEARCH_SECONDS: &str = "berlin.search.duration";
+/// Candidates found by the pre-filter, and those scoring above the
+/// inclusion threshold.
+pub const CANDIDATES: &str = "berlin.search.candidates";
+pub const SCORED_CANDIDATES: &str = "berlin.search.scored_candidates";
+/// Known terms resolved directly from the word index (hits) or falling
+/// through to an empty lookup (misses); the ratio is the hit rate.
+pub const WORD_INDEX_HITS: &str = "berlin.search.word_index.hits";
+pub const WORD_INDEX_MISSES: &str = "berlin.search.word_index.misses";
+
+struct Instruments {
+    searches: Counter<u64>,
+    pre_filter: Histogram<f64>,
+    score: Histogram<f64>,
+    rank: Histogram<f64>,
+    search: Histogram<f64>,
+    candidates: Histogram<u64>,
+    scored: Histogram<u64>,
+    word_index_hits: Counter<u64>,
+    word_index_misses: Counter<u64>,
+}
+
+static INSTRUMENTS: OnceLock<Instruments> = OnceLock::new();
+
+fn instruments() -> &'static Instruments {
+    INSTRUMENTS.get_or_init(|| {
+        let meter = global::meter(METER);
+        let seconds = |name| meter.f64_histogram(name).with_unit("s").build();
+        let candidates = |name| meter.u64_histogram(name).with_unit("{candidate}").build();
+        let lookups = |name| meter.u64_counter(name).with_unit("{lookup}").build();
+        Instruments {
+            searches: meter.u64_counter(SEARCHES).with_unit("{search}").build(),
+            pre_filter: seconds(PRE_FILTER_SECONDS),
+            score: seconds(SCORE_SECONDS),
+            rank: seconds(RANK_SECONDS),
+            search: seconds(SEARCH_SECONDS),
+            candidates: candidates(CANDIDATES),
+            scored: candidates(SCORED_CANDIDATES),
+            word_index_hits: lookups(WORD_INDEX_HITS),
+            word_index_misses: lookups(WORD_INDEX_MISSES),
+        }
+    })
+}
+
+/// Records the stage timings and candidate counts of a search.
+pub(crate) fn record_search(query: &SlowQuery) {
+    let instruments = instruments();
+    instruments.searches.add(1, &[]);
+    instruments
+        .pre_filter
+        .record(query.pre_filter.as_secs_f64(), &[]);
+    instruments.score.record(query.score.as_secs_f64(), &[]);
+    instruments.rank.record(query.rank.as_secs_f64(), &[]);
+    instruments.search.record(query.total.as_secs_f64(), &[]);
+    instruments.candidates.record(query.candidates as u64, &[]);
+    instruments.scored.record(query.scored as u64, &[]);
+}
+
+/// Counts a lookup of a known term in the word index.
+pub(crate) fn record_word_lookup(hit: bool) {
+    let instruments = instruments();
+    match hit {
+        true => instruments.word_index_hits.add(1, &[]),
+        false => instruments.word_index_misses.add(1, &[]),
+    }
+}
@@ -0,0 +1,119 @@
+//! Node.js bindings, enabled by the `node` feature. Build the addon with
+//! `napi build --features node` (see `package.json`).
+
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use ustr::Ustr;
+
+use crate::location::{DisplayStyle, Location};
+use crate::locations_db::{self, parse_data_files};
+use crate::search::{Score, SearchTerm};
+
+#[napi]
+pub struct LocationsDb {
+    db: locations_db::LocationsDb,
+}
+
+#[napi(object)]
+pub struct SearchOptions {
+    pub limit: Option<u32>,
+    pub lev_dist: Option<u32>,
+    pub state: Option<String>,
+}
+
+#[napi(object)]
+pub struct AltName {
+    pub lang: String,
+    pub name: String,
+}
+
+#[napi(object)]
+pub struct SearchResult {
+    pub key: String,
+    pub encoding: String,
+    pub id: String,
+    pub state: String,
+    pub display_name: String,
+    pub subdiv: Option<String>,
+    pub names: Vec<String>,
+    pub codes: Vec<String>,
+    pub alt_names: Vec<AltName>,
+    // the same place under other code systems, e.g. an airport's LOCODE
+    pub related_keys: Vec<String>,
+    pub score: Option<i64>,
+    pub offset: Option<Vec<u32>>,
+    pub redirected_from: Option<String>,
+}
+
+/// Load and index the datasets in a data directory.
+#[napi]
+pub fn load_db(path: String) -> Result<LocationsDb> {
+    let db = parse_data_files(path.into()).map_err(|e| Error::from_reason(e.to_string()))?;
+    Ok(LocationsDb { db })
+}
+
+#[napi]
+impl LocationsDb {
+    /// Search for locations, returning results in score order.
+    #[napi]
+    pub fn search(&self, query: String, options: Option<SearchOptions>) -> Vec<SearchResult> {
+        let (limit, lev_dist, state) = match options {
+            Some(o) => (o.limit.unwrap_or(10), o.lev_dist.unwrap_or(2), o.state),
+            None => (10, 2, None),
+        };
+        let st = SearchTerm::from_raw_query(query, state, limit as usize, lev_dist);
+        self.db
+            .search(&st)
+            .into_iter()
+            .map(|(key, score)| SearchResult {
+                redirected_from: self
+                    .db
+                    .redirected_from(&st, key, &score)
+                    .map(|k| k.to_string()),
+                ..self.result(key, Some(score))
+            })
+            .collect()
+    }
+
+    /// Look up a location by its key, e.g. `UN-LOCODE-gb:abc`.
+    #[napi]
+    pub fn retrieve(&self, key: String) -> Option<SearchResult> {
+        self.db.resolve(&key).map(|resolution| SearchResult {
+            redirected_from: resolution.redirected_from.map(|k| k.to_string()),
+            ..self.result(resolution.location.key, None)
+        })
+    }
+
+    #[napi(getter)]
+    pub fn size(&self) -> u32 {
+        self.db.all.len() as u32
+    }
+}
+
+impl LocationsDb {
+    fn result(&self, key: Ustr, score: Option<Score>) -> SearchResult {
+        let loc: &Location = self.db.all.get(&key).expect("location in db");
+        SearchResult {
+            key: loc.key.to_string(),
+            encoding: loc.encoding.to_string(),
+            id: loc.id.to_string(),
+            state: loc.get_state().to_string(),
+            display_name: loc.display_name(&self.db, DisplayStyle::Hierarchy),
+            subdiv: loc.get_subdiv().map(|s| s.to_string()),
+            names: loc.get_names().iter().map(|n| n.to_string()).collect(),
+            codes: loc.get_codes().iter().map(|c| c.to_string()).collect(),
+            alt_names: loc
+                .alt_names
+                .iter()
+                .map(|(lang, name)| AltName {
+                    lang: lang.to_string(),
+                    name: name.to_string(),
+                })
+                .collect(),
+            related_keys: loc.related_keys.iter().map(|k| k.to_string()).collect(),
+            score: score.map(|s| s.score),
+            offset: score.map(|s| vec![s.offset.start as u32, s.offset.end as u32]),
+            redirected_from: None,
+        }
+    }
+}
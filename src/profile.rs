@@ -0,0 +1,58 @@
+//! Named bundles of filters and boosts for common uses of search, chosen
+//! with `SearchTerm::with_profile`.
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{DedupBy, DedupPolicy, SearchConfig};
+use crate::location::{LocData, Location, LocodeFunction, IATA_ENCODING};
+use crate::PROFILE_FUNCTION_BOOST;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchProfile {
+    // ports and multimodal terminals, ports first
+    Shipping,
+    // airports and the LOCODEs of places with one, airport records first
+    Aviation,
+    // anything but airport records, post offices first
+    Postal,
+}
+
+impl SearchProfile {
+    /// Whether results like the location are kept.
+    pub fn admits(&self, loc: &Location) -> bool {
+        let functions = loc.functions();
+        match self {
+            SearchProfile::Shipping => {
+                matches!(loc.data, LocData::Locd(_))
+                    && functions
+                        .iter()
+                        .any(|f| matches!(f, LocodeFunction::Port | LocodeFunction::Multimodal))
+            }
+            SearchProfile::Aviation => functions.contains(&LocodeFunction::Airport),
+            SearchProfile::Postal => !matches!(loc.data, LocData::Airp(_)),
+        }
+    }
+    /// The boost to the score of the location.
+    pub fn boost(&self, loc: &Location) -> i64 {
+        let preferred = match self {
+            SearchProfile::Shipping => loc.functions().contains(&LocodeFunction::Port),
+            SearchProfile::Aviation => matches!(loc.data, LocData::Airp(_)),
+            SearchProfile::Postal => loc.functions().contains(&LocodeFunction::Postal),
+        };
+        match preferred {
+            true => PROFILE_FUNCTION_BOOST,
+            false => 0,
+        }
+    }
+    /// Adjusts the config to the profile: an airport and its LOCODE are
+    /// kept as one result.
+    pub fn configure(&self, config: &mut SearchConfig) {
+        if let SearchProfile::Aviation = self {
+            config.dedup.get_or_insert_with(|| DedupPolicy {
+                by: vec![DedupBy::CrossReference],
+                preferred: vec![IATA_ENCODING.into()],
+            });
+        }
+    }
+}
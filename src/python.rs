@@ -0,0 +1,128 @@
+//! Python bindings, enabled by the `python` feature. Build the extension
+//! module with `maturin build` (see `pyproject.toml`).
+
+use std::path::PathBuf;
+
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use ustr::Ustr;
+
+use crate::location::{DisplayStyle, Location};
+use crate::locations_db::{parse_data_files, LocationsDb};
+use crate::search::{Score, SearchTerm};
+
+#[pyclass(name = "LocationsDb", module = "berlin")]
+pub struct PyLocationsDb {
+    db: LocationsDb,
+}
+
+#[pymethods]
+impl PyLocationsDb {
+    /// Load and index the datasets in a data directory.
+    #[staticmethod]
+    fn load(py: Python, path: PathBuf) -> PyResult<Self> {
+        let db = py
+            .allow_threads(|| parse_data_files(path).map_err(|e| e.to_string()))
+            .map_err(PyRuntimeError::new_err)?;
+        Ok(PyLocationsDb { db })
+    }
+
+    /// Search for locations, returning a list of result dicts in score order.
+    #[pyo3(signature = (query, limit = 10, lev_dist = 2, state = None))]
+    fn search(
+        &self,
+        py: Python,
+        query: String,
+        limit: usize,
+        lev_dist: u32,
+        state: Option<String>,
+    ) -> PyResult<Vec<PyObject>> {
+        let results = py.allow_threads(|| {
+            let st = SearchTerm::from_raw_query(query, state, limit, lev_dist);
+            self.db
+                .search(&st)
+                .into_iter()
+                .map(|(key, score)| (key, score, self.db.redirected_from(&st, key, &score)))
+                .collect::<Vec<_>>()
+        });
+        results
+            .into_iter()
+            .map(|(key, score, redirected_from)| {
+                let dict = self.result_dict(py, key, Some(score))?;
+                dict.as_ref(py)
+                    .downcast::<PyDict>()?
+                    .set_item("redirected_from", redirected_from.map(|k| k.to_string()))?;
+                Ok(dict)
+            })
+            .collect()
+    }
+
+    /// Look up a location by its key, e.g. `UN-LOCODE-gb:abc`.
+    fn retrieve(&self, py: Python, key: &str) -> PyResult<Option<PyObject>> {
+        match self.db.resolve(key) {
+            Some(resolution) => {
+                let dict = self.result_dict(py, resolution.location.key, None)?;
+                let redirected_from = resolution.redirected_from.map(|k| k.to_string());
+                dict.as_ref(py)
+                    .downcast::<PyDict>()?
+                    .set_item("redirected_from", redirected_from)?;
+                Ok(Some(dict))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn __len__(&self) -> usize {
+        self.db.all.len()
+    }
+}
+
+impl PyLocationsDb {
+    fn result_dict(&self, py: Python, key: Ustr, score: Option<Score>) -> PyResult<PyObject> {
+        let loc: &Location = self.db.all.get(&key).expect("location in db");
+        let dict = PyDict::new(py);
+        dict.set_item("key", loc.key.as_str())?;
+        dict.set_item("encoding", loc.encoding.as_str())?;
+        dict.set_item("id", loc.id.as_str())?;
+        dict.set_item("state", loc.get_state().as_str())?;
+        let display_name = loc.display_name(&self.db, DisplayStyle::Hierarchy);
+        dict.set_item("display_name", display_name)?;
+        dict.set_item("subdiv", loc.get_subdiv().map(|s| s.to_string()))?;
+        let names = loc
+            .get_names()
+            .iter()
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>();
+        dict.set_item("names", names)?;
+        let codes = loc
+            .get_codes()
+            .iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<_>>();
+        dict.set_item("codes", codes)?;
+        let alt_names = loc
+            .alt_names
+            .iter()
+            .map(|(lang, name)| (lang.to_string(), name.to_string()))
+            .collect::<Vec<_>>();
+        dict.set_item("alt_names", alt_names)?;
+        let related_keys = loc
+            .related_keys
+            .iter()
+            .map(|k| k.to_string())
+            .collect::<Vec<_>>();
+        dict.set_item("related_keys", related_keys)?;
+        if let Some(score) = score {
+            dict.set_item("score", score.score)?;
+            dict.set_item("offset", (score.offset.start, score.offset.end))?;
+        }
+        Ok(dict.into())
+    }
+}
+
+#[pymodule]
+fn berlin(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyLocationsDb>()?;
+    Ok(())
+}
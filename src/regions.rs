@@ -0,0 +1,162 @@
+//! Groupings of states, such as the EU or the continents, of
+//! subdivisions, such as the nations of the UK, and of any locations, such
+//! as the places of a metro area, searchable as locations of their own and
+//! linked to their members as virtual parents.
+
+use std::error::Error;
+use std::io::Read;
+
+use serde::{Deserialize, Serialize};
+use ustr::{Ustr, UstrSet};
+
+use crate::location::{LocData, Location, Region, REGION_ENCODING};
+use crate::locations_db::LocationsDb;
+use crate::normalize;
+use crate::search::Score;
+
+/// The regions `RegionDef::builtin` reads: the EU, the EEA, the Schengen
+/// Area, the continents and the nations of the UK.
+pub const BUILTIN_REGIONS: &str = include_str!("regions.json");
+
+/// A region and how its members are found, as read from JSON:
+///
+/// ```json
+/// [{"code": "benelux", "name": "Benelux", "states": ["be", "nl", "lu"]}]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegionDef {
+    // the id of the region's key, as in "REGION-eu", also searched as a code
+    pub code: String,
+    pub name: String,
+    // ISO 3166-1 alpha-2 codes
+    #[serde(default)]
+    pub states: Vec<String>,
+    // continent codes of the state dataset, such as "eu" or "as"
+    #[serde(default)]
+    pub continents: Vec<String>,
+    // ISO 3166-2 codes, as "gb:cay"
+    #[serde(default)]
+    pub subdivisions: Vec<String>,
+    // a state whose subdivisions in none of the regions defined earlier are
+    // members, as England's are of the rest of GB
+    #[serde(default)]
+    pub remainder_of: Option<String>,
+    // location keys, as of the LOCODEs making up a metro area
+    #[serde(default)]
+    pub keys: Vec<String>,
+}
+
+impl RegionDef {
+    pub fn builtin() -> Vec<Self> {
+        serde_json::from_str(BUILTIN_REGIONS).expect("builtin regions")
+    }
+    pub fn read<R: Read>(reader: R) -> Result<Vec<Self>, Box<dyn Error>> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+    fn members(&self, db: &LocationsDb, claimed: &UstrSet) -> UstrSet {
+        let codes = |codes: &[String]| {
+            codes
+                .iter()
+                .filter_map(|c| Ustr::from_existing(&normalize(c)))
+                .collect::<UstrSet>()
+        };
+        let (states, continents) = (codes(&self.states), codes(&self.continents));
+        let remainder_of = self
+            .remainder_of
+            .as_ref()
+            .and_then(|s| Ustr::from_existing(&normalize(s)));
+        let mut members = codes(&self.subdivisions)
+            .iter()
+            .filter_map(|sd| db.subdiv_by_code.get(sd).copied())
+            .chain(self.keys.iter().filter_map(|k| Ustr::from_existing(k)))
+            .filter(|k| db.all.contains_key(k))
+            .collect::<UstrSet>();
+        for loc in db.all.values() {
+            let member = match loc.data {
+                LocData::St(s) => states.contains(&s.alpha2) || continents.contains(&s.continent),
+                LocData::Subdv(sd) => {
+                    Some(sd.supercode) == remainder_of && !claimed.contains(&loc.key)
+                }
+                _ => false,
+            };
+            if member {
+                members.insert(loc.key);
+            }
+        }
+        members
+    }
+}
+
+/// Adds the regions as locations and links their members to them. Run
+/// once the states and subdivisions are loaded, before `mk_fst`.
+pub fn add_regions(mut db: LocationsDb, defs: &[RegionDef]) -> LocationsDb {
+    let mut claimed = UstrSet::default();
+    for def in defs {
+        let region = Region::new(&def.name, &def.code);
+        let loc = Location::from_data(REGION_ENCODING.into(), &def.code, LocData::Regn(region));
+        for member in def.members(&db, &claimed) {
+            let regions = &mut db.all.get_mut(&member).expect("location in db").regions;
+            if !regions.contains(&loc.key) {
+                regions.push(loc.key);
+            }
+            db.members_by_region
+                .entry(loc.key)
+                .or_default()
+                .insert(member);
+            claimed.insert(member);
+        }
+        db.insert(loc);
+    }
+    db
+}
+
+impl LocationsDb {
+    /// The regions a location lies within, through its own membership or
+    /// its state's or subdivision's.
+    pub fn regions_of(&self, key: Ustr) -> Vec<Ustr> {
+        let Some(loc) = self.all.get(&key) else {
+            return vec![];
+        };
+        let (state, subdiv) = loc.get_parents();
+        let mut regions: Vec<Ustr> = vec![];
+        for area in [Some(key), state, subdiv].into_iter().flatten() {
+            for region in self.all.get(&area).into_iter().flat_map(|l| &l.regions) {
+                if !regions.contains(region) {
+                    regions.push(*region);
+                }
+            }
+        }
+        regions
+    }
+    pub fn in_region(&self, key: Ustr, region: Ustr) -> bool {
+        self.regions_of(key).contains(&region)
+    }
+    /// The locations linked to a region, sorted.
+    pub fn region_members(&self, region: Ustr) -> Vec<Ustr> {
+        let mut members = self
+            .members_by_region
+            .get(&region)
+            .map(|m| m.iter().copied().collect::<Vec<_>>())
+            .unwrap_or_default();
+        members.sort_unstable_by_key(|k| k.as_str());
+        members
+    }
+    /// Replaces each region among ranked results by those of its members
+    /// not ranked already, at the region's score.
+    pub fn expand_regions(&self, results: Vec<(Ustr, Score)>) -> Vec<(Ustr, Score)> {
+        let mut ranked = results.iter().map(|(k, _)| *k).collect::<UstrSet>();
+        let mut expanded = vec![];
+        for (key, score) in results {
+            if !self.members_by_region.contains_key(&key) {
+                expanded.push((key, score));
+                continue;
+            }
+            for member in self.region_members(key) {
+                if ranked.insert(member) {
+                    expanded.push((member, score));
+                }
+            }
+        }
+        expanded
+    }
+}
@@ -0,0 +1,72 @@
+//! Several independent databases under names, searched together.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use ustr::Ustr;
+
+use crate::locations_db::LocationsDb;
+use crate::search::{Score, SearchTerm};
+
+/// Named databases, such as "unlocode", "customers" and "warehouses", for
+/// deployments serving several datasets or tenants.
+#[derive(Default)]
+pub struct DbRegistry {
+    pub dbs: BTreeMap<String, LocationsDb>,
+}
+
+/// A result of a combined search, with the name of its database, since
+/// keys need not be unique across databases.
+#[derive(Clone, PartialEq, Eq)]
+pub struct RegistryResult<'a> {
+    pub db: &'a str,
+    pub key: Ustr,
+    pub score: Score,
+}
+
+impl DbRegistry {
+    /// Adds a database under a name, returning any it replaces.
+    pub fn insert(&mut self, name: &str, db: LocationsDb) -> Option<LocationsDb> {
+        self.dbs.insert(name.to_string(), db)
+    }
+    pub fn remove(&mut self, name: &str) -> Option<LocationsDb> {
+        self.dbs.remove(name)
+    }
+    pub fn get(&self, name: &str) -> Option<&LocationsDb> {
+        self.dbs.get(name)
+    }
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut LocationsDb> {
+        self.dbs.get_mut(name)
+    }
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.dbs.keys().map(|n| n.as_str())
+    }
+    /// Searches every database, then ranks their results together.
+    pub fn search(&self, st: &SearchTerm) -> Vec<RegistryResult<'_>> {
+        self.search_in(self.names(), st)
+    }
+    /// Searches the named databases, then ranks their results together.
+    /// Unknown names are skipped.
+    pub fn search_in<'a, 'n>(
+        &'a self,
+        names: impl IntoIterator<Item = &'n str>,
+        st: &SearchTerm,
+    ) -> Vec<RegistryResult<'a>> {
+        let mut results = names
+            .into_iter()
+            .filter_map(|name| self.dbs.get_key_value(name))
+            .flat_map(|(name, db)| {
+                db.search(st)
+                    .into_iter()
+                    .map(|(key, score)| RegistryResult {
+                        db: name.as_str(),
+                        key,
+                        score,
+                    })
+            })
+            .collect::<Vec<_>>();
+        results.sort_by_key(|r| (Reverse(r.score), r.key.as_str(), r.db));
+        results.truncate(st.max_results());
+        results
+    }
+}
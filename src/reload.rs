@@ -0,0 +1,64 @@
+//! Serving searches while a new database is built, swapping it in once
+//! it is ready.
+
+use std::error::Error;
+use std::sync::{Arc, RwLock};
+use std::thread::JoinHandle;
+
+use crate::locations_db::LocationsDb;
+
+/// A database generation, which stays usable for as long as it is held,
+/// so searches in flight during a swap finish against it.
+#[derive(Clone)]
+pub struct Generation {
+    pub number: u64,
+    pub db: Arc<LocationsDb>,
+}
+
+/// Holds the current database, replacing it whole on reload. Readers
+/// take the lock only to clone the current `Generation`.
+pub struct ReloadableDb {
+    current: RwLock<Generation>,
+}
+
+impl ReloadableDb {
+    pub fn new(db: LocationsDb) -> Self {
+        ReloadableDb {
+            current: RwLock::new(Generation {
+                number: 0,
+                db: Arc::new(db),
+            }),
+        }
+    }
+    pub fn current(&self) -> Generation {
+        self.current.read().expect("cannot aquire lock").clone()
+    }
+    pub fn generation(&self) -> u64 {
+        self.current.read().expect("cannot aquire lock").number
+    }
+    /// Makes `db` current, returning its generation number. The previous
+    /// database is dropped, if no search still holds it, after the lock is
+    /// released, so that readers are not kept waiting on the drop.
+    pub fn swap(&self, db: LocationsDb) -> u64 {
+        let db = Arc::new(db);
+        let (number, previous) = {
+            let mut current = self.current.write().expect("cannot aquire lock");
+            current.number += 1;
+            (current.number, std::mem::replace(&mut current.db, db))
+        };
+        drop(previous);
+        number
+    }
+    /// Builds a database on a background thread, such as with
+    /// `parse_data_files`, and swaps it in if the build succeeds. The current database serves searches meanwhile.
+    pub fn reload_in_background<F>(self: &Arc<Self>, build: F) -> JoinHandle<Result<u64, String>>
+    where
+        F: FnOnce() -> Result<LocationsDb, Box<dyn Error>> + Send + 'static,
+    {
+        let reloadable = Arc::clone(self);
+        std::thread::spawn(move || {
+            let db = build().map_err(|e| e.to_string())?;
+            Ok(reloadable.swap(db))
+        })
+    }
+}
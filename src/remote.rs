@@ -0,0 +1,81 @@
+//! Loading the data files from an object store, enabled by the
+//! `object-store` feature, so that containerised services can pull their
+//! datasets from S3, GCS, Azure or HTTP at startup rather than baking them
+//! into images.
+
+use std::error::Error;
+
+use csv::ReaderBuilder;
+use object_store::parse_url_opts;
+use rayon::iter::IntoParallelIterator;
+use url::Url;
+
+use crate::async_api::AsyncResult;
+use crate::config::LoadOptions;
+use crate::location::CsvWeight;
+use crate::locations_db::{
+    apply_load_options, decode_json, link_related, parse_csv_reader, parse_data_blocks_into,
+    parse_redirects_reader, parse_weights, LocationsDb, CODE_LIST_FILE, DATA_FILES, REDIRECTS_FILE,
+    WEIGHTS_FILE,
+};
+
+/// Loads and indexes a data directory laid out as for `parse_data_files`
+/// under a URL such as `s3://bucket/berlin/` or `gs://bucket/berlin/`.
+/// Credentials and regions are read from the environment, as
+/// `AWS_ACCESS_KEY_ID` or `GOOGLE_SERVICE_ACCOUNT`.
+pub async fn load_from_url(url: &str, options: LoadOptions) -> AsyncResult<LocationsDb> {
+    let url = Url::parse(url)?;
+    let env = std::env::vars().map(|(k, v)| (k.to_ascii_lowercase(), v));
+    let (store, prefix) = parse_url_opts(&url, env)?;
+    let fetch = |file: &str| {
+        let (store, path) = (&store, prefix.child(file));
+        async move { store.get(&path).await?.bytes().await }
+    };
+    let mut blocks = vec![];
+    for file in DATA_FILES {
+        blocks.push((file.to_string(), fetch(file).await?.to_vec()));
+    }
+    let code_list = fetch(CODE_LIST_FILE).await?.to_vec();
+    let optional = |file| async move {
+        match fetch(file).await {
+            Ok(bytes) => Ok(Some(bytes.to_vec())),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(err) => Err(err),
+        }
+    };
+    let redirects = optional(REDIRECTS_FILE).await?;
+    let weights = optional(WEIGHTS_FILE).await?;
+    tokio::task::spawn_blocking(move || {
+        let sidecars = (redirects.as_deref(), weights.as_deref());
+        build(blocks, &code_list, sidecars, &options).map_err(|e| e.to_string())
+    })
+    .await?
+    .map_err(|e| e.into())
+}
+
+fn build(
+    blocks: Vec<(String, Vec<u8>)>,
+    code_list: &[u8],
+    (redirects, weights): (Option<&[u8]>, Option<&[u8]>),
+    options: &LoadOptions,
+) -> Result<LocationsDb, Box<dyn Error>> {
+    let blocks = blocks
+        .into_iter()
+        .map(|(file, mut bytes)| decode_json(&mut bytes).map(|json| (file, json)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let db = LocationsDb::with_options(options);
+    let db = parse_data_blocks_into(db, blocks.into_par_iter(), None)?;
+    let db = link_related(parse_csv_reader(db, code_list)?);
+    let mut db = apply_load_options(db, options);
+    if let Some(weights) = weights {
+        let mut csv_reader = ReaderBuilder::new().from_reader(weights);
+        let weights = csv_reader
+            .deserialize::<CsvWeight>()
+            .collect::<Result<Vec<_>, _>>()?;
+        db = parse_weights(db, weights.into_iter());
+    }
+    if let Some(redirects) = redirects {
+        db = parse_redirects_reader(db, redirects)?;
+    }
+    Ok(db.mk_fst())
+}
@@ -0,0 +1,30 @@
+//! JSON Schemas of the query and result documents, for generating client
+//! models in other languages.
+
+use schemars::gen::SchemaGenerator;
+use serde_json::{json, Value};
+
+use crate::bulk::NdjsonQuery;
+use crate::explain::{Explanation, FeatureResult, ScoreHistogram};
+use crate::locations_db::{Annotation, DetailedResult};
+use crate::search::ParsedQuery;
+
+/// A JSON Schema (draft 7) defining the query and result documents under
+/// `definitions`, by type name: `NdjsonQuery` for queries, then
+/// `DetailedResult`, `Explanation`, `FeatureResult`, `Annotation`,
+/// `ParsedQuery` and `ScoreHistogram`, and the types they use.
+pub fn schema() -> Value {
+    let mut generator = SchemaGenerator::default();
+    generator.subschema_for::<NdjsonQuery>();
+    generator.subschema_for::<DetailedResult>();
+    generator.subschema_for::<Explanation>();
+    generator.subschema_for::<FeatureResult>();
+    generator.subschema_for::<Annotation>();
+    generator.subschema_for::<ParsedQuery>();
+    generator.subschema_for::<ScoreHistogram>();
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "berlin",
+        "definitions": generator.take_definitions(),
+    })
+}
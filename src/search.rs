@@ -1,58 +1,164 @@
-use std::cmp::Ordering;
+use std::cmp::{Ordering, Reverse};
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
 
 use schemars::JsonSchema;
 use serde::Serialize;
-use strsim::normalized_levenshtein as similarity_algo;
 use unicode_segmentation::UnicodeSegmentation;
 use ustr::{Ustr, UstrSet};
 
-use crate::LEV_LENGTH_MAX;
-use crate::SCORE_SOFT_MAX;
+use crate::config::{LevBreakpoints, SearchConfig};
+use crate::filters::FilterChain;
+use crate::location::Location;
+use crate::profile::SearchProfile;
+use crate::similarity::{Levenshtein, Similarity};
+use crate::{MAX_CODE_LENGTH, MIN_TOKEN_LENGTH, SCORE_SOFT_MAX};
 
-const STOP_WORDS: [&str; 18] = [
+pub(crate) const STOP_WORDS: [&str; 18] = [
     "any", "all", "are", "is", "at", "to", "in", "on", "of", "for", "by", "and", "was", "did",
     "the", "city", "that", "with",
 ];
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SearchTerm {
     pub raw: String,
     pub normalized: String,
     pub codes: Vec<MatchDef<Ustr>>,
     pub matches: SearchableStringSet,
     pub state_filter: Option<Ustr>,
+    // results must carry one of these tags, if any are given, and all of
+    // `tags_all`
+    pub tags_any: Vec<Ustr>,
+    pub tags_all: Vec<Ustr>,
+    // words or phrases which, found among a result's names, lower its score
+    // by `negative_penalty`, or rule it out if there is no penalty
+    pub negative_terms: Vec<String>,
+    pub negative_penalty: Option<i64>,
+    // the kinds of result kept and preferred, as set by `with_profile`
+    pub profile: Option<SearchProfile>,
+    // the most results returned, or 0 for all of them
     pub limit: usize,
     pub lev_dist: u32,
+    pub config: SearchConfig,
+    // the words that are searched for, without stop words
+    pub tokens: Vec<Offset>,
+    // stop words and stop phrases, which are not searched for alone
+    pub stop_spans: Vec<Offset>,
+    // the terms results must and must not match, if the query has
+    // boolean operators
+    pub boolean: Option<BooleanQuery>,
+    // the further filters the query was normalized with, those of the
+    // database it is searched in; see `LocationsDb::prepare`
+    pub filters: FilterChain,
+    // the span of the raw query each byte of the normalized one came from
+    pub raw_spans: Vec<Range<usize>>,
 }
 
-#[derive(Debug)]
+/// The terms of a query with boolean operators, the uppercase words `AND`,
+/// `OR` and `NOT` between terms. `AND` binds tighter than `OR`, and `NOT`
+/// excludes the term after it, so that "paris NOT texas" finds the places
+/// named Paris outside Texas. Terms are normalized like the query.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BooleanQuery {
+    // alternatives, each of terms that must all be matched
+    pub any_of: Vec<Vec<String>>,
+    // terms none of which may be matched
+    pub none_of: Vec<String>,
+}
+
+const OPERATORS: [&str; 3] = ["AND", "OR", "NOT"];
+
+/// How a query was interpreted, as returned by `SearchTerm::parsed`, for
+/// API layers to echo back.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ParsedQuery {
+    pub normalized: String,
+    // LOCODEs written with their country, as "gb:abc"
+    pub codes: Vec<ParsedTerm>,
+    // words and phrases known to the index, matched only exactly
+    pub exact: Vec<ParsedTerm>,
+    // words and phrases also matched approximately
+    pub fuzzy: Vec<ParsedTerm>,
+    pub stop_words: Vec<ParsedTerm>,
+    pub state_filter: Option<String>,
+}
+
+/// A query term that `SearchConfig::lev_breakpoints` narrowed fuzzy
+/// matching of, with the distance it was searched at, 0 if it was too long
+/// to be matched fuzzily.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct LevClamp {
+    pub term: String,
+    pub offset: Offset,
+    pub distance: u32,
+}
+
+/// A term of a parsed query and the span of the normalized query it
+/// stands for. Respellings, such as "saint etienne" for "saint-etienne",
+/// differ from the text of their span.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ParsedTerm {
+    pub term: String,
+    pub offset: Offset,
+}
+
+#[derive(Debug, Clone)]
 pub struct SearchableStringSet {
     pub stop_words: Vec<Ustr>,
     exact: Vec<MatchDef<Ustr>>,
     not_exact: Vec<MatchDef<String>>,
+    // terms too long to match fuzzily, and unknown to the index
+    too_long: Vec<MatchDef<String>>,
+    lev_length_max: usize,
+    min_token_length: usize,
+    max_code_length: usize,
+}
+
+/// A term that matches none of the words of the query.
+#[derive(Debug)]
+pub struct TermNotInQuery(pub String);
+
+impl fmt::Display for TermNotInQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "term not in query: {}", self.0)
+    }
 }
 
+impl Error for TermNotInQuery {}
+
 impl SearchTerm {
-    pub fn add_code(&mut self, u: Ustr) {
-        let str = u.as_str();
-        let start = self.normalized.find(str).unwrap();
-        self.codes.push(MatchDef {
-            term: u,
-            offset: Offset {
-                start,
-                end: start + str.len(),
-            },
-        })
+    /// Adds a code spanning one or more consecutive words of the query,
+    /// at the offset of the first such span.
+    pub fn add_code(&mut self, u: Ustr) -> Result<(), TermNotInQuery> {
+        let offset = self
+            .tokens
+            .iter()
+            .enumerate()
+            .flat_map(|(i, first)| {
+                self.tokens[i..].iter().map(|last| Offset {
+                    start: first.start,
+                    end: last.end,
+                })
+            })
+            .find(|o| self.normalized.get(o.start..o.end) == Some(u.as_str()))
+            .ok_or_else(|| TermNotInQuery(u.to_string()))?;
+        self.codes.push(MatchDef { term: u, offset });
+        Ok(())
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct MatchDef<T> {
     pub term: T,
     pub offset: Offset,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, JsonSchema, Serialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 pub struct Offset {
     pub start: usize,
     pub end: usize,
@@ -60,7 +166,7 @@ pub struct Offset {
 
 impl PartialOrd for Offset {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.start.partial_cmp(&other.start)
+        Some(self.cmp(other))
     }
 }
 
@@ -74,6 +180,7 @@ impl Ord for Offset {
 }
 
 #[derive(PartialEq, Eq, Copy, Clone, JsonSchema, Serialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 pub struct Score {
     pub score: i64,
     pub offset: Offset,
@@ -81,31 +188,119 @@ pub struct Score {
 
 impl PartialOrd for Score {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.score.cmp(&other.score))
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for Score {
     fn cmp(&self, other: &Self) -> Ordering {
-        match self.partial_cmp(other) {
-            None => Ordering::Equal,
-            Some(ord) => match ord {
-                Ordering::Equal => self.offset.cmp(&other.offset),
-                ord => ord,
-            },
+        match self.score.cmp(&other.score) {
+            Ordering::Equal => self.offset.cmp(&other.offset),
+            ord => ord,
+        }
+    }
+}
+
+impl BooleanQuery {
+    /// Whether a location matching the terms `mentions` accepts meets the
+    /// query.
+    pub fn admits(&self, mentions: impl Fn(&str) -> bool) -> bool {
+        let any = self.any_of.is_empty()
+            || self
+                .any_of
+                .iter()
+                .any(|all| all.iter().all(|t| mentions(t)));
+        any && !self.none_of.iter().any(|t| mentions(t))
+    }
+    // Parses the operators of the raw query, returning the query and the
+    // positions among the words of the normalized query of the operators
+    // and the excluded terms, which are not searched for. Each word of the
+    // raw query is normalized alone to find the words it became.
+    fn parse(
+        raw: &str,
+        words: &[(usize, &str)],
+        normalize: impl Fn(&str) -> String,
+    ) -> Option<(Self, Vec<usize>)> {
+        let tokens = raw.split_whitespace().collect::<Vec<_>>();
+        if !tokens.iter().any(|t| OPERATORS.contains(t)) {
+            return None;
+        }
+        let mut spans = vec![];
+        for token in &tokens {
+            let start = spans.last().map_or(0, |s: &Range<usize>| s.end);
+            spans.push(start..start + normalize(token).unicode_words().count());
+        }
+        if spans.last().map(|s| s.end) != Some(words.len()) {
+            return None;
+        }
+        // the words of each term, and the operators before it
+        let mut clauses: Vec<(Range<usize>, Vec<&str>)> = vec![];
+        let mut operators = vec![];
+        let mut skipped = vec![];
+        for (token, span) in tokens.iter().zip(spans) {
+            match OPERATORS.contains(token) {
+                true => {
+                    operators.push(*token);
+                    skipped.extend(span);
+                }
+                false => match clauses.last_mut() {
+                    Some((words, _)) if operators.is_empty() => words.end = span.end,
+                    _ => clauses.push((span, std::mem::take(&mut operators))),
+                },
+            }
+        }
+        let mut query = BooleanQuery::default();
+        for (span, operators) in clauses.into_iter().filter(|(s, _)| !s.is_empty()) {
+            let term = words[span.clone()]
+                .iter()
+                .map(|(_, w)| *w)
+                .collect::<Vec<_>>()
+                .join(" ");
+            match query.any_of.last_mut() {
+                _ if operators.contains(&"NOT") => {
+                    query.none_of.push(term);
+                    skipped.extend(span);
+                }
+                Some(all) if !operators.contains(&"OR") => all.push(term),
+                _ => query.any_of.push(vec![term]),
+            }
         }
+        skipped.sort_unstable();
+        Some((query, skipped))
     }
 }
 
+/// The sort key of ranked results: best score first, then by key, so that
+/// results scoring the same come out in the same order on every run. Keys
+/// are compared as strings, as `Ustr`'s own ordering varies between runs.
+pub fn rank_order(&(key, score): &(Ustr, Score)) -> (Reverse<Score>, &'static str) {
+    (Reverse(score), key.as_str())
+}
+
 impl SearchableStringSet {
+    /// Terms matched only exactly.
+    pub fn exact_terms(&self) -> impl Iterator<Item = &MatchDef<Ustr>> {
+        self.exact.iter()
+    }
+    /// Terms matched approximately as well as exactly.
+    pub fn fuzzy_terms(&self) -> impl Iterator<Item = &MatchDef<String>> {
+        self.not_exact.iter()
+    }
     pub fn new(stop_words: Vec<Ustr>) -> SearchableStringSet {
         SearchableStringSet {
-            stop_words: stop_words,
+            stop_words,
             exact: vec![],
             not_exact: vec![],
+            too_long: vec![],
+            lev_length_max: LevBreakpoints::default().lev_length_max,
+            min_token_length: MIN_TOKEN_LENGTH,
+            max_code_length: MAX_CODE_LENGTH,
         }
     }
     pub fn match_str(&self, subject: &str) -> Option<Score> {
+        self.match_str_with(subject, &Levenshtein)
+    }
+    pub fn match_str_with(&self, subject: &str, similarity: &dyn Similarity) -> Option<Score> {
         let exact = self
             .exact
             .iter()
@@ -123,12 +318,15 @@ impl SearchableStringSet {
                 .not_exact
                 .iter()
                 .map(|w| {
-                    let score = if w.term.len() > 3 && subject.starts_with(&w.term) {
+                    let score = if w.term.len() > self.max_code_length
+                        && subject.starts_with(&w.term)
+                    {
                         SCORE_SOFT_MAX + (2 * w.term.len() as i64)
                     } else {
                         match w.term.len() > subject.len() - 2 && w.term.len() < subject.len() + 2 {
                             true => {
-                                (similarity_algo(subject, &w.term) * SCORE_SOFT_MAX as f64) as i64
+                                let similarity = similarity.similarity(subject, &w.term);
+                                (similarity * SCORE_SOFT_MAX as f64) as i64
                             }
                             false => 0,
                         }
@@ -144,9 +342,9 @@ impl SearchableStringSet {
     pub fn build_search<'c>(
         &'c self,
         mut op: fst::map::OpBuilder<'c>,
-        mut search_action: impl FnMut(fst::map::OpBuilder<'c>, &'c str) -> fst::map::OpBuilder<'c>,
+        search_action: impl FnMut(fst::map::OpBuilder<'c>, &'c str) -> fst::map::OpBuilder<'c>,
         mut grab_action: impl FnMut(&'c Ustr) -> Option<&UstrSet>,
-    ) -> (fst::map::OpBuilder, UstrSet) {
+    ) -> (fst::map::OpBuilder<'c>, UstrSet) {
         let mut pre_filtered: UstrSet = UstrSet::default();
         let ungrabbed = {
             self.exact
@@ -164,69 +362,238 @@ impl SearchableStringSet {
             .iter()
             .map(|ne| ne.term.as_str())
             .chain(ungrabbed)
-            .fold(op, |op, t| search_action(op, t));
+            .fold(op, search_action);
         (op, pre_filtered)
     }
 
-    pub fn add(&mut self, matchable: &str, normalized: &String, allow_inexact: bool) {
+    /// Adds a match on the part of the query at `offset`, as found when
+    /// the query was split into words.
+    pub fn add(&mut self, matchable: &str, offset: Offset, allow_inexact: bool) {
         // TODO: do we really want to add inexact matches of <2 chars?
         match Ustr::from_existing(matchable) {
             Some(u) => match matchable.len() {
-                0 | 1 => {}                             // ignore
+                n if n < self.min_token_length => {}    // ignore
                 _ if self.stop_words.contains(&u) => {} // ignore stop words
-                _ => self.add_exact(u, normalized),
+                _ => self.add_exact(u, offset),
             },
-            None if allow_inexact && matchable.chars().count() < LEV_LENGTH_MAX => {
-                self.add_not_exact(matchable.to_string(), normalized)
+            None if allow_inexact && matchable.chars().count() < self.lev_length_max => {
+                self.add_not_exact(matchable.to_string(), offset)
             }
+            None if allow_inexact => self.too_long.push(MatchDef {
+                term: matchable.to_string(),
+                offset,
+            }),
             None => {}
         }
     }
-    fn add_exact(&mut self, u: Ustr, normalized: &String) {
-        let str = u.as_str();
-        let loc = normalized.find(str);
-        match loc {
-            Some(start) => self.exact.push(MatchDef {
-                term: u,
-                offset: Offset {
-                    start,
-                    end: start + str.len(),
-                },
-            }),
-            None => (),
+    /// Adds an exact-only match on a respelling of part of the query,
+    /// which need not appear in the query itself.
+    pub fn add_variant(&mut self, variant: &str, offset: Offset) {
+        match Ustr::from_existing(variant) {
+            Some(u) if variant.len() >= self.min_token_length && !self.stop_words.contains(&u) => {
+                self.exact.push(MatchDef { term: u, offset })
+            }
+            _ => {}
         }
     }
-    fn add_not_exact(&mut self, ne: String, normalized: &String) {
-        let loc = normalized.find(&ne);
-        match loc {
-            Some(start) => self.not_exact.push(MatchDef {
-                offset: Offset {
-                    start,
-                    end: start + ne.len(),
-                },
-                term: ne,
-            }),
-            None => (),
+    fn add_exact(&mut self, u: Ustr, offset: Offset) {
+        self.exact.push(MatchDef { term: u, offset })
+    }
+    fn add_not_exact(&mut self, ne: String, offset: Offset) {
+        self.not_exact.push(MatchDef { term: ne, offset })
+    }
+}
+
+/// Spells out hyphens as spaces and drops apostrophes, as in
+/// "saint etienne" for "saint-etienne", if there are any.
+pub fn respell_punctuation(s: &str) -> Option<String> {
+    match s.contains(['-', '\'']) {
+        true => Some(s.replace('-', " ").replace('\'', "")),
+        false => None,
+    }
+}
+
+/// Finds LOCODEs written as the official "GB ABC", or as "GBABC" or
+/// "GB:ABC", returning each as a known "gb:abc" code and the range of
+/// word positions it covers.
+fn country_codes(words: &[(usize, &str)]) -> Vec<(Ustr, Range<usize>)> {
+    let is_country = |w: &str| w.len() == 2 && w.chars().all(|c| c.is_ascii_alphabetic());
+    let is_place = |w: &str| w.len() == 3 && w.chars().all(|c| c.is_ascii_alphanumeric());
+    let known = |country: &str, place: &str| Ustr::from_existing(&format!("{country}:{place}"));
+    let mut found = vec![];
+    let mut i = 0;
+    while i < words.len() {
+        let w = words[i].1;
+        let next = words.get(i + 1).map(|(_, w)| *w);
+        let single = match w.len() {
+            5 if is_country(&w[..2]) && is_place(&w[2..]) => known(&w[..2], &w[2..]),
+            6 if is_country(&w[..2]) && &w[2..3] == ":" && is_place(&w[3..]) => {
+                Ustr::from_existing(w)
+            }
+            _ => None,
+        };
+        let pair = next
+            .filter(|next| is_country(w) && is_place(next))
+            .and_then(|next| known(w, next));
+        match (single, pair) {
+            (Some(code), _) => {
+                found.push((code, i..i + 1));
+                i += 1;
+            }
+            (None, Some(code)) => {
+                found.push((code, i..i + 2));
+                i += 2;
+            }
+            (None, None) => i += 1,
         }
     }
+    found
+}
+
+/// Finds the stop phrases in a query, as ranges of word positions.
+fn stop_phrases(words: &[(usize, &str)], phrases: &[String]) -> Vec<Range<usize>> {
+    let mut found = vec![];
+    let mut i = 0;
+    while i < words.len() {
+        let phrase_len = phrases.iter().find_map(|phrase| {
+            let phrase_words = phrase.split(' ').collect::<Vec<_>>();
+            let candidate = words.iter().skip(i).take(phrase_words.len());
+            let matched = candidate.map(|(_, w)| *w).eq(phrase_words.iter().copied());
+            matched.then_some(phrase_words.len())
+        });
+        match phrase_len {
+            Some(len) => {
+                found.push(i..i + len);
+                i += len;
+            }
+            None => i += 1,
+        }
+    }
+    found
+}
+
+/// Strips a possessive or plural ending from an English word, keeping
+/// the stem as a prefix of the word so that its offset is unchanged.
+fn light_stem(word: &str) -> Option<&str> {
+    ["'s", "s'", "'", "es", "s"]
+        .iter()
+        .find_map(|suffix| word.strip_suffix(suffix))
+        .filter(|stem| stem.len() > 2)
 }
 
 impl SearchTerm {
+    /// The number of results to keep: the limit, or all if it is 0.
+    pub fn max_results(&self) -> usize {
+        match self.limit {
+            0 => usize::MAX,
+            limit => limit,
+        }
+    }
     pub fn from_raw_query(
         raw: String,
         state_filter: Option<String>,
         limit: usize,
         lev_dist: u32,
     ) -> Self {
-        let normalized = crate::normalize(&raw);
+        Self::with_config(raw, state_filter, limit, lev_dist, SearchConfig::default())
+    }
+    pub fn with_config(
+        raw: String,
+        state_filter: Option<String>,
+        limit: usize,
+        lev_dist: u32,
+        config: SearchConfig,
+    ) -> Self {
+        Self::build(
+            raw,
+            state_filter,
+            limit,
+            lev_dist,
+            config,
+            FilterChain::default(),
+        )
+    }
+    /// The term normalized anew with further filters, keeping its other
+    /// settings. Codes are found again in the new normalized query, and
+    /// dropped only where the filters leave no such words.
+    pub fn with_filters(&self, filters: &FilterChain) -> Self {
+        let mut term = Self::build(
+            self.raw.clone(),
+            None,
+            self.limit,
+            self.lev_dist,
+            self.config.clone(),
+            filters.clone(),
+        );
+        for code in &self.codes {
+            if !term.codes.iter().any(|c| c.term == code.term) {
+                let _ = term.add_code(code.term);
+            }
+        }
+        SearchTerm {
+            state_filter: self.state_filter,
+            tags_any: self.tags_any.clone(),
+            tags_all: self.tags_all.clone(),
+            negative_terms: self.negative_terms.clone(),
+            negative_penalty: self.negative_penalty,
+            profile: self.profile,
+            ..term
+        }
+    }
+    fn build(
+        raw: String,
+        state_filter: Option<String>,
+        limit: usize,
+        lev_dist: u32,
+        config: SearchConfig,
+        filters: FilterChain,
+    ) -> Self {
+        let normalize = |s: &str| {
+            let normalized = match config.case_locale {
+                Some(locale) => crate::normalize_with(s, locale),
+                None => crate::normalize(s),
+            };
+            match filters.is_empty() {
+                true => normalized,
+                false => filters.apply(&normalized, None),
+            }
+        };
+        let (normalized, raw_spans) = FilterChain::standard()
+            .then(&filters)
+            .apply_spans(&raw, config.case_locale);
         let split_words: Vec<&str> = normalized.unicode_words().collect();
-        let split_indices: Vec<(usize, usize, &str)> = normalized
-            .unicode_word_indices()
-            .collect::<Vec<(usize, &str)>>()
+        let word_indices = normalized.unicode_word_indices().collect::<Vec<_>>();
+        let phrases = stop_phrases(&word_indices, &config.stop_phrases);
+        let (boolean, skipped) = BooleanQuery::parse(&raw, &word_indices, normalize)
+            .map_or((None, vec![]), |(query, skipped)| (Some(query), skipped));
+        let mut codes = country_codes(&word_indices);
+        codes.retain(|c| !c.1.clone().any(|i| skipped.contains(&i)));
+        let split_indices: Vec<(usize, usize, &str)> = word_indices
             .iter()
-            .map(|(n, w)| (*n, n + w.graphemes(true).count(), *w))
+            .enumerate()
+            .filter(|(i, _)| {
+                !phrases
+                    .iter()
+                    .chain(codes.iter().map(|c| &c.1))
+                    .any(|p| p.contains(i))
+                    && !skipped.contains(i)
+            })
+            .map(|(_, (n, w))| (*n, n + w.len(), *w))
             .filter(|(_, _, w)| !STOP_WORDS.contains(w))
             .collect();
+        let stop_spans = word_indices
+            .iter()
+            .enumerate()
+            .filter(|(i, (_, w))| {
+                STOP_WORDS.contains(w) && !codes.iter().any(|c| c.1.contains(i))
+                    || phrases.iter().any(|p| p.contains(i))
+                    || skipped.contains(i)
+            })
+            .map(|(_, (n, w))| Offset {
+                start: *n,
+                end: n + w.len(),
+            })
+            .collect();
         let stop_words: Vec<Ustr> = split_words
             .iter()
             .filter_map(|w| Ustr::from_existing(w).filter(|w| STOP_WORDS.contains(&w.as_str())))
@@ -235,26 +602,216 @@ impl SearchTerm {
             raw,
             normalized: normalized.clone(),
             state_filter: state_filter.and_then(|s| Ustr::from_existing(&s)),
+            tags_any: vec![],
+            tags_all: vec![],
+            negative_terms: vec![],
+            negative_penalty: None,
+            profile: None,
             lev_dist,
             limit,
+            config,
             codes: vec![],
             matches: SearchableStringSet::new(stop_words.clone()),
+            tokens: split_indices
+                .iter()
+                .map(|(start, end, _)| Offset {
+                    start: *start,
+                    end: *end,
+                })
+                .collect(),
+            stop_spans,
+            boolean,
+            filters,
+            raw_spans,
         };
-        // info!("Split words: {:?}", split_words);
-        let graphemes: Vec<&str> = normalized.graphemes(true).collect();
-        for (i, (n, _, w)) in split_indices.iter().enumerate() {
-            if split_indices.len() > i + 1 {
-                let doublet: String = graphemes[*n..split_indices[i + 1].1].join("");
-                st.matches.add(&doublet, &st.normalized, true);
-                if split_indices.len() > i + 2 {
-                    let triplet = graphemes[*n..split_indices[i + 2].1].join("");
-                    st.matches.add(&triplet, &st.normalized, false);
+        st.matches.lev_length_max = st.config.lev_breakpoints.lev_length_max;
+        st.matches.min_token_length = st.config.min_token_length;
+        st.matches.max_code_length = st.config.max_code_length;
+        for (term, words) in codes {
+            let (start, _) = word_indices[words.start];
+            let (n, w) = word_indices[words.end - 1];
+            let offset = Offset {
+                start,
+                end: n + w.len(),
+            };
+            st.codes.push(MatchDef { term, offset });
+        }
+        let mut ngrams = st.config.max_ngrams.unwrap_or(usize::MAX);
+        for (i, (n, e, w)) in split_indices.iter().enumerate() {
+            if st.config.doublets && ngrams > 0 && split_indices.len() > i + 1 {
+                ngrams -= 1;
+                let (_, end, next) = split_indices[i + 1];
+                let doublet = &normalized[*n..end];
+                let offset = Offset { start: *n, end };
+                st.matches.add(doublet, offset, true);
+                // in case the name is a compound, as in "badhersfeld"
+                st.matches.add_variant(&format!("{w}{next}"), offset);
+                if let Some(variant) = respell_punctuation(doublet) {
+                    st.matches.add_variant(&variant, offset);
                 }
             }
-            st.matches.add(w, &st.normalized, true)
+            if st.config.triplets && ngrams > 0 && split_indices.len() > i + 2 {
+                ngrams -= 1;
+                let end = split_indices[i + 2].1;
+                let triplet = &normalized[*n..end];
+                st.matches.add(triplet, Offset { start: *n, end }, false);
+            }
+            let offset = Offset { start: *n, end: *e };
+            st.matches.add(w, offset, true);
+            if let Some(variant) = respell_punctuation(w) {
+                st.matches.add_variant(&variant, offset);
+            }
+            if st.config.stem && Ustr::from_existing(w).is_none() {
+                if let Some(stem) = light_stem(w) {
+                    let offset = Offset {
+                        start: *n,
+                        end: n + stem.len(),
+                    };
+                    st.matches.add(stem, offset, false)
+                }
+            }
+        }
+        // A stop phrase may still begin a name, as in "isle of wight", so
+        // it is kept in an exact-only match along with the following word.
+        for phrase in phrases {
+            if let Some((n, w)) = word_indices.get(phrase.end) {
+                let start = word_indices[phrase.start].0;
+                let end = n + w.len();
+                st.matches
+                    .add(&normalized[start..end], Offset { start, end }, false);
+            }
         }
         st
     }
+    /// The interpretation of the query: the codes, exact and fuzzy terms
+    /// and stop words found in it.
+    pub fn parsed(&self) -> ParsedQuery {
+        let term = |term: &str, offset: Offset| ParsedTerm {
+            term: term.to_string(),
+            offset,
+        };
+        ParsedQuery {
+            normalized: self.normalized.clone(),
+            codes: self.codes.iter().map(|c| term(&c.term, c.offset)).collect(),
+            exact: self
+                .matches
+                .exact_terms()
+                .map(|m| term(&m.term, m.offset))
+                .collect(),
+            fuzzy: self
+                .matches
+                .fuzzy_terms()
+                .map(|m| term(&m.term, m.offset))
+                .collect(),
+            stop_words: self
+                .stop_spans
+                .iter()
+                .map(|o| term(self.normalized.get(o.start..o.end).unwrap_or_default(), *o))
+                .collect(),
+            state_filter: self.state_filter.map(|s| s.to_string()),
+        }
+    }
+    /// The fuzzy terms searched for at less than the query's Levenshtein
+    /// distance, or not fuzzily at all, because of their length.
+    pub fn lev_clamps(&self) -> Vec<LevClamp> {
+        let breakpoints = &self.config.lev_breakpoints;
+        let narrowed = self
+            .matches
+            .fuzzy_terms()
+            .filter(|m| m.term.len() > self.config.max_code_length)
+            .map(|m| LevClamp {
+                term: m.term.clone(),
+                offset: m.offset,
+                distance: breakpoints.distance(m.term.chars().count(), self.lev_dist),
+            });
+        let exact_only = self.matches.too_long.iter().map(|m| LevClamp {
+            term: m.term.clone(),
+            offset: m.offset,
+            distance: 0,
+        });
+        let mut clamps = narrowed
+            .chain(exact_only)
+            .filter(|c| c.distance < self.lev_dist)
+            .collect::<Vec<_>>();
+        clamps.sort_by_key(|c| (c.offset.start, c.offset.end));
+        clamps
+    }
+    /// Restricts and boosts results for a use, such as shipping, adjusting
+    /// the config to suit it.
+    pub fn with_profile(mut self, profile: SearchProfile) -> Self {
+        profile.configure(&mut self.config);
+        self.profile = Some(profile);
+        self
+    }
+    /// Whether the profile, if any, keeps the result.
+    pub fn profile_admits(&self, loc: &Location) -> bool {
+        self.profile.is_none_or(|p| p.admits(loc))
+    }
+    /// Keeps only results carrying at least one of the tags.
+    pub fn with_tags_any<T: AsRef<str>>(mut self, tags: impl IntoIterator<Item = T>) -> Self {
+        self.tags_any = tags
+            .into_iter()
+            .map(|t| crate::normalize(t.as_ref()).into())
+            .collect();
+        self
+    }
+    /// Keeps only results carrying all of the tags.
+    pub fn with_tags_all<T: AsRef<str>>(mut self, tags: impl IntoIterator<Item = T>) -> Self {
+        self.tags_all = tags
+            .into_iter()
+            .map(|t| crate::normalize(t.as_ref()).into())
+            .collect();
+        self
+    }
+    /// Lowers the score of results with any of the terms among their names
+    /// by `penalty`, or excludes them if it is `None`. Terms are normalized
+    /// like the query and match whole words, so that "airport" catches
+    /// "heathrow airport" but not "airports".
+    pub fn with_negative_terms<T: AsRef<str>>(
+        mut self,
+        terms: impl IntoIterator<Item = T>,
+        penalty: Option<i64>,
+    ) -> Self {
+        self.negative_terms = terms
+            .into_iter()
+            .map(|t| crate::normalize(t.as_ref()))
+            .filter(|t| !t.is_empty())
+            .collect();
+        self.negative_penalty = penalty;
+        self
+    }
+    /// Whether any of the negative terms is among the names.
+    pub fn negative_match<'a>(&self, names: impl IntoIterator<Item = &'a str>) -> bool {
+        names.into_iter().any(|name| {
+            let name = format!(" {name} ");
+            self.negative_terms
+                .iter()
+                .any(|t| name.contains(&format!(" {t} ")))
+        })
+    }
+    pub fn tags_match(&self, tags: &[Ustr]) -> bool {
+        (self.tags_any.is_empty() || self.tags_any.iter().any(|t| tags.contains(t)))
+            && self.tags_all.iter().all(|t| tags.contains(t))
+    }
+    /// The span of the raw query that a span of the normalized query was
+    /// normalized from.
+    pub fn raw_offset(&self, offset: Offset) -> Offset {
+        let end = offset.end.min(self.raw_spans.len());
+        match self.raw_spans.get(offset.start..end) {
+            Some([first, .., last]) => Offset {
+                start: first.start,
+                end: last.end,
+            },
+            Some([only]) => Offset {
+                start: only.start,
+                end: only.end,
+            },
+            _ => Offset {
+                start: self.raw.len(),
+                end: self.raw.len(),
+            },
+        }
+    }
     pub fn codes_match(&self, subject_codes: &[Ustr], score: i64) -> Option<Score> {
         let res: Option<Score> = subject_codes
             .iter()
@@ -271,14 +828,78 @@ impl SearchTerm {
         res
     }
     pub fn match_str(&self, subject: &str) -> Option<Score> {
-        self.matches.match_str(subject)
+        self.matches
+            .match_str_with(subject, self.config.similarity.as_ref())
+    }
+    /// Applies the multiplier the config gives the location's country.
+    pub fn country_boosted(&self, loc: &Location, score: Score) -> Score {
+        match self.config.country_boosts.get(&loc.get_state()) {
+            Some(multiplier) => Score {
+                score: (score.score as f64 * multiplier) as i64,
+                ..score
+            },
+            None => score,
+        }
+    }
+    /// The fraction of the query's tokens within a match.
+    pub fn coverage(&self, offset: Offset) -> f64 {
+        match self.tokens.is_empty() {
+            true => 1.,
+            false => {
+                let covered = self
+                    .tokens
+                    .iter()
+                    .filter(|t| t.start >= offset.start && t.end <= offset.end)
+                    .count();
+                covered as f64 / self.tokens.len() as f64
+            }
+        }
+    }
+    /// The spans of the normalized query outside all of the given matches,
+    /// such as the results of a search, trimmed of whitespace.
+    pub fn remainder(&self, matches: impl IntoIterator<Item = Offset>) -> Vec<Offset> {
+        let mut matches = matches.into_iter().collect::<Vec<_>>();
+        matches.sort_unstable();
+        let mut gaps = vec![];
+        let mut start = 0;
+        for m in matches.into_iter().chain([Offset {
+            start: self.normalized.len(),
+            end: self.normalized.len(),
+        }]) {
+            if m.start > start {
+                gaps.push(Offset {
+                    start,
+                    end: m.start,
+                });
+            }
+            start = start.max(m.end);
+        }
+        gaps.into_iter()
+            .filter_map(|gap| {
+                let text = &self.normalized[gap.start..gap.end];
+                let trimmed = text.trim_start();
+                let start = gap.start + text.len() - trimmed.len();
+                let end = start + trimmed.trim_end().len();
+                (end > start).then_some(Offset { start, end })
+            })
+            .collect()
+    }
+    /// The number of words and word pairs the query is matched on.
+    pub fn term_count(&self) -> usize {
+        self.matches.exact.len() + self.matches.not_exact.len()
     }
     pub fn build_search<'c>(
         &'c self,
         op: fst::map::OpBuilder<'c>,
         search_action: impl FnMut(fst::map::OpBuilder<'c>, &'c str) -> fst::map::OpBuilder<'c>,
-        grab_action: impl FnMut(&'c Ustr) -> Option<&UstrSet>,
-    ) -> (fst::map::OpBuilder, UstrSet) {
-        self.matches.build_search(op, search_action, grab_action)
+        mut grab_action: impl FnMut(&'c Ustr) -> Option<&UstrSet>,
+    ) -> (fst::map::OpBuilder<'c>, UstrSet) {
+        let (op, mut pre_filtered) = self
+            .matches
+            .build_search(op, search_action, &mut grab_action);
+        for code in &self.codes {
+            pre_filtered.extend(grab_action(&code.term).into_iter().flatten());
+        }
+        (op, pre_filtered)
     }
 }
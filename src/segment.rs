@@ -0,0 +1,78 @@
+use std::rc::Rc;
+
+use fst::Automaton;
+use ustr::{Ustr, UstrMap, UstrSet};
+
+/// One searchable slice of the word index. The FST maps each word to its
+/// position in `words`, which holds the locations carrying that word.
+///
+/// The base dataset lives in a single segment; runtime additions are
+/// appended as further segments and merged back by compaction.
+#[derive(Default)]
+pub struct FstSegment {
+    pub fst: fst::Map<Vec<u8>>,
+    pub words: Vec<(Ustr, UstrSet)>,
+}
+
+impl FstSegment {
+    pub fn from_words(words_map: &UstrMap<UstrSet>) -> Self {
+        let mut words = words_map
+            .iter()
+            .map(|(k, v)| (*k, v.clone()))
+            .collect::<Vec<_>>();
+        words.sort_unstable_by_key(|a| a.0.as_str());
+        let fst = fst::Map::from_iter(
+            words
+                .iter()
+                .enumerate()
+                .map(|(i, (word, _))| (word.as_str(), i as u64)),
+        )
+        .expect("Build FST");
+        FstSegment { fst, words }
+    }
+    pub fn locations(&self, value: u64) -> Option<&UstrSet> {
+        self.words.get(value as usize).map(|(_, locs)| locs)
+    }
+}
+
+/// A merged replacement for the first `replaced` segments of a database,
+/// built from a read-only view so it can be prepared off the search path.
+pub struct Compaction {
+    pub segment: FstSegment,
+    pub replaced: usize,
+    // the tombstones the merged segment no longer needs
+    pub tombstones: UstrSet,
+}
+
+/// Shares one (expensive to build) automaton between the searches of
+/// several segments.
+pub(crate) struct SharedAutomaton<A>(pub(crate) Rc<A>);
+
+impl<A> Clone for SharedAutomaton<A> {
+    fn clone(&self) -> Self {
+        SharedAutomaton(self.0.clone())
+    }
+}
+
+impl<A: Automaton> Automaton for SharedAutomaton<A> {
+    type State = A::State;
+
+    fn start(&self) -> Self::State {
+        self.0.start()
+    }
+    fn is_match(&self, state: &Self::State) -> bool {
+        self.0.is_match(state)
+    }
+    fn can_match(&self, state: &Self::State) -> bool {
+        self.0.can_match(state)
+    }
+    fn will_always_match(&self, state: &Self::State) -> bool {
+        self.0.will_always_match(state)
+    }
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        self.0.accept(state, byte)
+    }
+    fn accept_eof(&self, state: &Self::State) -> Option<Self::State> {
+        self.0.accept_eof(state)
+    }
+}
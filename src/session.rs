@@ -0,0 +1,78 @@
+//! Biasing a sequence of queries toward the places matched earlier in it.
+
+use ustr::{Ustr, UstrMap};
+
+use crate::location::LocData;
+use crate::locations_db::LocationsDb;
+use crate::search::{rank_order, Score, SearchTerm};
+
+/// The states and subdivisions matched by recent queries, such as the
+/// earlier questions of a conversation. After "Northern Ireland", a bare
+/// "Newry" prefers the Newry in Northern Ireland.
+pub struct SearchSession {
+    // added to the score of a result within the context, at full weight
+    pub boost: i64,
+    // the weight the context keeps from one query to the next
+    pub decay: f64,
+    // the weights of the states and subdivisions in context, from 0 to 1
+    pub context: UstrMap<f64>,
+}
+
+impl Default for SearchSession {
+    fn default() -> Self {
+        SearchSession {
+            boost: 50,
+            decay: 0.5,
+            context: UstrMap::default(),
+        }
+    }
+}
+
+// weights below this are dropped from the context
+const MIN_WEIGHT: f64 = 0.05;
+
+impl SearchSession {
+    /// Searches, boosting results within the states and subdivisions in
+    /// context, then brings the best result's into context. Every result
+    /// is boosted before the limit is applied.
+    pub fn search(&mut self, db: &LocationsDb, st: &SearchTerm) -> Vec<(Ustr, Score)> {
+        let mut results = db.search(&SearchTerm {
+            limit: 0,
+            ..st.clone()
+        });
+        for (key, score) in results.iter_mut() {
+            score.score += (self.weight(db, *key) * self.boost as f64) as i64;
+        }
+        results.sort_by_key(rank_order);
+        results.truncate(st.max_results());
+        self.context.values_mut().for_each(|w| *w *= self.decay);
+        self.context.retain(|_, w| *w >= MIN_WEIGHT);
+        if let Some((key, _)) = results.first() {
+            self.note(db, *key);
+        }
+        results
+    }
+    /// Brings the state and subdivision of a location, or the location
+    /// itself if it is one, into context at full weight.
+    pub fn note(&mut self, db: &LocationsDb, key: Ustr) {
+        for area in Self::areas(db, key) {
+            self.context.insert(area, 1.);
+        }
+    }
+    pub fn clear(&mut self) {
+        self.context.clear();
+    }
+    fn weight(&self, db: &LocationsDb, key: Ustr) -> f64 {
+        Self::areas(db, key)
+            .filter_map(|area| self.context.get(&area))
+            .fold(0., |a, w| f64::max(a, *w))
+    }
+    fn areas(db: &LocationsDb, key: Ustr) -> impl Iterator<Item = Ustr> {
+        let loc = db.all.get(&key);
+        let own = loc
+            .filter(|l| matches!(l.data, LocData::St(_) | LocData::Subdv(_)))
+            .map(|l| l.key);
+        let (state, subdiv) = loc.map(|l| l.get_parents()).unwrap_or_default();
+        own.into_iter().chain(state).chain(subdiv)
+    }
+}
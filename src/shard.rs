@@ -0,0 +1,74 @@
+//! Partitioning a database by country into shards searched in parallel,
+//! for combined datasets too large to search well through one index.
+
+use std::collections::BTreeMap;
+
+#[cfg(feature = "parallel")]
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use ustr::Ustr;
+
+use crate::key::AsKey;
+use crate::location::Location;
+use crate::locations_db::LocationsDb;
+use crate::search::{rank_order, Score, SearchTerm};
+
+/// Databases by state code, each holding a state and every location in
+/// it, so that hierarchical boosts work within a shard as they would in
+/// the whole database.
+#[derive(Default)]
+pub struct ShardedDb {
+    pub shards: BTreeMap<Ustr, LocationsDb>,
+}
+
+impl ShardedDb {
+    /// Splits a database by state, indexing each shard with the options,
+    /// kinds, namespaces and redirects of the original.
+    pub fn partition(db: LocationsDb) -> Self {
+        let mut shards: BTreeMap<Ustr, LocationsDb> = BTreeMap::new();
+        for (_, loc) in db.all {
+            let shard = shards
+                .entry(loc.get_state())
+                .or_insert_with(|| LocationsDb {
+                    custom_kinds: db.custom_kinds.clone(),
+                    redirects: db.redirects.clone(),
+                    index_options: db.index_options.clone(),
+                    key_namespaces: db.key_namespaces.clone(),
+                    ..Default::default()
+                });
+            shard.insert(loc);
+        }
+        #[cfg(feature = "parallel")]
+        let shards = shards.into_par_iter();
+        #[cfg(not(feature = "parallel"))]
+        let shards = shards.into_iter();
+        ShardedDb {
+            shards: shards.map(|(state, db)| (state, db.mk_fst())).collect(),
+        }
+    }
+    pub fn get<K: AsKey + ?Sized>(&self, key: &K) -> Option<&Location> {
+        let key = key.existing_key()?;
+        self.shards.values().find_map(|db| db.all.get(&key))
+    }
+    pub fn len(&self) -> usize {
+        self.shards.values().map(|db| db.all.len()).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.shards.values().all(|db| db.all.is_empty())
+    }
+    /// Searches the shards in parallel, or only the one for the search
+    /// term's state filter, then ranks their results together.
+    pub fn search(&self, st: &SearchTerm) -> Vec<(Ustr, Score)> {
+        let shards = match st.state_filter {
+            Some(state) => self.shards.get(&state).into_iter().collect::<Vec<_>>(),
+            None => self.shards.values().collect(),
+        };
+        #[cfg(feature = "parallel")]
+        let shards = shards.into_par_iter();
+        #[cfg(not(feature = "parallel"))]
+        let shards = shards.into_iter();
+        let mut results = shards.flat_map(|db| db.search(st)).collect::<Vec<_>>();
+        results.sort_unstable_by_key(rank_order);
+        results.truncate(st.max_results());
+        results
+    }
+}
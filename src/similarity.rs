@@ -0,0 +1,96 @@
+//! String similarity measures used to score inexact matches.
+
+use std::fmt::Debug;
+
+pub trait Similarity: Debug + Send + Sync {
+    /// How alike two strings are, from 0 (unrelated) to 1 (identical).
+    fn similarity(&self, a: &str, b: &str) -> f64;
+}
+
+/// Normalized Levenshtein distance, the default.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Levenshtein;
+
+impl Similarity for Levenshtein {
+    fn similarity(&self, a: &str, b: &str) -> f64 {
+        strsim::normalized_levenshtein(a, b)
+    }
+}
+
+/// Jaro-Winkler similarity, which is kinder to transposed letters and
+/// favours strings sharing a prefix.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct JaroWinkler;
+
+impl Similarity for JaroWinkler {
+    fn similarity(&self, a: &str, b: &str) -> f64 {
+        strsim::jaro_winkler(a, b)
+    }
+}
+
+/// Normalized Levenshtein distance after sorting the words of each string,
+/// so that word order does not matter.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct TokenSortRatio;
+
+impl Similarity for TokenSortRatio {
+    fn similarity(&self, a: &str, b: &str) -> f64 {
+        let sorted = |s: &str| {
+            let mut words = s.split_whitespace().collect::<Vec<_>>();
+            words.sort_unstable();
+            words.join(" ")
+        };
+        strsim::normalized_levenshtein(&sorted(a), &sorted(b))
+    }
+}
+
+const QWERTY_ROWS: [&str; 4] = ["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+// the cost of substituting a key for one beside it
+const ADJACENT_KEY_COST: f64 = 0.5;
+
+/// Normalized Levenshtein distance in which substituting a letter for one
+/// next to it on a QWERTY keyboard costs half an edit, as such typos are
+/// the most likely.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct KeyboardLevenshtein;
+
+impl KeyboardLevenshtein {
+    fn position(c: char) -> Option<(i32, i32)> {
+        QWERTY_ROWS
+            .iter()
+            .enumerate()
+            .find_map(|(row, keys)| keys.find(c).map(|col| (row as i32, col as i32)))
+    }
+    fn substitution_cost(a: char, b: char) -> f64 {
+        match (Self::position(a), Self::position(b)) {
+            _ if a == b => 0.,
+            (Some((ra, ca)), Some((rb, cb))) if (ra - rb).abs() <= 1 && (ca - cb).abs() <= 1 => {
+                ADJACENT_KEY_COST
+            }
+            _ => 1.,
+        }
+    }
+}
+
+impl Similarity for KeyboardLevenshtein {
+    fn similarity(&self, a: &str, b: &str) -> f64 {
+        let a = a.chars().collect::<Vec<_>>();
+        let b = b.chars().collect::<Vec<_>>();
+        let longest = a.len().max(b.len());
+        if longest == 0 {
+            return 1.;
+        }
+        let mut previous = (0..=b.len()).map(|j| j as f64).collect::<Vec<_>>();
+        for (i, ca) in a.iter().enumerate() {
+            let mut current = vec![i as f64 + 1.];
+            for (j, cb) in b.iter().enumerate() {
+                let cost = (previous[j] + Self::substitution_cost(*ca, *cb))
+                    .min(previous[j + 1] + 1.)
+                    .min(current[j] + 1.);
+                current.push(cost);
+            }
+            previous = current;
+        }
+        1. - previous[b.len()] / longest as f64
+    }
+}
@@ -0,0 +1,75 @@
+//! Reporting searches slower than a threshold, with the timings of each
+//! stage, for diagnosing latency spikes in production.
+//!
+//! Nothing is reported until a threshold is set on a database's
+//! `slow_query_log`. Slow searches are then logged as warnings, or passed
+//! to a callback.
+
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::search::SearchTerm;
+
+/// A search that took longer than the threshold.
+#[derive(Debug)]
+pub struct SlowQuery<'a> {
+    pub term: &'a SearchTerm,
+    // found by the pre-filter, and scoring above the inclusion threshold
+    pub candidates: usize,
+    pub scored: usize,
+    pub pre_filter: Duration,
+    pub score: Duration,
+    pub rank: Duration,
+    pub total: Duration,
+}
+
+pub type SlowQueryCallback = Arc<dyn Fn(&SlowQuery) + Send + Sync>;
+
+struct Settings {
+    threshold: Duration,
+    callback: Option<SlowQueryCallback>,
+}
+
+/// Where a database reports its slow searches, which may be changed while
+/// it is searched.
+#[derive(Default)]
+pub struct SlowQueryLog(RwLock<Option<Settings>>);
+
+impl SlowQueryLog {
+    /// Reports each search taking longer than `threshold` to `callback`,
+    /// or as a warning if there is none, replacing any earlier setting.
+    pub fn set(&self, threshold: Duration, callback: Option<SlowQueryCallback>) {
+        *self.0.write().expect("slow query log") = Some(Settings {
+            threshold,
+            callback,
+        });
+    }
+    /// Stops reporting slow searches.
+    pub fn clear(&self) {
+        *self.0.write().expect("slow query log") = None;
+    }
+    pub(crate) fn report(&self, query: &SlowQuery) {
+        // the callback is called after the lock is released, so that it
+        // may change the setting itself
+        let callback = match self.0.read().expect("slow query log").as_ref() {
+            Some(settings) if query.total > settings.threshold => settings.callback.clone(),
+            _ => return,
+        };
+        match callback {
+            Some(callback) => callback(query),
+            None => warn!(
+                query = query.term.raw,
+                state = ?query.term.state_filter,
+                candidates = query.candidates,
+                scored = query.scored,
+                "slow search in {:.2?}: pre-filter {:.2?}, score {:.2?}, rank {:.2?}",
+                query.total,
+                query.pre_filter,
+                query.score,
+                query.rank,
+            ),
+        }
+    }
+}
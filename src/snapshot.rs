@@ -0,0 +1,174 @@
+//! Snapshots of a parsed database, which load without re-reading and
+//! merging the source datasets.
+//!
+//! Snapshots carry the version of their format. Older formats are
+//! migrated forward on reading; newer ones are refused with a
+//! `SnapshotError` rather than misread.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use ustr::{Ustr, UstrMap, UstrSet};
+
+use crate::config::IndexOptions;
+use crate::location::{CustomKind, Location};
+use crate::locations_db::LocationsDb;
+
+/// The version of the format `write_snapshot` writes.
+pub const SNAPSHOT_VERSION: u32 = 5;
+
+// Upgrades a snapshot of the version at its index plus one to the next;
+// snapshots without a version are version 1.
+const MIGRATIONS: [fn(&mut Value); 4] = [
+    // version 2 added the LOCODE status, which version 1 snapshots lack
+    |snapshot| {
+        for loc in locations(snapshot) {
+            if let Some(Value::Object(locode)) = loc.get_mut("data").and_then(|d| d.get_mut("Locd"))
+            {
+                locode.entry("status").or_insert(Value::Null);
+            }
+        }
+    },
+    // version 3 added regions and their members' links to them, of which
+    // older snapshots have none
+    |snapshot| {
+        for loc in locations(snapshot) {
+            loc.entry("regions").or_insert(json!([]));
+        }
+    },
+    // version 4 added the redirects of retired keys
+    |snapshot| insert_missing(snapshot, [("redirects", json!({}))]),
+    // version 5 added the database's custom kinds, key namespaces, index
+    // options and tombstones, which were the defaults before
+    |snapshot| {
+        insert_missing(
+            snapshot,
+            [
+                ("custom_kinds", json!([])),
+                ("key_namespaces", json!({})),
+                ("index_options", json!(IndexOptions::default())),
+                ("tombstones", json!([])),
+            ],
+        )
+    },
+];
+
+// the raw locations of a snapshot
+fn locations(snapshot: &mut Value) -> impl Iterator<Item = &mut Map<String, Value>> {
+    let locations = snapshot.get_mut("locations").and_then(Value::as_array_mut);
+    locations
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object_mut)
+}
+
+fn insert_missing<const N: usize>(snapshot: &mut Value, fields: [(&str, Value); N]) {
+    if let Some(snapshot) = snapshot.as_object_mut() {
+        for (field, value) in fields {
+            snapshot.entry(field).or_insert(value);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    // written by a newer version of the crate
+    UnsupportedVersion(u32),
+    Decode(serde_json::Error),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "snapshot format version {version} is newer than the supported {SNAPSHOT_VERSION}"
+            ),
+            SnapshotError::Decode(err) => write!(f, "cannot decode snapshot: {err}"),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    version: u32,
+    locations: Vec<&'a Location>,
+    redirects: BTreeMap<&'a str, &'a str>,
+    custom_kinds: Vec<&'a CustomKind>,
+    key_namespaces: BTreeMap<&'a str, &'a str>,
+    index_options: &'a IndexOptions,
+    tombstones: BTreeSet<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    locations: Vec<Location>,
+    redirects: UstrMap<Ustr>,
+    custom_kinds: Vec<CustomKind>,
+    key_namespaces: UstrMap<Ustr>,
+    index_options: IndexOptions,
+    tombstones: UstrSet,
+}
+
+pub fn write_snapshot<W: Write>(db: &LocationsDb, writer: W) -> Result<(), Box<dyn Error>> {
+    let mut locations = db.all.values().collect::<Vec<_>>();
+    locations.sort_unstable_by_key(|l| l.key.as_str());
+    let pairs = |map: &'_ UstrMap<Ustr>| {
+        map.iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect::<BTreeMap<_, _>>()
+    };
+    let mut custom_kinds = db.custom_kinds.values().collect::<Vec<_>>();
+    custom_kinds.sort_unstable_by_key(|k| k.encoding.as_str());
+    let snapshot = SnapshotRef {
+        version: SNAPSHOT_VERSION,
+        locations,
+        redirects: pairs(&db.redirects),
+        custom_kinds,
+        key_namespaces: pairs(&db.key_namespaces),
+        index_options: &db.index_options,
+        tombstones: db.tombstones.iter().map(|k| k.as_str()).collect(),
+    };
+    serde_json::to_writer(writer, &snapshot)?;
+    Ok(())
+}
+
+/// Reads a snapshot written by `write_snapshot` into an indexed database,
+/// migrating it from an older format if need be. Errors are
+/// `SnapshotError`s.
+pub fn read_snapshot<R: Read>(reader: R) -> Result<LocationsDb, Box<dyn Error>> {
+    let mut value: Value = serde_json::from_reader(reader).map_err(SnapshotError::Decode)?;
+    let version = match value.get("version") {
+        None => 1,
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .filter(|v| *v > 0)
+            .ok_or_else(|| SnapshotError::Decode(serde::de::Error::custom("invalid version")))?,
+    };
+    if version > SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version).into());
+    }
+    for migrate in &MIGRATIONS[version as usize - 1..] {
+        migrate(&mut value);
+    }
+    let snapshot: Snapshot = serde_json::from_value(value).map_err(SnapshotError::Decode)?;
+    let mut db = LocationsDb::default();
+    for kind in snapshot.custom_kinds {
+        db.register_kind(kind);
+    }
+    db.key_namespaces = snapshot.key_namespaces;
+    db.index_options = snapshot.index_options;
+    for loc in snapshot.locations {
+        db.insert(loc);
+    }
+    db.redirects = snapshot.redirects;
+    db.tombstones = snapshot.tombstones;
+    Ok(db.mk_fst())
+}
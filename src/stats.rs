@@ -0,0 +1,78 @@
+//! Counters of the searches run on a database, cheap enough to leave on in
+//! production and read or reset at runtime, e.g. from an admin endpoint.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// Counters shared between threads, attached to a database through
+/// `LocationsDb::stats`.
+#[derive(Debug, Default)]
+pub struct SearchStats {
+    queries: AtomicU64,
+    candidates: AtomicU64,
+    truncated: AtomicU64,
+    word_index_hits: AtomicU64,
+    word_index_misses: AtomicU64,
+}
+
+/// The counts at a point in time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, JsonSchema)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct SearchStatsSnapshot {
+    pub queries: u64,
+    // found by the pre-filter, per query
+    pub average_candidates: f64,
+    // queries with more results than their limit, which were cut short
+    pub truncated: u64,
+    // known terms resolved from the word index, and those not found in it
+    pub word_index_hits: u64,
+    pub word_index_misses: u64,
+}
+
+impl SearchStats {
+    pub fn snapshot(&self) -> SearchStatsSnapshot {
+        Self::counts([
+            self.queries.load(Ordering::Relaxed),
+            self.candidates.load(Ordering::Relaxed),
+            self.truncated.load(Ordering::Relaxed),
+            self.word_index_hits.load(Ordering::Relaxed),
+            self.word_index_misses.load(Ordering::Relaxed),
+        ])
+    }
+    /// Zeroes the counters, returning their counts until now.
+    pub fn reset(&self) -> SearchStatsSnapshot {
+        Self::counts([
+            self.queries.swap(0, Ordering::Relaxed),
+            self.candidates.swap(0, Ordering::Relaxed),
+            self.truncated.swap(0, Ordering::Relaxed),
+            self.word_index_hits.swap(0, Ordering::Relaxed),
+            self.word_index_misses.swap(0, Ordering::Relaxed),
+        ])
+    }
+    fn counts([queries, candidates, truncated, hits, misses]: [u64; 5]) -> SearchStatsSnapshot {
+        SearchStatsSnapshot {
+            queries,
+            average_candidates: candidates as f64 / queries.max(1) as f64,
+            truncated,
+            word_index_hits: hits,
+            word_index_misses: misses,
+        }
+    }
+    pub(crate) fn record_search(&self, candidates: usize) {
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        self.candidates
+            .fetch_add(candidates as u64, Ordering::Relaxed);
+    }
+    pub(crate) fn record_truncated(&self) {
+        self.truncated.fetch_add(1, Ordering::Relaxed);
+    }
+    pub(crate) fn record_word_lookup(&self, hit: bool) {
+        let counter = match hit {
+            true => &self.word_index_hits,
+            false => &self.word_index_misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,222 @@
+//! Small synthetic databases for integration tests, enabled by the
+//! `test_support` feature, so that crates depending on this one can test
+//! against reproducible data without shipping the real datasets.
+//!
+//! The same `FixtureSpec` always generates the same locations.
+
+use std::collections::HashSet;
+
+#[cfg(feature = "parallel")]
+use rayon::iter::IntoParallelIterator;
+use serde_json::{json, Map, Value};
+use ustr::Ustr;
+
+use crate::location::{LOCODE_ENCODING, STATE_ENCODING, SUBDIV_ENCODING};
+use crate::locations_db::{parse_data_blocks, LocationsDb};
+
+const CONTINENTS: [&str; 6] = ["AF", "AS", "EU", "NA", "OC", "SA"];
+const ONSETS: [&str; 16] = [
+    "b", "br", "d", "f", "g", "k", "l", "m", "n", "p", "r", "s", "st", "t", "v", "z",
+];
+const VOWELS: [&str; 6] = ["a", "e", "i", "o", "u", "ia"];
+const CODAS: [&str; 6] = ["", "n", "r", "l", "s", "m"];
+
+/// What `generate` creates. Subdivisions are shared out among the states
+/// and LOCODEs among the subdivisions, or the states if there are none.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixtureSpec {
+    pub seed: u64,
+    // at most 676, the two-letter codes
+    pub states: usize,
+    pub subdivisions: usize,
+    pub locodes: usize,
+    // LOCODEs given the name of another LOCODE, in a different subdivision
+    // where there are several; at most half the LOCODEs
+    pub collisions: usize,
+}
+
+impl Default for FixtureSpec {
+    fn default() -> Self {
+        FixtureSpec {
+            seed: 0,
+            states: 3,
+            subdivisions: 6,
+            locodes: 30,
+            collisions: 3,
+        }
+    }
+}
+
+/// A generated database and the keys of what it holds, in order of
+/// generation.
+pub struct Fixture {
+    pub db: LocationsDb,
+    pub states: Vec<Ustr>,
+    pub subdivisions: Vec<Ustr>,
+    pub locodes: Vec<Ustr>,
+    // the names shared by LOCODEs, with the keys of the LOCODEs sharing each
+    pub collisions: Vec<(String, Vec<Ustr>)>,
+}
+
+// SplitMix64, which is enough to vary names and keeps fixtures stable
+// without depending on a random number crate.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+    fn pick<'a>(&mut self, choices: &[&'a str]) -> &'a str {
+        choices[(self.next() % choices.len() as u64) as usize]
+    }
+    // a capitalised, pronounceable name not among `taken`
+    fn name(&mut self, taken: &mut HashSet<String>) -> String {
+        loop {
+            let syllables = 2 + self.next() % 2;
+            let mut name = String::new();
+            for _ in 0..syllables {
+                name.push_str(self.pick(&ONSETS));
+                name.push_str(self.pick(&VOWELS));
+            }
+            name.push_str(self.pick(&CODAS));
+            let name = name[..1].to_uppercase() + &name[1..];
+            if taken.insert(name.clone()) {
+                return name;
+            }
+        }
+    }
+}
+
+// "AA", "AB", ... as used for states, and three letters for the rest
+fn letters(mut n: usize, len: usize) -> String {
+    let mut code = vec![b'A'; len];
+    for c in code.iter_mut().rev() {
+        *c += (n % 26) as u8;
+        n /= 26;
+    }
+    String::from_utf8(code).expect("ASCII")
+}
+
+fn raw(encoding: &str, id: &str, name: &str, d: Value) -> (String, Value) {
+    let raw = json!({
+        "<c>": encoding,
+        "s": format!("<bln|{encoding}#{id}|\"{name}\">"),
+        "i": id,
+        "d": d,
+    });
+    (format!("{encoding}#{id}"), raw)
+}
+
+impl FixtureSpec {
+    /// The locations in the raw JSON shape of the datasets, as a single
+    /// block, e.g. for writing out as a data file.
+    pub fn blocks(&self) -> Vec<(String, Value)> {
+        self.generate_raw().0
+    }
+    /// Generates and indexes the database.
+    pub fn generate(&self) -> Fixture {
+        let (blocks, mut fixture) = self.generate_raw();
+        #[cfg(feature = "parallel")]
+        let blocks = blocks.into_par_iter();
+        let db = parse_data_blocks(blocks, None).expect("parse generated fixture");
+        fixture.db = db.mk_fst();
+        fixture
+    }
+    fn generate_raw(&self) -> (Vec<(String, Value)>, Fixture) {
+        assert!(self.states > 0 && self.states <= 26 * 26, "1 to 676 states");
+        let mut rng = Rng(self.seed);
+        let mut taken = HashSet::new();
+        let mut block = Map::new();
+        let mut fixture = Fixture {
+            db: LocationsDb::default(),
+            states: vec![],
+            subdivisions: vec![],
+            locodes: vec![],
+            collisions: vec![],
+        };
+        let key =
+            |encoding: &str, id: &str| Ustr::from(&format!("{encoding}-{}", id.to_lowercase()));
+        let mut insert = |(id, raw): (String, Value)| block.insert(id, raw);
+
+        let mut states = vec![];
+        for n in 0..self.states {
+            let (alpha2, name) = (letters(n, 2), rng.name(&mut taken));
+            insert(raw(
+                STATE_ENCODING,
+                &alpha2,
+                &name,
+                json!({
+                    "name": name,
+                    "short": name,
+                    "alpha2": alpha2,
+                    "alpha3": format!("{alpha2}X"),
+                    "official_en": name,
+                    "continent": CONTINENTS[n % CONTINENTS.len()],
+                }),
+            ));
+            fixture.states.push(key(STATE_ENCODING, &alpha2));
+            states.push(alpha2);
+        }
+        // (state, subcode) of each subdivision
+        let mut subdivisions = vec![];
+        for n in 0..self.subdivisions {
+            let (state, subcode) = (&states[n % states.len()], letters(n / states.len(), 3));
+            let (id, name) = (format!("{state}:{subcode}"), rng.name(&mut taken));
+            insert(raw(
+                SUBDIV_ENCODING,
+                &id,
+                &name,
+                json!({
+                    "name": name,
+                    "supercode": state,
+                    "subcode": subcode,
+                    "level": "region",
+                }),
+            ));
+            fixture.subdivisions.push(key(SUBDIV_ENCODING, &id));
+            subdivisions.push((state.clone(), Some(subcode)));
+        }
+        if subdivisions.is_empty() {
+            subdivisions = states.iter().map(|s| (s.clone(), None)).collect();
+        }
+
+        let names = (0..self.locodes)
+            .map(|_| rng.name(&mut taken))
+            .collect::<Vec<_>>();
+        let collisions = self.collisions.min(self.locodes / 2);
+        for n in 0..self.locodes {
+            // the first LOCODEs take the names of those half way along, and
+            // lie in the next subdivision over
+            let (name, place) = match n < collisions {
+                true => (&names[n + self.locodes / 2], n + self.locodes / 2 + 1),
+                false => (&names[n], n),
+            };
+            let (state, subdivision) = &subdivisions[place % subdivisions.len()];
+            let subcode = letters(n, 3);
+            let id = format!("{state}:{subcode}");
+            let mut d = json!({
+                "name": name,
+                "supercode": state,
+                "subcode": subcode,
+                "function_code": "--3-----",
+            });
+            if let Some(subdivision) = subdivision {
+                d["subdivision_code"] = json!(subdivision);
+            }
+            insert(raw(LOCODE_ENCODING, &id, name, d));
+            fixture.locodes.push(key(LOCODE_ENCODING, &id));
+        }
+        fixture.collisions = (0..collisions)
+            .map(|n| {
+                let shared = vec![fixture.locodes[n], fixture.locodes[n + self.locodes / 2]];
+                (names[n + self.locodes / 2].clone(), shared)
+            })
+            .collect();
+        let blocks = vec![("fixture.json".to_string(), Value::Object(block))];
+        (blocks, fixture)
+    }
+}
@@ -0,0 +1,70 @@
+#![allow(dead_code)]
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use csv::ReaderBuilder;
+use serde_json::{json, Value};
+
+use berlin_core::location::{AnyLocation, CsvLocode, Location};
+use berlin_core::locations_db::{parse_data_block, parse_data_list, LocationsDb};
+
+pub fn data_dir() -> PathBuf {
+    let mut data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    data_dir.extend(["tests", "data"]);
+    data_dir
+}
+
+/// Loads the test fixtures into a fresh, indexed database.
+pub fn load_fake_data() -> LocationsDb {
+    load_fake_data_unindexed().mk_fst()
+}
+
+/// Loads the test fixtures without building the search index.
+pub fn load_fake_data_unindexed() -> LocationsDb {
+    let db = RwLock::new(LocationsDb::default());
+    let fo = File::open(data_dir().join("test-codes.json")).expect("cannot open json file");
+    let json: Value = serde_json::from_reader(BufReader::new(fo)).expect("cannot decode json");
+    match json {
+        Value::Object(obj) => {
+            parse_data_block(&db, obj).expect("cannot parse json");
+        }
+        other => panic!("Expected a JSON object: {:?}", other),
+    }
+    let db = db.into_inner().expect("rw lock extract");
+    let csv_file_open = File::open(data_dir().join("test-code-list.csv")).expect("Read CSV File");
+    let mut csv_reader = ReaderBuilder::new().from_reader(csv_file_open);
+    let iter = csv_reader
+        .deserialize::<CsvLocode>()
+        .map(|result| result.expect("could not parse CSV line"));
+    parse_data_list(db, iter).expect("could not parse csv file")
+}
+
+/// Builds a location from the same raw JSON shape as the datasets.
+pub fn location(raw: Value) -> Location {
+    let raw = serde_json::from_value::<AnyLocation>(raw).expect("raw location");
+    Location::from_raw(raw).expect("location")
+}
+
+/// Raw JSON for a port LOCODE, such as `locode_json("GB:QXF", "Quixfield",
+/// json!({}))`, with any further data fields taken from `extra`.
+pub fn locode_json(key: &str, name: &str, extra: Value) -> Value {
+    let (state, subcode) = key.split_once(':').expect("STATE:CODE");
+    let mut data = json!({
+        "name": name,
+        "supercode": state,
+        "subcode": subcode,
+        "function_code": "--3-----"
+    });
+    if let Value::Object(extra) = extra {
+        data.as_object_mut().expect("object").extend(extra);
+    }
+    json!({ "<c>": "UN-LOCODE", "i": key, "d": data })
+}
+
+/// Builds a port LOCODE; see [`locode_json`].
+pub fn locode(key: &str, name: &str, extra: Value) -> Location {
+    location(locode_json(key, name, extra))
+}
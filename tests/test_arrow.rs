@@ -0,0 +1,33 @@
+#![cfg(feature = "arrow")]
+
+mod common;
+
+use berlin_core::arrow::{locations_batch, results_batch};
+use berlin_core::search::SearchTerm;
+
+#[test]
+fn should_export_locations_and_results() {
+    let db = common::load_fake_data();
+    let locations = locations_batch(&db).expect("locations batch");
+    assert_eq!(locations.num_rows(), 17);
+
+    let queries = ["abercorn", "bulgaria"];
+    let results = queries
+        .iter()
+        .map(|q| db.search(&SearchTerm::from_raw_query(q.to_string(), None, 2, 3)))
+        .collect::<Vec<_>>();
+    let batch = results_batch(
+        &db,
+        queries
+            .iter()
+            .copied()
+            .zip(results.iter().map(|r| r.as_slice())),
+    )
+    .expect("results batch");
+    assert_eq!(
+        batch.num_rows(),
+        results.iter().map(|r| r.len()).sum::<usize>()
+    );
+    assert!(batch.column_by_name("query_index").is_some());
+    assert!(batch.column_by_name("key").is_some());
+}
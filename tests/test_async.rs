@@ -0,0 +1,51 @@
+#![cfg(feature = "async")]
+
+mod common;
+
+use std::sync::Arc;
+
+use berlin_core::async_api::{load_snapshot, search_async};
+use berlin_core::search::SearchTerm;
+use berlin_core::snapshot::write_snapshot;
+
+#[tokio::test]
+async fn should_search_and_load_from_async_code() {
+    let db = common::load_fake_data();
+    let path = std::env::temp_dir().join(format!("berlin-async-{}.snapshot", std::process::id()));
+    write_snapshot(&db, std::fs::File::create(&path).unwrap()).expect("write snapshot");
+    let restored = Arc::new(load_snapshot(path.clone()).await.expect("load snapshot"));
+    std::fs::remove_file(&path).unwrap();
+
+    let st = SearchTerm::from_raw_query("abercorn".to_string(), None, 5, 3);
+    let results = search_async(restored, st).await.expect("search");
+    assert_eq!(results[0].0, "UN-LOCODE-gb:abc");
+    assert!(load_snapshot(path).await.is_err());
+}
+
+#[cfg(feature = "object-store")]
+#[tokio::test]
+async fn should_load_from_an_object_store_url() {
+    use berlin_core::config::LoadOptions;
+    use berlin_core::locations_db::{CODE_LIST_FILE, DATA_FILES};
+    use berlin_core::remote::load_from_url;
+
+    let dir = std::env::temp_dir().join(format!("berlin-remote-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for file in DATA_FILES {
+        std::fs::write(dir.join(file), "{}").unwrap();
+    }
+    let data_dir = common::data_dir();
+    std::fs::copy(data_dir.join("test-codes.json"), dir.join(DATA_FILES[0])).unwrap();
+    std::fs::copy(
+        data_dir.join("test-code-list.csv"),
+        dir.join(CODE_LIST_FILE),
+    )
+    .unwrap();
+    let url = format!("file://{}/", dir.display());
+    let db = load_from_url(&url, LoadOptions::default()).await;
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let db = db.expect("load from url");
+    assert_eq!(db.all.len(), 17);
+    assert!(load_from_url(&url, LoadOptions::default()).await.is_err());
+}
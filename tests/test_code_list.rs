@@ -16,6 +16,7 @@ use berlin_core::search::SearchTerm;
 
 #[fixture]
 #[once]
+#[allow(clippy::expect_fun_call)]
 pub fn fake_data() -> LocationsDb {
     let start = Instant::now();
     let db = LocationsDb::default();
@@ -0,0 +1,121 @@
+mod common;
+
+use serde_json::json;
+
+use berlin_core::config::CaseLocale;
+use berlin_core::filters::{CharFilter, FilterChain};
+use berlin_core::locations_db::LocationsDb;
+use berlin_core::normalize;
+use berlin_core::search::SearchTerm;
+use ustr::Ustr;
+
+use common::{load_fake_data_unindexed, locode};
+
+fn ampersands() -> FilterChain {
+    FilterChain(vec![
+        CharFilter::Replace {
+            from: "&".to_string(),
+            to: " and ".to_string(),
+        },
+        CharFilter::StripPunctuation,
+        CharFilter::CollapseWhitespace,
+    ])
+}
+
+#[test]
+fn should_normalize_names_and_queries_alike() {
+    let mut db = LocationsDb::default();
+    db.index_options.filters = ampersands();
+    db.insert(locode("GB:BWS", "Bath & Wells", json!({})));
+    let db = db.mk_fst();
+    for query in ["bath and wells", "Bath & Wells!"] {
+        let st = SearchTerm::from_raw_query(query.to_string(), None, 5, 0);
+        let results = db.search(&st);
+        assert_eq!(results[0].0, "UN-LOCODE-gb:bws", "{query}");
+        assert_eq!(db.prepare(&st).normalized, "bath and wells");
+    }
+    // the names themselves are kept as `normalize` leaves them
+    assert_eq!(
+        db.all[&"UN-LOCODE-gb:bws".into()].get_names()[0],
+        "bath & wells"
+    );
+}
+
+#[test]
+fn should_keep_added_codes_through_filters() {
+    let mut db = load_fake_data_unindexed();
+    db.index_options.filters = ampersands();
+    let db = db.mk_fst();
+    let plain = SearchTerm::from_raw_query("Abercarn & GB!".to_string(), None, 10, 0);
+    let mut coded = plain.clone();
+    coded.add_code(Ustr::from("gb")).unwrap();
+    let prepared = db.prepare(&coded);
+    assert_eq!(prepared.normalized, "abercarn and gb");
+    let codes = prepared
+        .codes
+        .iter()
+        .map(|c| (c.term.as_str(), c.offset.start, c.offset.end));
+    assert_eq!(codes.collect::<Vec<_>>(), [("gb", 13, 15)]);
+    let score = |st: &SearchTerm| {
+        let results = db.search(st);
+        let gb = results.iter().find(|(key, _)| *key == "ISO-3166-1-gb");
+        gb.map_or(0, |(_, score)| score.score)
+    };
+    assert!(score(&coded) > score(&plain));
+}
+
+#[test]
+fn should_leave_other_databases_unfiltered() {
+    let mut filtered = LocationsDb::default();
+    filtered.index_options.filters = ampersands();
+    let filtered = filtered.mk_fst();
+    let st = SearchTerm::from_raw_query("Bath & Wells".to_string(), None, 5, 0);
+    assert_eq!(filtered.prepare(&st).normalized, "bath and wells");
+    assert_eq!(
+        LocationsDb::default().prepare(&st).normalized,
+        "bath & wells"
+    );
+    assert_eq!(normalize("  Bath &  Wells!"), "  bath &  wells!");
+}
+
+#[test]
+fn should_apply_the_standard_chain_as_normalize_does() {
+    for s in ["Łódź", "Straße", "GB:ABC", "Ísafjörður"] {
+        assert_eq!(FilterChain::standard().apply(s, None), normalize(s), "{s}");
+    }
+    let turkic = CharFilter::FoldCase(CaseLocale::Turkic);
+    assert_eq!(FilterChain(vec![turkic]).apply("ISPARTA", None), "ısparta");
+    assert_eq!(
+        FilterChain::default().apply("Bath & Wells", None),
+        "Bath & Wells"
+    );
+}
+
+#[test]
+fn should_annotate_through_filters_that_change_lengths() {
+    let mut db = LocationsDb::default();
+    db.index_options.filters = ampersands();
+    db.insert(locode("GB:BWS", "Bath & Wells", json!({})));
+    let db = db.mk_fst();
+    let raw = "ﬂights to Æbury,  BATH & Wells!";
+    let st = SearchTerm::from_raw_query(raw.to_string(), None, 5, 0);
+    let annotations = db.annotate(&st);
+    assert_eq!(annotations.len(), 1);
+    let bath = &annotations[0];
+    assert_eq!(&raw[bath.start..bath.end], "BATH & Wells");
+}
+
+#[test]
+fn should_map_each_normalized_byte_to_the_raw_query() {
+    let raw = "ﬂights to Æbury & Wells!";
+    let (normalized, spans) = FilterChain::standard()
+        .then(&ampersands())
+        .apply_spans(raw, None);
+    assert_eq!(normalized, ampersands().apply(&normalize(raw), None));
+    assert_eq!(spans.len(), normalized.len());
+    let (n, _) = normalized.match_indices("aebury").next().unwrap();
+    assert_eq!(&raw[spans[n].start..spans[n + 5].end], "Æbury");
+    let (n, _) = normalized.match_indices("and").next().unwrap();
+    assert_eq!(&raw[spans[n].start..spans[n + 2].end], "&");
+    assert_eq!(&raw[spans[0].start..spans[1].end], "ﬂ");
+}
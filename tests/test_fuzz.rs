@@ -0,0 +1,49 @@
+#![cfg(feature = "arbitrary")]
+
+mod common;
+
+use arbitrary::{Arbitrary, Unstructured};
+use serde_json::json;
+
+use berlin_core::fuzz::{fuzz_coordinates, fuzz_location, fuzz_search, FuzzLocation, FuzzQuery};
+use berlin_core::location::{AnyLocation, Location};
+
+// bytes from a fixed xorshift, so each run tries the same inputs
+fn inputs(count: usize) -> impl Iterator<Item = Vec<u8>> {
+    let mut state = 0x2545f4914f6cdd1d_u64;
+    (0..count).map(move |n| {
+        (0..16 + n % 240)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    })
+}
+
+#[test]
+fn should_not_panic_on_arbitrary_input() {
+    let db = common::load_fake_data();
+    for bytes in inputs(2000) {
+        let mut u = Unstructured::new(&bytes);
+        if let Ok(query) = FuzzQuery::arbitrary(&mut u) {
+            fuzz_search(&db, &query);
+        }
+        if let Ok(location) = FuzzLocation::arbitrary(&mut Unstructured::new(&bytes)) {
+            fuzz_location(&location);
+        }
+        fuzz_coordinates(&String::from_utf8_lossy(&bytes));
+    }
+}
+
+#[test]
+fn should_refuse_airports_with_unreadable_elevations() {
+    let raw = json!({"<c>": "IATA", "i": "XYZ", "d": {
+        "name": "Xyz", "iata": "XYZ", "type": "small_airport", "country": "GB",
+        "region": "GB-ABD", "y": 57.0, "x": -2.0, "elevation": "high",
+    }});
+    let raw = serde_json::from_value::<AnyLocation>(raw).expect("raw location");
+    assert!(Location::from_raw(raw).is_err());
+}
@@ -0,0 +1,1034 @@
+mod common;
+
+use std::sync::{Arc, RwLock};
+
+use serde_json::json;
+use ustr::Ustr;
+
+use berlin_core::config::{standard_status_rules, DedupBy, DedupPolicy, LoadOptions, SearchConfig};
+use berlin_core::coordinates::Coordinates;
+use berlin_core::location::{
+    AnyLocation, CsvRedirect, CustomKind, CustomRecord, LocData, Location, LocodeFunction,
+};
+use berlin_core::locations_db::{link_related, parse_data_block, parse_redirects, LocationsDb};
+use berlin_core::profile::SearchProfile;
+use berlin_core::regions::{add_regions, RegionDef};
+use berlin_core::registry::DbRegistry;
+use berlin_core::reload::ReloadableDb;
+use berlin_core::search::{Score, SearchTerm};
+use berlin_core::session::SearchSession;
+use berlin_core::shard::ShardedDb;
+use berlin_core::snapshot::{read_snapshot, write_snapshot};
+use berlin_core::stats::SearchStats;
+
+use common::{load_fake_data, load_fake_data_unindexed, location, locode};
+
+#[test]
+fn should_search_runtime_additions_across_segments() {
+    let mut db = load_fake_data();
+    assert_eq!(db.segments.len(), 1);
+
+    db.add_locations([locode(
+        "BG:ZZX",
+        "Zzyzxovo",
+        json!({"subdivision_code": "02"}),
+    )]);
+    assert_eq!(db.segments.len(), 2);
+
+    let query = || SearchTerm::from_raw_query("Zzyzxova".to_string(), None, 5, 3);
+    let results = db.search(&query());
+    assert_eq!(results[0].0, "UN-LOCODE-bg:zzx");
+
+    // Pre-existing locations are still found through the base segment
+    let abercorn = SearchTerm::from_raw_query("abercorn".to_string(), None, 5, 3);
+    assert_eq!(db.search(&abercorn)[0].0, "UN-LOCODE-gb:abc");
+
+    db.compact();
+    assert_eq!(db.segments.len(), 1);
+    assert_eq!(db.search(&query())[0].0, "UN-LOCODE-bg:zzx");
+}
+
+#[test]
+fn should_index_custom_location_kinds() {
+    let kind = CustomKind {
+        name_field: "title".to_string(),
+        code_field: "ref".to_string(),
+        ..CustomKind::new("WAREHOUSE")
+    };
+    let raw = json!({
+        "<c>": "WAREHOUSE",
+        "i": "GB:W17",
+        "d": { "title": "Quillingham Depot", "ref": "W17", "supercode": "GB" }
+    });
+
+    let mut parsed = LocationsDb::default();
+    parsed.register_kind(kind.clone());
+    let parsed = RwLock::new(parsed);
+    let serde_json::Value::Object(obj) = json!({ "GB:W17": raw.clone() }) else {
+        unreachable!()
+    };
+    parse_data_block(&parsed, obj).expect("parse custom kind");
+    let parsed = parsed.into_inner().unwrap();
+    assert!(matches!(
+        parsed.all[&Ustr::from("WAREHOUSE-gb:w17")].data,
+        LocData::Custom(_)
+    ));
+
+    let mut db = load_fake_data();
+    let raw = serde_json::from_value::<AnyLocation>(raw).unwrap();
+    db.add_locations([Location::from_raw_custom(raw, &kind).expect("custom location")]);
+    let st = SearchTerm::from_raw_query("quillingham depot".to_string(), None, 5, 3);
+    let results = db.search(&st);
+    assert_eq!(results[0].0, "WAREHOUSE-gb:w17");
+    let warehouse = &db.all[&results[0].0];
+    assert_eq!(warehouse.get_parents().0, Some(Ustr::from("ISO-3166-1-gb")));
+}
+
+#[test]
+fn should_add_custom_locations_beneath_subdivisions() {
+    let mut db = load_fake_data();
+    let site = Coordinates {
+        lat: 51.65,
+        lon: -3.13,
+    };
+    let keys = db
+        .add_custom(
+            [CustomRecord::new("ACME-SITE", "Gwyddon Works", "hq1", "GB")
+                .with_subdivision("CAY")
+                .with_coordinates(site)],
+        )
+        .expect("custom location");
+    assert_eq!(keys, [Ustr::from("ACME-SITE-hq1")]);
+
+    let st = SearchTerm::from_raw_query("gwyddon works".to_string(), None, 5, 0);
+    assert_eq!(db.search(&st)[0].0, keys[0]);
+    let parent = db.indices[&keys[0]].ancestors(&db.arena).nth(1).unwrap();
+    assert_eq!(*db.arena[parent].get(), "ISO-3166-2-gb:cay");
+    let nearest = db.nearest(site, 1);
+    assert_eq!(nearest[0].0, keys[0]);
+    assert!(nearest[0].1 < 1e-6);
+
+    let unknown =
+        CustomRecord::new("ACME-SITE", "Nowhere Works", "hq2", "GB").with_subdivision("ZZZ");
+    assert!(db.add_custom([unknown]).is_err());
+}
+
+#[test]
+fn should_match_alternate_names() {
+    let mut db = load_fake_data();
+    let st = SearchTerm::from_raw_query("Royaume-Uni".to_string(), None, 5, 3);
+    assert_eq!(db.search(&st)[0].0, "ISO-3166-1-gb");
+
+    db.add_locations([locode(
+        "BG:MUC",
+        "Munichovo",
+        json!({"name:de": "Münchowo"}),
+    )]);
+    let loc = &db.all[&Ustr::from("UN-LOCODE-bg:muc")];
+    assert_eq!(loc.alt_names, vec![("de".into(), "munchowo".into())]);
+    let st = SearchTerm::from_raw_query("Münchowo".to_string(), None, 5, 3);
+    assert_eq!(db.search(&st)[0].0, "UN-LOCODE-bg:muc");
+}
+
+#[test]
+fn should_redirect_retired_keys() {
+    let redirects = [
+        CsvRedirect {
+            from: "UN-LOCODE-gb:abx".to_string(),
+            to: "UN-LOCODE-gb:abc".to_string(),
+        },
+        CsvRedirect {
+            from: "ISO-3166-2-gb:wsx".to_string(),
+            to: "ISO-3166-2-gb:abd".to_string(),
+        },
+    ];
+    let db = parse_redirects(load_fake_data_unindexed(), redirects.into_iter())
+        .expect("parse redirects")
+        .mk_fst();
+
+    let resolution = db.resolve("UN-LOCODE-gb:abx").expect("redirected");
+    assert_eq!(resolution.location.key, "UN-LOCODE-gb:abc");
+    assert_eq!(
+        resolution.redirected_from,
+        Some(Ustr::from("UN-LOCODE-gb:abx"))
+    );
+    assert_eq!(
+        db.resolve("UN-LOCODE-gb:abc").unwrap().redirected_from,
+        None
+    );
+    assert_eq!(
+        db.retrieve("UN-LOCODE-gb:abx").unwrap().key,
+        "UN-LOCODE-gb:abc"
+    );
+
+    // a retired location still in the data is dropped from every index
+    assert!(!db.all.contains_key(&Ustr::from("ISO-3166-2-gb:wsx")));
+    assert!(!db.subdiv_by_code.contains_key(&Ustr::from("gb:wsx")));
+    assert_eq!(
+        db.resolve_code("GB-WSX").unwrap().location.key,
+        "ISO-3166-2-gb:abd"
+    );
+
+    let st = SearchTerm::from_raw_query("abx".to_string(), None, 5, 0);
+    let results = db.search_detailed(&st);
+    assert_eq!(results[0].key, "UN-LOCODE-gb:abc");
+    assert_eq!(results[0].redirected_from, Some("UN-LOCODE-gb:abx".into()));
+    let st = SearchTerm::from_raw_query("abercarn".to_string(), None, 5, 0);
+    assert_eq!(db.search_detailed(&st)[0].redirected_from, None);
+
+    let mut bytes = vec![];
+    write_snapshot(&db, &mut bytes).expect("write snapshot");
+    let restored = read_snapshot(bytes.as_slice()).expect("read snapshot");
+    assert_eq!(
+        restored.resolve("UN-LOCODE-gb:abx").unwrap().location.key,
+        "UN-LOCODE-gb:abc"
+    );
+}
+
+/// The fixtures, plus an airport and the LOCODE of the same place.
+fn load_with_airport() -> LocationsDb {
+    let mut db = load_fake_data_unindexed();
+    db.insert(locode(
+        "GB:QXA",
+        "Quixby",
+        json!({"function_code": "1--4----"}),
+    ));
+    db.insert(location(json!({
+        "<c>": "IATA",
+        "i": "QXA",
+        "d": {
+            "name": "Quixby Airfield",
+            "iata": "QXA",
+            "icao": "EGQX",
+            "type": "small_airport",
+            "country": "GB",
+            "region": "GB-ABD",
+            "x": -2.2,
+            "y": 57.1
+        }
+    })));
+    link_related(db).mk_fst()
+}
+
+#[test]
+fn should_link_airports_to_locodes() {
+    let db = load_with_airport();
+    let locode = &db.all[&Ustr::from("UN-LOCODE-gb:qxa")];
+    let airport = &db.all[&Ustr::from("IATA-qxa")];
+    assert_eq!(locode.related_keys.as_slice(), &[airport.key]);
+    assert_eq!(airport.related_keys.as_slice(), &[locode.key]);
+    assert!(db.all[&Ustr::from("UN-LOCODE-gb:abc")]
+        .related_keys
+        .is_empty());
+    let st = SearchTerm::from_raw_query("quixby".to_string(), None, 5, 0);
+    let detailed = db.search_detailed(&st);
+    let quixby = detailed.iter().find(|r| r.key == locode.key).unwrap();
+    assert_eq!(quixby.related_keys, [airport.key]);
+}
+
+#[test]
+fn should_search_airports_by_name_and_code() {
+    let db = load_with_airport();
+    let keys = |results: Vec<(Ustr, Score)>| {
+        let keys = results.into_iter().map(|(k, _)| k.to_string());
+        keys.collect::<Vec<_>>()
+    };
+    let query = |q: &str| SearchTerm::from_raw_query(q.to_string(), None, 5, 2);
+    assert_eq!(db.search(&query("quixby")).len(), 2);
+    assert_eq!(keys(db.search_airports(&query("quixby"))), ["IATA-qxa"]);
+    assert_eq!(keys(db.search_airports(&query("EGQX"))), ["IATA-qxa"]);
+
+    let st = query("qxa");
+    let plain = db.search(&st);
+    let boosted = db.search_airports(&st);
+    let plain = plain.iter().find(|(k, _)| *k == "IATA-qxa").unwrap().1;
+    assert_eq!(boosted[0].0, "IATA-qxa");
+    assert!(boosted[0].1.score > plain.score);
+}
+
+#[test]
+fn should_restrict_and_boost_by_search_profile() {
+    assert_eq!(
+        LocodeFunction::parse("1--4----").as_slice(),
+        [LocodeFunction::Port, LocodeFunction::Airport]
+    );
+    assert_eq!(
+        LocodeFunction::parse("-----6-B").as_slice(),
+        [LocodeFunction::Multimodal, LocodeFunction::BorderCrossing]
+    );
+    assert!(LocodeFunction::parse("0-------").is_empty());
+
+    let db = load_with_airport();
+    let search = |query: &str, profile| {
+        let st = SearchTerm::from_raw_query(query.to_string(), None, 5, 2);
+        match profile {
+            Some(profile) => db.search(&st.with_profile(profile)),
+            None => db.search(&st),
+        }
+    };
+    let keys = |query: &str, profile| {
+        let keys = search(query, Some(profile)).into_iter().map(|(k, _)| k);
+        keys.map(|k| k.to_string()).collect::<Vec<_>>()
+    };
+    assert_eq!(
+        keys("quixby", SearchProfile::Shipping),
+        ["UN-LOCODE-gb:qxa"]
+    );
+    assert_eq!(keys("quixby", SearchProfile::Aviation), ["IATA-qxa"]);
+    assert_eq!(keys("quixby", SearchProfile::Postal), ["UN-LOCODE-gb:qxa"]);
+    assert_eq!(
+        keys("lyuliakovo", SearchProfile::Shipping),
+        ["UN-LOCODE-bg:blo"]
+    );
+    assert!(keys("lozarevo", SearchProfile::Shipping).is_empty());
+    for profile in [SearchProfile::Shipping, SearchProfile::Postal] {
+        let st = SearchTerm::from_raw_query("quixby".to_string(), None, 5, 2).with_profile(profile);
+        assert!(!st.config.detect_state);
+        let explained = db.explain(&st).into_iter().map(|e| e.key.to_string());
+        assert_eq!(explained.collect::<Vec<_>>(), keys("quixby", profile));
+    }
+
+    let score = |profile| {
+        let results = search("quixby", profile);
+        let port = results.iter().find(|(k, _)| *k == "UN-LOCODE-gb:qxa");
+        port.map(|(_, s)| s.score).unwrap()
+    };
+    assert_eq!(score(Some(SearchProfile::Shipping)), score(None) + 50);
+}
+
+#[test]
+fn should_collapse_duplicates_to_preferred_encoding() {
+    let db = load_with_airport();
+    let query = |config| SearchTerm::with_config("quixby".to_string(), None, 5, 2, config);
+    let results = db.search(&query(SearchConfig::default()));
+    assert_eq!(results.len(), 2);
+
+    let config = SearchConfig {
+        dedup: Some(DedupPolicy {
+            by: vec![DedupBy::CrossReference],
+            preferred: vec!["IATA".into()],
+        }),
+        ..Default::default()
+    };
+    let results = db.search(&query(config));
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].0, "IATA-qxa");
+}
+
+#[test]
+fn should_penalize_or_exclude_negative_terms() {
+    let db = load_with_airport();
+    let query = || SearchTerm::from_raw_query("quixby".to_string(), None, 5, 2);
+    let score = |st: &SearchTerm, key: &str| {
+        let results = db.search(st);
+        results
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, s)| s.score)
+    };
+    let plain = score(&query(), "IATA-qxa").expect("airport found");
+
+    let excluded = query().with_negative_terms(["Airfield"], None);
+    assert_eq!(score(&excluded, "IATA-qxa"), None);
+    assert!(score(&excluded, "UN-LOCODE-gb:qxa").is_some());
+
+    let penalized = query().with_negative_terms(["airfield"], Some(100));
+    assert_eq!(score(&penalized, "IATA-qxa"), Some(plain - 100));
+    // whole words only
+    let partial = query().with_negative_terms(["airf"], None);
+    assert_eq!(score(&partial, "IATA-qxa"), Some(plain));
+}
+
+#[test]
+fn should_resolve_bare_codes() {
+    let db = load_with_airport();
+    let key = |code: &str| db.resolve_code(code).map(|r| r.location.key.to_string());
+    assert_eq!(key("GB").as_deref(), Some("ISO-3166-1-gb"));
+    assert_eq!(key("QXA").as_deref(), Some("IATA-qxa"));
+    // GB:ABC is both a subdivision and a LOCODE, told apart by the dash
+    assert_eq!(key("GB-ABC").as_deref(), Some("ISO-3166-2-gb:abc"));
+    assert_eq!(key("GBABC").as_deref(), Some("UN-LOCODE-gb:abc"));
+    assert_eq!(key(" gb abc ").as_deref(), Some("UN-LOCODE-gb:abc"));
+    assert_eq!(key("GB-WSX").as_deref(), Some("ISO-3166-2-gb:wsx"));
+    for miss in ["ZZ", "XYZ", "GBZZZ", "abercorn", "GB-", ""] {
+        assert_eq!(key(miss), None, "{miss}");
+    }
+}
+
+#[test]
+fn should_configure_token_lengths() {
+    let port_ash = || {
+        let mut db = load_fake_data_unindexed();
+        db.insert(locode("GB:PAS", "Port Ash", json!({})));
+        db
+    };
+    let keys = |db: &LocationsDb, query: &str, config: SearchConfig| {
+        let st = SearchTerm::with_config(query.to_string(), None, 10, 1, config);
+        let keys = db.search(&st).into_iter().map(|(k, _)| k.to_string());
+        keys.collect::<Vec<_>>()
+    };
+
+    let db = port_ash().mk_fst();
+    assert!(keys(&db, "ash", SearchConfig::default()).is_empty());
+    let mut db = port_ash();
+    db.index_options.min_word_length = 3;
+    let db = db.mk_fst();
+    assert_eq!(
+        keys(&db, "ash", SearchConfig::default()),
+        ["UN-LOCODE-gb:pas"]
+    );
+
+    // four letters are matched fuzzily, unless codes may be that long
+    assert!(keys(&db, "burg", SearchConfig::default()).contains(&"ISO-3166-2-bg:02".to_string()));
+    let codes = SearchConfig {
+        max_code_length: 4,
+        ..Default::default()
+    };
+    assert!(!keys(&db, "burg", codes).contains(&"ISO-3166-2-bg:02".to_string()));
+
+    let exact = |config| {
+        let st = SearchTerm::with_config("bg".to_string(), None, 10, 1, config);
+        st.parsed()
+            .exact
+            .into_iter()
+            .map(|t| t.term)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(exact(SearchConfig::default()), ["bg"]);
+    let longer = SearchConfig {
+        min_token_length: 3,
+        ..Default::default()
+    };
+    assert!(exact(longer).is_empty());
+}
+
+fn detecting(query: &str, detect_state: bool) -> SearchTerm {
+    let config = SearchConfig {
+        detect_state,
+        ..Default::default()
+    };
+    SearchTerm::with_config(query.to_string(), None, 10, 1, config)
+}
+
+#[test]
+fn should_filter_by_a_country_named_in_the_query() {
+    let mut db = load_fake_data_unindexed();
+    db.insert(locode("GB:KST", "Kestrel", json!({})));
+    db.insert(locode("BG:KST", "Kestrel", json!({})));
+    let mut db = db.mk_fst();
+    let keys = |query: &str, detect_state| {
+        let mut keys = db
+            .search(&detecting(query, detect_state))
+            .into_iter()
+            .map(|(k, _)| k.to_string())
+            .filter(|k| k.starts_with("UN-LOCODE-"))
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys
+    };
+    let both = ["UN-LOCODE-bg:kst", "UN-LOCODE-gb:kst"];
+    assert_eq!(keys("kestrel bulgaria", false), both);
+    assert_eq!(keys("kestrel bulgaria", true), ["UN-LOCODE-bg:kst"]);
+    assert_eq!(keys("kestrel, gb", true), ["UN-LOCODE-gb:kst"]);
+    // nothing else named, or nothing found in the country
+    assert_eq!(keys("kestrel", true), both);
+    assert!(db.detect_state(&detecting("bulgaria", true)).is_none());
+    assert_eq!(keys("abercarn bulgaria", true), ["UN-LOCODE-gb:abc"]);
+    // searching again outside the country is still counted as one search
+    let stats = Arc::new(SearchStats::default());
+    db.stats = Some(stats.clone());
+    db.search(&detecting("abercarn bulgaria", true));
+    assert_eq!(stats.snapshot().queries, 1);
+
+    // a place sharing the name of the country makes it ambiguous
+    let mut db = load_fake_data_unindexed();
+    db.insert(locode("GB:KST", "Kestrel", json!({})));
+    db.insert(locode("BG:KST", "Kestrel", json!({})));
+    db.insert(locode("GB:BUL", "Bulgaria", json!({})));
+    let db = db.mk_fst();
+    assert!(db
+        .detect_state(&detecting("kestrel bulgaria", true))
+        .is_none());
+}
+
+#[test]
+fn should_match_compounds_both_ways() {
+    let mut db = load_fake_data_unindexed();
+    db.index_options.compounds = true;
+    db.insert(locode("DE:BHF", "Badhersfeldt", json!({})));
+    let db = db.mk_fst();
+    let query = |q: &str| db.search(&SearchTerm::from_raw_query(q.to_string(), None, 5, 0));
+    assert_eq!(query("bognorregis")[0].0, "UN-LOCODE-gb:bsi");
+    let results = query("bad hersfeldt");
+    assert_eq!(results[0].0, "UN-LOCODE-de:bhf");
+    assert_eq!(
+        (results[0].1.offset.start, results[0].1.offset.end),
+        (0, 13)
+    );
+}
+
+#[test]
+fn should_match_punctuation_variants() {
+    let mut db = load_fake_data();
+    db.add_locations([
+        locode("FR:SXE", "Saint-Quixienne", json!({})),
+        locode("IE:OQX", "O'Quixnell", json!({})),
+        locode("GB:QXH", "Quix Hollow", json!({})),
+    ]);
+    let query = |q: &str| db.search(&SearchTerm::from_raw_query(q.to_string(), None, 5, 0));
+    assert_eq!(query("saint quixienne")[0].0, "UN-LOCODE-fr:sxe");
+    assert_eq!(query("oquixnell")[0].0, "UN-LOCODE-ie:oqx");
+    // and the query is respelled to match unpunctuated names
+    let hyphenated = query("quix-hollow");
+    assert_eq!(hyphenated[0].0, "UN-LOCODE-gb:qxh");
+    assert_eq!(hyphenated[0].1.score, query("quix hollow")[0].1.score);
+}
+
+#[test]
+fn should_group_ambiguous_names() {
+    let mut db = load_fake_data();
+    let query = || SearchTerm::from_raw_query("quixfield".to_string(), None, 5, 0);
+    db.add_locations([locode("GB:QXF", "Quixfield", json!({}))]);
+    assert!(!db.search_grouped(&query()).ambiguous);
+
+    db.add_locations([locode("BG:QXF", "Quixfield", json!({}))]);
+    let grouped = db.search_grouped(&query());
+    assert!(grouped.ambiguous);
+    assert_eq!(grouped.groups[0].name, "quixfield");
+    let parents = grouped.groups[0]
+        .results
+        .iter()
+        .map(|r| r.parents.clone())
+        .collect::<Vec<_>>();
+    assert!(parents.contains(&vec![Ustr::from("ISO-3166-1-gb")]));
+    assert!(parents.contains(&vec![Ustr::from("ISO-3166-1-bg")]));
+}
+
+#[test]
+fn should_bias_sessions_toward_recent_places() {
+    let mut db = load_fake_data();
+    db.add_locations([
+        locode("GB:QXF", "Quixfield", json!({})),
+        locode("BG:QXF", "Quixfield", json!({})),
+    ]);
+    let query = |q: &str| SearchTerm::from_raw_query(q.to_string(), None, 5, 0);
+    for (context, expected) in [
+        ("bulgaria", "UN-LOCODE-bg:qxf"),
+        ("abercarn", "UN-LOCODE-gb:qxf"),
+    ] {
+        let mut session = SearchSession::default();
+        session.search(&db, &query(context));
+        assert_eq!(session.search(&db, &query("quixfield"))[0].0, expected);
+        // the boost applies before the limit
+        let mut session = SearchSession::default();
+        session.search(&db, &query(context));
+        let first = SearchTerm::from_raw_query("quixfield".to_string(), None, 1, 0);
+        let top = session.search(&db, &first);
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].0, expected);
+    }
+    let mut session = SearchSession::default();
+    session.search(&db, &query("bulgaria"));
+    for _ in 0..5 {
+        session.search(&db, &query("zzzz"));
+    }
+    assert!(session.context.is_empty());
+}
+
+#[test]
+fn should_penalise_doubtful_code_list_entries() {
+    let mut db = load_fake_data();
+    db.add_locations([
+        locode("GB:QXF", "Quixfield", json!({"status": "AI"})),
+        locode("BG:QXF", "Quixfield", json!({"status": "RQ"})),
+        locode("DE:QXF", "Quixfield", json!({"status": "XX"})),
+    ]);
+    let search = |config: SearchConfig| {
+        let st = SearchTerm::with_config("quixfield".to_string(), None, 5, 0, config);
+        db.search(&st)
+            .into_iter()
+            .map(|(key, score)| (key.to_string(), score.score))
+            .collect::<Vec<_>>()
+    };
+    let results = search(SearchConfig {
+        status_rules: standard_status_rules(),
+        ..Default::default()
+    });
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].0, "UN-LOCODE-gb:qxf");
+    assert_eq!(results[1].0, "UN-LOCODE-bg:qxf");
+    assert_eq!(results[0].1 - results[1].1, 100);
+    let unfiltered = search(SearchConfig::default());
+    assert_eq!(unfiltered.len(), 3);
+    assert!(unfiltered.iter().all(|(_, score)| *score == results[0].1));
+}
+
+#[test]
+fn should_warm_up_on_sample_queries() {
+    let db = load_fake_data();
+    let warm_up = db.warm_up(["abercorn", "bulgaria lozarevo"]);
+    assert_eq!(warm_up.queries, 2);
+    assert_eq!(warm_up.fst_keys, db.stats().indexed_words);
+}
+
+#[test]
+fn should_suggest_words_from_the_index() {
+    let db = load_fake_data();
+    let suggestions = db.suggest_words("Abercarm", 1);
+    assert_eq!(suggestions[0], (Ustr::from("abercarn"), 1));
+    assert!(db.suggest_words("qqqqqqqq", 1).is_empty());
+}
+
+#[test]
+fn should_remove_locations_at_runtime() {
+    let mut db = load_with_airport();
+    let query = |q: &str| SearchTerm::from_raw_query(q.to_string(), None, 5, 2);
+    assert_eq!(db.search(&query("abercorn"))[0].0, "UN-LOCODE-gb:abc");
+
+    let removed = db.remove("UN-LOCODE-gb:qxa").expect("removed");
+    assert_eq!(removed.key, "UN-LOCODE-gb:qxa");
+    assert!(db.all[&Ustr::from("IATA-qxa")].related_keys.is_empty());
+
+    db.remove("UN-LOCODE-gb:abc").expect("removed");
+    assert!(db.remove("UN-LOCODE-gb:abc").is_none());
+    assert!(db.tombstones.contains(&Ustr::from("UN-LOCODE-gb:abc")));
+    let found = |db: &LocationsDb, q| {
+        db.search(&query(q))
+            .into_iter()
+            .map(|r| r.0)
+            .collect::<Vec<_>>()
+    };
+    assert!(!found(&db, "abercorn").contains(&Ustr::from("UN-LOCODE-gb:abc")));
+    assert!(!found(&db, "abercarn").contains(&Ustr::from("UN-LOCODE-gb:abc")));
+
+    db.compact();
+    assert!(db.tombstones.is_empty());
+    assert!(!found(&db, "abercorn").contains(&Ustr::from("UN-LOCODE-gb:abc")));
+}
+
+#[test]
+fn should_search_across_registered_databases() {
+    let mut warehouses = LocationsDb::default();
+    warehouses.insert(locode("GB:ABC", "Abercarn Depot", json!({})));
+    let mut registry = DbRegistry::default();
+    registry.insert("unlocode", load_fake_data());
+    registry.insert("warehouses", warehouses.mk_fst());
+    assert_eq!(
+        registry.names().collect::<Vec<_>>(),
+        ["unlocode", "warehouses"]
+    );
+
+    let st = SearchTerm::from_raw_query("abercarn depot".to_string(), None, 5, 0);
+    let results = registry.search(&st);
+    assert_eq!(
+        (results[0].db, results[0].key.as_str()),
+        ("warehouses", "UN-LOCODE-gb:abc")
+    );
+    assert!(results.iter().any(|r| r.db == "unlocode"));
+    assert!(results.windows(2).all(|w| w[0].score >= w[1].score));
+
+    let results = registry.search_in(["unlocode", "missing"], &st);
+    assert!(results.iter().all(|r| r.db == "unlocode"));
+}
+
+#[test]
+fn should_report_database_stats() {
+    let stats = load_with_airport().stats();
+    assert_eq!(stats.by_kind["Airp"], 1);
+    assert_eq!(
+        stats.by_kind.values().sum::<usize>(),
+        stats.by_country.values().sum::<usize>()
+    );
+    assert!(stats.by_country[&Ustr::from("gb")] > 1);
+    assert!(stats.indexed_words > 0);
+    assert!(stats.fst_bytes > 0);
+    // locodes sit below a subdivision, below a state
+    assert_eq!(stats.arena_depth, 2);
+}
+
+#[test]
+fn should_report_referential_problems() {
+    let mut db = load_with_airport();
+    assert!(!db.validate().has_errors());
+
+    db.add_locations([
+        location(json!({
+            "<c>": "ISO-3166-2",
+            "i": "ZQ:AAA",
+            "d": { "name": "Orphania", "supercode": "ZQ", "subcode": "AAA", "level": "region" }
+        })),
+        locode("GB:QXB", "Quixbury", json!({"subdivision_code": "ZZZ"})),
+    ]);
+    db.insert(db.all[&Ustr::from("IATA-qxa")].clone());
+    let report = db.validate();
+    assert!(report.has_errors());
+    assert_eq!(
+        report.orphan_subdivisions,
+        vec![Ustr::from("ISO-3166-2-zq:aaa")]
+    );
+    assert_eq!(
+        report.unknown_subdivisions,
+        vec![(
+            Ustr::from("UN-LOCODE-gb:qxb"),
+            Ustr::from("ISO-3166-2-gb:zzz")
+        )]
+    );
+    assert_eq!(report.duplicate_keys, vec![Ustr::from("IATA-qxa")]);
+    assert!(report
+        .missing_coordinates
+        .contains(&Ustr::from("UN-LOCODE-gb:qxb")));
+}
+
+#[test]
+fn should_diff_databases() {
+    let old = load_fake_data_unindexed();
+    let mut new = load_fake_data_unindexed();
+    assert!(old.diff(&new).is_empty());
+
+    new.all.remove(&Ustr::from("UN-LOCODE-gb:bsi"));
+    new.insert(locode(
+        "GB:QXA",
+        "Quixby",
+        json!({"function_code": "1--4----"}),
+    ));
+    let abercarn = &old.all[&Ustr::from("UN-LOCODE-gb:abc")];
+    let mut renamed = abercarn.to_raw(None);
+    renamed["d"]["name"] = json!("Abercarne");
+    new.insert(location(renamed));
+
+    let diff = old.diff(&new);
+    assert_eq!(diff.added, vec![Ustr::from("UN-LOCODE-gb:qxa")]);
+    assert_eq!(diff.removed, vec![Ustr::from("UN-LOCODE-gb:bsi")]);
+    assert_eq!(diff.changed.len(), 1);
+    let fields = diff.changed[0]
+        .fields
+        .iter()
+        .map(|f| f.field.as_str())
+        .collect::<Vec<_>>();
+    // the change date came from the code list, which was not reapplied
+    assert_eq!(fields, ["changed.month", "changed.year", "name"]);
+    assert_eq!(diff.changed[0].fields[2].new, json!("abercarne"));
+}
+
+#[test]
+fn should_build_identical_indexes_regardless_of_insertion_order() {
+    let layout = |db: &LocationsDb| {
+        let name = |id| *db.arena[id].get();
+        let nodes = db
+            .arena
+            .iter()
+            .map(|n| (*n.get(), n.parent().map(name), n.next_sibling().map(name)))
+            .collect::<Vec<_>>();
+        let fsts = db
+            .segments
+            .iter()
+            .map(|s| s.fst.as_fst().as_bytes().to_vec())
+            .collect::<Vec<_>>();
+        (nodes, fsts)
+    };
+    let db = load_fake_data();
+
+    let mut locations = load_fake_data_unindexed()
+        .all
+        .into_values()
+        .collect::<Vec<_>>();
+    locations.sort_unstable_by_key(|l| std::cmp::Reverse(l.key.as_str()));
+    let mut reversed = LocationsDb::default();
+    locations.into_iter().for_each(|l| reversed.insert(l));
+    assert_eq!(layout(&reversed.mk_fst()), layout(&db));
+}
+
+#[test]
+fn should_break_ties_between_equal_scores_by_key() {
+    let twins = ["BG:TWA", "BG:TWB", "GB:TWC", "GB:TWD", "BG:TWE", "GB:TWF"]
+        .map(|id| locode(id, "Twinford", json!({})));
+    let with_twins = |order: &[usize]| {
+        let mut db = load_fake_data_unindexed();
+        order.iter().for_each(|n| db.insert(twins[*n].clone()));
+        db.mk_fst()
+    };
+    let (forward, backward) = (
+        with_twins(&[0, 1, 2, 3, 4, 5]),
+        with_twins(&[5, 3, 1, 4, 2, 0]),
+    );
+    let search = |db: &LocationsDb, limit| {
+        let st = SearchTerm::from_raw_query("twinford".to_string(), None, limit, 2);
+        db.search(&st)
+            .into_iter()
+            .map(|(k, _)| k.to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let ranked = search(&forward, 10);
+    assert_eq!(ranked.len(), 6);
+    assert!(ranked.windows(2).all(|w| w[0] < w[1]), "{ranked:?}");
+    for _ in 0..3 {
+        assert_eq!(search(&backward, 10), ranked);
+    }
+    assert_eq!(search(&backward, 2), ranked[..2]);
+    // a limit of 0 is no limit
+    assert_eq!(search(&backward, 0), ranked);
+}
+
+#[test]
+fn should_estimate_memory_use() {
+    let db = load_fake_data();
+    let report = db.memory_report();
+    assert!(report.locations > db.all.len() * std::mem::size_of::<Location>());
+    assert!(report.word_sets > 0 && report.fst > 0 && report.arena > 0);
+    assert!(report.interned_strings > 0);
+}
+
+#[test]
+fn should_key_datasets_under_configured_namespaces() {
+    let options = LoadOptions {
+        custom_kinds: vec![CustomKind::new("DEPOT")],
+        key_namespaces: vec![("DEPOT".to_string(), "ACME-DEPOT".to_string())],
+        ..Default::default()
+    };
+    let db = RwLock::new(LocationsDb::with_options(&options));
+    let serde_json::Value::Object(obj) = json!({
+        "GB:D1": {
+            "<c>": "DEPOT",
+            "i": "GB:D1",
+            "d": { "name": "Quillby Yard", "subcode": "D1", "supercode": "GB" }
+        }
+    }) else {
+        unreachable!()
+    };
+    parse_data_block(&db, obj).expect("parse namespaced block");
+    let db = db.into_inner().unwrap();
+    let loc = &db.all[&Ustr::from("ACME-DEPOT-gb:d1")];
+    assert_eq!(loc.encoding, "DEPOT");
+    assert_eq!(loc.location_key().encoding, "ACME-DEPOT");
+    assert_eq!(loc.location_key().code(), "d1");
+}
+
+#[test]
+fn should_bound_regions_by_their_descendants() {
+    let db = load_fake_data();
+    let bbox = db.bbox("ISO-3166-1-gb").expect("gb has coordinates");
+    let located = db
+        .all
+        .values()
+        .filter(|l| l.get_state() == "gb")
+        .filter_map(|l| l.get_coordinates())
+        .collect::<Vec<_>>();
+    assert!(located.len() > 1);
+    assert!(located.iter().all(|c| bbox.contains(*c)));
+    assert!(located.iter().any(|c| c.lat == bbox.min.lat));
+    assert!(db.bbox("ISO-3166-1-zz").is_none());
+}
+
+#[test]
+fn should_annotate_the_raw_query() {
+    let db = load_fake_data();
+    let raw = "Flüge nach ÅBERCARN, Wales";
+    let st = SearchTerm::from_raw_query(raw.to_string(), None, 5, 0);
+    let annotations = db.annotate(&st);
+    let abercarn = &annotations[0];
+    assert_eq!(&raw[abercarn.start..abercarn.end], "ÅBERCARN");
+    assert_eq!(abercarn.key, "UN-LOCODE-gb:abc");
+    assert!(annotations.windows(2).all(|w| w[0].end <= w[1].start));
+}
+
+#[test]
+fn should_generate_json_schemas_of_documents() {
+    let schema = berlin_core::schema::schema();
+    let definitions = &schema["definitions"];
+    assert_eq!(definitions["NdjsonQuery"]["required"], json!(["q"]));
+    assert_eq!(
+        definitions["DetailedResult"]["properties"]["score"]["$ref"],
+        "#/definitions/Score"
+    );
+    assert!(definitions["Offset"].is_object());
+}
+
+#[cfg(feature = "utoipa")]
+#[test]
+fn should_describe_results_for_openapi() {
+    use berlin_core::locations_db::DetailedResult;
+    use utoipa::PartialSchema;
+
+    let schema = serde_json::to_value(DetailedResult::schema()).unwrap();
+    assert_eq!(schema["properties"]["key"]["type"], "string");
+    assert!(schema["required"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("score")));
+}
+
+#[test]
+fn should_inline_details_of_results() {
+    let db = load_fake_data();
+    let st = SearchTerm::from_raw_query("abercorn".to_string(), None, 5, 3);
+    let detailed = db.search_detailed(&st);
+    assert_eq!(detailed.len(), db.search(&st).len());
+    let abercorn = &detailed[0];
+    assert_eq!(abercorn.key, "UN-LOCODE-gb:abc");
+    assert_eq!(abercorn.kind, "Locd");
+    assert_eq!(abercorn.codes, ["abc"]);
+    assert!(abercorn.coordinates.is_some());
+    let parents = abercorn
+        .parents
+        .iter()
+        .map(|p| p.key.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(parents, ["ISO-3166-1-gb", "ISO-3166-2-gb:cay"]);
+    assert!(abercorn.parents.iter().all(|p| p.name.is_some()));
+    let json = serde_json::to_value(abercorn).unwrap();
+    assert_eq!(json["parents"][0]["key"], "ISO-3166-1-gb");
+}
+
+#[test]
+fn should_swap_in_reloaded_databases() {
+    let reloadable = Arc::new(ReloadableDb::new(LocationsDb::default().mk_fst()));
+    let held = reloadable.current();
+    assert_eq!(held.number, 0);
+    let handle = reloadable.reload_in_background(|| Ok(load_fake_data()));
+    assert_eq!(handle.join().unwrap(), Ok(1));
+    assert_eq!(reloadable.generation(), 1);
+
+    let st = SearchTerm::from_raw_query("abercorn".to_string(), None, 5, 3);
+    assert!(held.db.search(&st).is_empty());
+    assert_eq!(reloadable.current().db.search(&st)[0].0, "UN-LOCODE-gb:abc");
+
+    let failed = reloadable.reload_in_background(|| Err("no data".into()));
+    assert_eq!(failed.join().unwrap(), Err("no data".to_string()));
+    assert_eq!(reloadable.generation(), 1);
+}
+
+#[test]
+fn should_search_shards_like_the_whole_database() {
+    let db = load_fake_data();
+    let sharded = ShardedDb::partition(load_fake_data());
+    assert!(sharded.shards.len() > 1);
+    assert_eq!(sharded.len(), db.all.len());
+    for query in ["abercorn", "lozarevo", "bulgaria lozarevo lyuliakovo"] {
+        let st = SearchTerm::from_raw_query(query.to_string(), None, 5, 3);
+        let scores = |results: Vec<(Ustr, Score)>| {
+            let mut scores = results
+                .into_iter()
+                .map(|(key, score)| (score.score, key.to_string()))
+                .collect::<Vec<_>>();
+            scores.sort_unstable();
+            scores
+        };
+        assert_eq!(scores(sharded.search(&st)), scores(db.search(&st)));
+    }
+    let st = SearchTerm::from_raw_query("abercorn".to_string(), Some("bg".to_string()), 5, 3);
+    assert!(sharded.search(&st).is_empty());
+    assert!(sharded.get("UN-LOCODE-gb:abc").is_some());
+}
+
+#[test]
+fn should_link_regions_to_their_members() {
+    let db = add_regions(load_fake_data_unindexed(), &RegionDef::builtin()).mk_fst();
+    let regions = |key: &str| {
+        db.regions_of(Ustr::from(key))
+            .iter()
+            .map(|r| r.to_string())
+            .collect::<Vec<_>>()
+    };
+    let lozarevo = regions("UN-LOCODE-bg:loz");
+    for region in [
+        "REGION-eu",
+        "REGION-eea",
+        "REGION-schengen",
+        "REGION-europe",
+    ] {
+        assert!(lozarevo.contains(&region.to_string()), "{region}");
+    }
+    assert_eq!(
+        regions("UN-LOCODE-gb:abc"),
+        ["REGION-europe", "REGION-wales"]
+    );
+    assert_eq!(
+        regions("UN-LOCODE-gb:svn"),
+        ["REGION-europe", "REGION-scotland"]
+    );
+    assert_eq!(
+        regions("UN-LOCODE-gb:bsi"),
+        ["REGION-europe", "REGION-england"]
+    );
+
+    let st = SearchTerm::from_raw_query("European Union".to_string(), None, 5, 0);
+    assert_eq!(db.search(&st)[0].0, "REGION-eu");
+
+    let scoped = |region: &str| {
+        let config = SearchConfig {
+            region: Some(Ustr::from(region)),
+            ..Default::default()
+        };
+        let st = SearchTerm::with_config("abercarn".to_string(), None, 5, 0, config);
+        db.search(&st)
+            .into_iter()
+            .map(|(k, _)| k)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(scoped("REGION-wales"), ["UN-LOCODE-gb:abc"]);
+    assert!(scoped("REGION-eu").is_empty());
+}
+
+#[test]
+fn should_expand_metro_areas_to_their_members() {
+    let metro = RegionDef {
+        code: "sunnycoast".to_string(),
+        name: "Sunny Coast Metro".to_string(),
+        keys: vec![
+            "UN-LOCODE-bg:loz".to_string(),
+            "UN-LOCODE-bg:blo".to_string(),
+        ],
+        ..Default::default()
+    };
+    let db = add_regions(load_fake_data_unindexed(), &[metro]).mk_fst();
+    let search = |expand_regions| {
+        let config = SearchConfig {
+            expand_regions,
+            ..Default::default()
+        };
+        let st = SearchTerm::with_config("sunny coast".to_string(), None, 5, 0, config);
+        db.search(&st)
+    };
+    assert_eq!(search(false)[0].0, "REGION-sunnycoast");
+    let expanded = search(true);
+    let keys = expanded.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>();
+    assert_eq!(keys, ["UN-LOCODE-bg:blo", "UN-LOCODE-bg:loz"]);
+    assert_eq!(expanded[0].1.score, expanded[1].1.score);
+    assert_eq!(
+        db.regions_of(Ustr::from("UN-LOCODE-bg:loz")),
+        [Ustr::from("REGION-sunnycoast")]
+    );
+}
+
+#[test]
+fn should_filter_results_by_tags() {
+    let raw = locode(
+        "BG:ZZX",
+        "Zzyzxovo",
+        json!({"function_code": "1", "tags": ["Port", "customer-region-A"]}),
+    );
+    assert_eq!(raw.tags.as_slice(), ["customer-region-a", "port"]);
+    assert!(raw.extra.is_empty());
+    assert_eq!(
+        raw.to_raw(None)["d"]["tags"],
+        json!(["customer-region-a", "port"])
+    );
+
+    let mut db = load_fake_data();
+    assert!(db.tag("UN-LOCODE-gb:abc", "port"));
+    assert!(!db.tag("UN-LOCODE-gb:zzz", "port"));
+    let search = |st: SearchTerm| {
+        db.search(&st)
+            .into_iter()
+            .map(|(k, _)| k)
+            .collect::<Vec<_>>()
+    };
+    let query = || SearchTerm::from_raw_query("abercarn".to_string(), None, 5, 0);
+    assert_eq!(
+        search(query().with_tags_any(["port", "capital"])),
+        ["UN-LOCODE-gb:abc"]
+    );
+    assert!(search(query().with_tags_all(["port", "capital"])).is_empty());
+    assert!(search(query().with_tags_any(["capital"])).is_empty());
+}
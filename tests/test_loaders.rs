@@ -0,0 +1,548 @@
+mod common;
+
+use berlin_core::bulk;
+use berlin_core::config::{IndexOptions, LoadOptions};
+#[cfg(feature = "fs")]
+use berlin_core::export::ExportFormat;
+use berlin_core::filters::{CharFilter, FilterChain};
+#[cfg(feature = "fs")]
+use berlin_core::lint::lint_data_dir;
+use berlin_core::location::{
+    ChangeDate, CodeListChange, CsvLocode, CustomKind, LocData, MergeOutcome,
+};
+#[cfg(feature = "fs")]
+use berlin_core::locations_db::parse_data_files;
+use berlin_core::locations_db::{
+    apply_load_options, link_related, merge_data_list, parse_csv_reader, parse_data_slices,
+    parse_json_reader, LocationsDb,
+};
+use berlin_core::search::SearchTerm;
+use berlin_core::snapshot::{read_snapshot, write_snapshot, SnapshotError, SNAPSHOT_VERSION};
+
+use common::{data_dir, location};
+use serde_json::json;
+use ustr::Ustr;
+
+#[test]
+fn should_load_from_byte_slices() {
+    let codes = std::fs::read(data_dir().join("test-codes.json")).expect("read json");
+    let code_list = std::fs::read(data_dir().join("test-code-list.csv")).expect("read csv");
+    let redirects = "from,to\nUN-LOCODE-gb:abx,UN-LOCODE-gb:abc\n";
+    let db = parse_data_slices(
+        &[("test-codes.json", &codes)],
+        Some(&code_list),
+        Some(redirects.as_bytes()),
+    )
+    .expect("load");
+    assert_eq!(db.all.len(), 17);
+    assert!(db.resolve("UN-LOCODE-gb:abx").is_some());
+
+    let st = SearchTerm::from_raw_query("abercorn".to_string(), None, 5, 3);
+    let results = db.search(&st);
+    assert_eq!(results[0].0, "UN-LOCODE-gb:abc");
+}
+
+#[test]
+fn should_load_from_readers() {
+    let codes = std::fs::File::open(data_dir().join("test-codes.json")).expect("open json");
+    let code_list = std::fs::File::open(data_dir().join("test-code-list.csv")).expect("open csv");
+    let db = parse_json_reader(LocationsDb::default(), codes).expect("load json");
+    let db = link_related(parse_csv_reader(db, code_list).expect("load csv")).mk_fst();
+    assert_eq!(db.all.len(), 17);
+
+    let st = SearchTerm::from_raw_query("abercorn".to_string(), None, 5, 3);
+    assert_eq!(db.search(&st)[0].0, "UN-LOCODE-gb:abc");
+    assert!(parse_json_reader(LocationsDb::default(), "[]".as_bytes()).is_err());
+}
+
+#[cfg(feature = "embedded-data")]
+#[test]
+fn should_load_the_builtin_snapshot() {
+    let db = LocationsDb::builtin().expect("builtin snapshot");
+    assert!(!db.all.is_empty());
+}
+
+#[test]
+fn should_round_trip_snapshots() {
+    let db = common::load_fake_data();
+    let mut bytes = vec![];
+    write_snapshot(&db, &mut bytes).expect("write snapshot");
+    let restored = read_snapshot(bytes.as_slice()).expect("read snapshot");
+    assert_eq!(restored.all.len(), db.all.len());
+
+    let st = SearchTerm::from_raw_query("abercorn".to_string(), None, 5, 3);
+    let keys = |db: &LocationsDb| {
+        db.search(&st)
+            .into_iter()
+            .map(|(k, _)| k)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(keys(&restored), keys(&db));
+}
+
+#[test]
+fn should_carry_database_settings_in_snapshots() {
+    let options = LoadOptions {
+        custom_kinds: vec![CustomKind::new("ACME-DEPOT")],
+        key_namespaces: vec![("UN-LOCODE".to_string(), "ACME-PORT".to_string())],
+        ..Default::default()
+    };
+    let mut db = LocationsDb::with_options(&options);
+    db.index_options.min_word_length = 3;
+    db.index_options.filters = FilterChain(vec![CharFilter::Replace {
+        from: "&".to_string(),
+        to: " and ".to_string(),
+    }]);
+    db.insert(common::locode("GB:BWS", "Bath & Wells", json!({})));
+    db.insert(common::locode("GB:QXF", "Quixfield", json!({})));
+    let mut db = db.mk_fst();
+    db.remove("UN-LOCODE-gb:qxf");
+    let mut bytes = vec![];
+    write_snapshot(&db, &mut bytes).expect("write snapshot");
+    let restored = read_snapshot(bytes.as_slice()).expect("read snapshot");
+
+    assert_eq!(restored.custom_kinds, db.custom_kinds);
+    assert_eq!(restored.key_namespaces, db.key_namespaces);
+    assert_eq!(restored.index_options, db.index_options);
+    assert_eq!(restored.tombstones, db.tombstones);
+    // indexed with the filters and searched with them
+    let st = SearchTerm::from_raw_query("bath and wells".to_string(), None, 5, 0);
+    assert_eq!(restored.search(&st)[0].0, "UN-LOCODE-gb:bws");
+    let key = restored.parse_key("ACME-DEPOT-yard-1").expect("key");
+    assert_eq!(key.encoding, "ACME-DEPOT");
+}
+
+#[test]
+fn should_migrate_or_refuse_snapshot_versions() {
+    let db = common::load_fake_data();
+    let mut bytes = vec![];
+    write_snapshot(&db, &mut bytes).expect("write snapshot");
+    let mut snapshot: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(snapshot["version"], SNAPSHOT_VERSION);
+
+    // version 4 snapshots have no settings of the database
+    for field in [
+        "custom_kinds",
+        "key_namespaces",
+        "index_options",
+        "tombstones",
+    ] {
+        snapshot.as_object_mut().unwrap().remove(field);
+    }
+    snapshot["version"] = json!(4);
+    let restored = read_snapshot(snapshot.to_string().as_bytes()).expect("read v4 snapshot");
+    assert_eq!(restored.index_options, IndexOptions::default());
+
+    // snapshots from before versioning have no version, status, regions
+    // or redirects either
+    snapshot.as_object_mut().unwrap().remove("version");
+    snapshot.as_object_mut().unwrap().remove("redirects");
+    for loc in snapshot["locations"].as_array_mut().unwrap() {
+        loc.as_object_mut().unwrap().remove("regions");
+        if let Some(locode) = loc["data"].get_mut("Locd") {
+            locode.as_object_mut().unwrap().remove("status");
+        }
+    }
+    let restored = read_snapshot(snapshot.to_string().as_bytes()).expect("read old snapshot");
+    assert_eq!(restored.all.len(), db.all.len());
+    assert!(restored.redirects.is_empty());
+
+    snapshot["version"] = json!(SNAPSHOT_VERSION + 1);
+    let err = read_snapshot(snapshot.to_string().as_bytes())
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err.downcast_ref::<SnapshotError>(),
+        Some(SnapshotError::UnsupportedVersion(_))
+    ));
+    let err = read_snapshot("{".as_bytes()).err().unwrap();
+    assert!(matches!(
+        err.downcast_ref::<SnapshotError>(),
+        Some(SnapshotError::Decode(_))
+    ));
+}
+
+#[test]
+fn should_export_bulk_ndjson() {
+    let db = common::load_fake_data();
+    let mut bytes = vec![];
+    db.export_bulk_ndjson(&mut bytes).expect("export");
+    let lines = std::str::from_utf8(&bytes)
+        .expect("utf8")
+        .lines()
+        .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2 * db.all.len());
+
+    let action: serde_json::Value = serde_json::from_str(lines[0]).expect("action");
+    let doc: serde_json::Value = serde_json::from_str(lines[1]).expect("document");
+    assert_eq!(action["index"]["_id"], doc["key"]);
+}
+
+#[cfg(feature = "fs")]
+#[test]
+fn should_export_a_loadable_data_directory() {
+    let mut db = common::load_fake_data_unindexed();
+    db.insert(common::locode(
+        "GB:QXP",
+        "Quixport",
+        json!({ "lat": 51.123456789, "lon": -0.987654321 }),
+    ));
+    let db = db.mk_fst();
+    let dir = std::env::temp_dir().join(format!("berlin-export-{}", std::process::id()));
+    db.export(&dir, ExportFormat::Json).expect("export json");
+    db.export(&dir, ExportFormat::Csv).expect("export csv");
+    let reloaded = parse_data_files(dir.clone()).expect("reload");
+    let table = std::fs::read_to_string(dir.join("locations.csv")).expect("read csv");
+    let keys = |file: &str| {
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.join(file)).expect("read"))
+                .expect("json");
+        json.as_object()
+            .expect("object")
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    let (locodes, standard) = (keys("locode.json"), keys("MY-STANDARD.json"));
+    std::fs::remove_dir_all(&dir).expect("clean up");
+
+    assert!(locodes.iter().all(|k| k.starts_with("UN-LOCODE-")));
+    assert_eq!(standard, ["MY-STANDARD-my:1", "MY-STANDARD-my:2"]);
+    let quixport = reloaded.get("UN-LOCODE-gb:qxp").expect("quixport");
+    assert_eq!(
+        quixport.get_coordinates().map(|c| (c.lat, c.lon)),
+        Some((51.123456789, -0.987654321))
+    );
+
+    assert_eq!(reloaded.all.len(), db.all.len());
+    for (key, loc) in &db.all {
+        let as_json = |l| serde_json::to_value(l).expect("serialize");
+        assert_eq!(as_json(&reloaded.all[key]), as_json(loc), "{key}");
+    }
+    assert_eq!(table.lines().count(), db.all.len() + 1);
+}
+
+#[cfg(feature = "fs")]
+#[test]
+fn should_lint_a_data_directory() {
+    let db = common::load_fake_data();
+    let dir = std::env::temp_dir().join(format!("berlin-lint-{}", std::process::id()));
+    db.export(&dir, ExportFormat::Json).expect("export json");
+    let clean = lint_data_dir(&dir).expect("lint");
+    let locodes = dir.join("locode.json");
+    let mut json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&locodes).expect("read")).expect("json");
+    let locd = |id: &str, name: &str, lat: f64| {
+        common::locode_json(id, name, json!({"lat": lat, "lon": 25.0}))
+    };
+    json["BG:ZZA"] = locd("BG:ZZA", "Zzyzx", 95.0);
+    json["BG:ZZB"] = locd("BG:ZZB", "Zzyzx", 42.0);
+    json["BG:ZZC"] = locd("BG:ZZC", "PlovdÃ©v", 42.0);
+    std::fs::write(&locodes, json.to_string()).expect("write");
+    let report = lint_data_dir(&dir).expect("lint");
+    std::fs::write(&locodes, b"{\"BG:ZZA\": \"\xff\"}").expect("write");
+    let undecodable = lint_data_dir(&dir).expect("lint");
+    std::fs::remove_dir_all(&dir).expect("clean up");
+
+    assert!(!clean.has_errors(), "{clean:?}");
+    assert!(report.has_errors());
+    assert_eq!(
+        report.invalid_coordinates,
+        vec![Ustr::from("UN-LOCODE-bg:zza")]
+    );
+    assert_eq!(
+        report.duplicate_names,
+        vec![(
+            Ustr::from("zzyzx"),
+            vec![
+                Ustr::from("UN-LOCODE-bg:zza"),
+                Ustr::from("UN-LOCODE-bg:zzb")
+            ]
+        )]
+    );
+    assert_eq!(report.encoding_problems.len(), 1);
+    assert!(report.encoding_problems[0]
+        .1
+        .starts_with("BG:ZZC: mis-decoded UTF-8"));
+    assert_eq!(undecodable.encoding_problems.len(), 1);
+    assert!(undecodable.encoding_problems[0].1.starts_with("not UTF-8"));
+}
+
+#[test]
+fn should_report_code_list_merges() {
+    let mut db = LocationsDb::default();
+    db.insert(common::locode(
+        "GB:QXA",
+        "Quixby",
+        json!({"function_code": "1-------"}),
+    ));
+    db.insert(
+        location(json!({
+            "<c>": "QX-STANDARD",
+            "i": "GB:QXB",
+            "d": { "name": "Quixbury", "supercode": "GB", "subcode": "QXB" }
+        }))
+        .with_key_namespace("UN-LOCODE".into()),
+    );
+    let row = |subcode: &str, name: &str, coordinates: Option<&str>| CsvLocode {
+        change: String::new(),
+        country: "GB".to_string(),
+        subcode: subcode.to_string(),
+        name: name.to_string(),
+        name_wo_diacritics: name.to_string(),
+        subdivision_code: "QX".to_string(),
+        status: "RL".to_string(),
+        function: "1--4----".to_string(),
+        date: String::new(),
+        iata_code: String::new(),
+        coordinates: coordinates.map(str::to_string),
+    };
+    let outcomes = merge_data_list(
+        &mut db,
+        [
+            row("QXA", "Quixby Marsh", Some("5130N 00007W")),
+            row("QXB", "Quixbury", None),
+            row("QXC", "Quixcombe", None),
+        ]
+        .into_iter(),
+    );
+    let outcomes = outcomes.into_iter().map(|(_, o)| o).collect::<Vec<_>>();
+    assert_eq!(
+        outcomes,
+        [
+            MergeOutcome::Updated,
+            MergeOutcome::NotLocode,
+            MergeOutcome::NotFound
+        ]
+    );
+    let quixby = &db.all[&Ustr::from("UN-LOCODE-gb:qxa")];
+    assert_eq!(quixby.get_names()[0], "quixby marsh");
+    assert_eq!(quixby.get_subdiv().unwrap(), "qx");
+    assert!(quixby.get_coordinates().is_some());
+    assert!(quixby.words.contains(&Ustr::from("marsh")));
+
+    let unchanged = merge_data_list(&mut db, [row("QXA", "Quixby Marsh", None)].into_iter());
+    assert_eq!(unchanged[0].1, MergeOutcome::Unchanged);
+
+    // a blank IATA column keeps the code merged before
+    let with_iata = CsvLocode {
+        iata_code: "QXX".to_string(),
+        ..row("QXA", "Quixby Marsh", None)
+    };
+    merge_data_list(&mut db, [with_iata].into_iter());
+    let blank = merge_data_list(&mut db, [row("QXA", "Quixby Marsh", None)].into_iter());
+    assert_eq!(blank[0].1, MergeOutcome::Unchanged);
+    let LocData::Locd(quixby) = db.all[&Ustr::from("UN-LOCODE-gb:qxa")].data else {
+        panic!("not a LOCODE");
+    };
+    assert_eq!(quixby.airport_iata(), Some(Ustr::from("qxx")));
+}
+
+#[test]
+fn should_merge_code_lists_into_namespaced_locodes() {
+    let options = LoadOptions {
+        key_namespaces: vec![("UN-LOCODE".to_string(), "ACME-PORT".to_string())],
+        ..Default::default()
+    };
+    let codes = std::fs::File::open(data_dir().join("test-codes.json")).expect("open json");
+    let code_list = std::fs::File::open(data_dir().join("test-code-list.csv")).expect("open csv");
+    let db = parse_json_reader(LocationsDb::with_options(&options), codes).expect("load json");
+    let db = parse_csv_reader(db, code_list).expect("load csv");
+
+    let abercarn = db.get("ACME-PORT-gb:abc").expect("namespaced LOCODE");
+    assert!(abercarn.get_coordinates().is_some());
+    assert_eq!(abercarn.location_key().encoding, "ACME-PORT");
+    let key = db.parse_key("ACME-PORT-gb:abc").expect("key");
+    assert_eq!(
+        (key.encoding.as_str(), key.id.as_str()),
+        ("ACME-PORT", "gb:abc")
+    );
+    assert!(db.resolve_code("GBABC").is_some());
+}
+
+#[test]
+fn should_read_code_list_changes_and_cut_off_later_ones() {
+    let db = common::load_fake_data_unindexed();
+    let changed =
+        |db: &LocationsDb, key: &str| db.all.get(&Ustr::from(key)).map(|l| l.last_changed());
+    assert_eq!(
+        changed(&db, "UN-LOCODE-gb:bsi"),
+        Some(ChangeDate::new(2007, 1))
+    );
+    assert_eq!(
+        changed(&db, "UN-LOCODE-bg:dbd"),
+        Some(ChangeDate::new(2016, 7))
+    );
+    assert_eq!(changed(&db, "ISO-3166-1-gb"), Some(None));
+    assert_eq!(
+        ChangeDate::from_code_list("9512"),
+        ChangeDate::new(1995, 12)
+    );
+    assert_eq!(ChangeDate::from_code_list("0713"), None);
+    assert_eq!(ChangeDate::new(2016, 7).unwrap().to_code_list(), "1607");
+
+    let options = LoadOptions {
+        code_list_cutoff: ChangeDate::new(2010, 1),
+        ..Default::default()
+    };
+    let db = apply_load_options(db, &options).mk_fst();
+    assert!(changed(&db, "UN-LOCODE-bg:loz").is_some());
+    assert!(changed(&db, "UN-LOCODE-bg:dbd").is_none());
+    assert!(changed(&db, "UN-LOCODE-bg:da3").is_none());
+    let st = SearchTerm::from_raw_query("damyanitsa".to_string(), None, 5, 1);
+    assert!(db.search(&st).is_empty());
+
+    let mut db = common::load_fake_data_unindexed();
+    let LocData::Locd(lozarevo) = db.all[&Ustr::from("UN-LOCODE-bg:loz")].data else {
+        panic!("not a LOCODE");
+    };
+    let mut row = lozarevo.to_csv();
+    assert_eq!((row.date.as_str(), row.change.as_str()), ("1001", ""));
+    row.change = "X".to_string();
+    merge_data_list(&mut db, [row].into_iter());
+    let lozarevo = &db.all[&Ustr::from("UN-LOCODE-bg:loz")];
+    assert_eq!(lozarevo.code_list_change(), Some(CodeListChange::Removed));
+    for mark in ["+", "#", "|", "X"] {
+        assert_eq!(CodeListChange::parse(mark).map(|c| c.mark()), Some(mark));
+    }
+}
+
+#[test]
+fn should_write_bulk_results_as_csv() {
+    let db = common::load_fake_data();
+    let queries = ["abercorn", "zzqqxxyy", "lozarevo"].map(str::to_string);
+    let mut out = vec![];
+    bulk::write_csv(
+        &db,
+        queries,
+        |q| SearchTerm::from_raw_query(q, None, 1, 2),
+        &mut out,
+    )
+    .expect("write csv");
+    let mut reader = csv::Reader::from_reader(out.as_slice());
+    let headers = reader.headers().unwrap().clone();
+    assert_eq!(
+        headers.iter().collect::<Vec<_>>(),
+        [
+            "input",
+            "matched_key",
+            "name",
+            "state",
+            "subdiv",
+            "score",
+            "lat",
+            "lon"
+        ]
+    );
+    let rows = reader.records().map(|r| r.unwrap()).collect::<Vec<_>>();
+    assert_eq!(rows.len(), 3);
+    assert_eq!(&rows[0][1], "UN-LOCODE-gb:abc");
+    assert_eq!(&rows[0][3], "gb");
+    assert!(!rows[0][6].is_empty());
+    assert_eq!(&rows[1][0], "zzqqxxyy");
+    assert_eq!(&rows[1][1], "");
+    assert_eq!(&rows[2][1], "UN-LOCODE-bg:loz");
+}
+
+#[test]
+fn should_stream_ndjson_results() {
+    let db = common::load_fake_data();
+    let input = "{\"q\": \"abercorn\", \"state\": \"gb\"}\n\nnot json\n{\"q\": \"lozarevo\", \"limit\": 2}\n";
+    let defaults = bulk::NdjsonQuery {
+        q: String::new(),
+        state: None,
+        limit: Some(5),
+        lev_distance: Some(2),
+    };
+    let mut out = vec![];
+    bulk::search_ndjson(&db, input.as_bytes(), &mut out, &defaults).expect("search");
+    let lines = std::str::from_utf8(&out)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0]["q"], "abercorn");
+    assert_eq!(lines[0]["results"][0]["key"], "UN-LOCODE-gb:abc");
+    assert!(lines[1]["error"].is_string());
+    assert_eq!(lines[2]["results"][0]["key"], "UN-LOCODE-bg:loz");
+}
+
+#[test]
+fn should_geocode_a_csv_column() {
+    let db = common::load_fake_data();
+    let input = "id,place\n1,Abercorn\n2,zzqqxxyy\n3,\"Lozarevo, Bulgaria\"\n";
+    let mut out = vec![];
+    let options = bulk::GeocodeOptions::default();
+    bulk::geocode_csv(&db, input.as_bytes(), "place", &options, &mut out).expect("geocode");
+    let mut reader = csv::Reader::from_reader(out.as_slice());
+    assert_eq!(
+        reader.headers().unwrap().iter().collect::<Vec<_>>(),
+        [
+            "id",
+            "place",
+            "berlin_key",
+            "berlin_score",
+            "berlin_country"
+        ]
+    );
+    let rows = reader.records().map(|r| r.unwrap()).collect::<Vec<_>>();
+    assert_eq!(&rows[0][2], "UN-LOCODE-gb:abc");
+    assert_eq!(&rows[0][4], "gb");
+    assert_eq!(&rows[1][2], "");
+    assert_eq!(&rows[2][0], "3");
+    assert_eq!(&rows[2][4], "bg");
+
+    let missing = bulk::geocode_csv(&db, input.as_bytes(), "town", &options, vec![]);
+    assert!(missing.is_err());
+}
+
+#[cfg(feature = "fetch")]
+#[test]
+fn should_fetch_and_verify_releases() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    use berlin_core::fetch::{DataFile, Release};
+    use sha2::{Digest, Sha256};
+
+    let body = std::fs::read(data_dir().join("test-code-list.csv")).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let served = body.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().take(2) {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+                served.len()
+            );
+            stream.write_all(header.as_bytes()).unwrap();
+            stream.write_all(&served).unwrap();
+        }
+    });
+    let release = |sha256: &str| Release {
+        files: vec![DataFile {
+            name: "code-list_csv.csv".to_string(),
+            url: format!("http://{addr}/code-list.csv"),
+            sha256: Some(sha256.to_string()),
+        }],
+    };
+    let dir = std::env::temp_dir().join(format!("berlin-fetch-{}", std::process::id()));
+    assert!(release("00").fetch(&dir).is_err());
+    assert!(!dir.exists());
+
+    let sha256 = format!("{:x}", Sha256::digest(&body));
+    let fetched = release(&sha256).fetch(&dir);
+    let verified = release(&sha256).verify(&dir);
+    let written = std::fs::read(dir.join("code-list_csv.csv"));
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(fetched.unwrap(), [dir.join("code-list_csv.csv")]);
+    assert!(verified.is_ok());
+    assert_eq!(written.unwrap(), body);
+}
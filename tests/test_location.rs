@@ -0,0 +1,145 @@
+mod common;
+
+use serde_json::json;
+
+use ustr::Ustr;
+
+use berlin_core::key::LocationKey;
+use berlin_core::location::DisplayStyle;
+use berlin_core::search::SearchTerm;
+
+use common::{location, locode};
+
+#[test]
+fn should_keep_unrecognised_fields_as_extra() {
+    let loc = locode(
+        "BG:ZZX",
+        "Zzyzxovo",
+        json!({
+            "name:de": "Zzyzxowo",
+            "name:es": "Zzyzxova",
+            "name:fr": "Zzyzxowo",
+            "population": 4200,
+            "wikidata": "Q1"
+        }),
+    );
+    assert_eq!(loc.get_names()[0], "zzyzxovo");
+    let alt_names = loc.alt_names.iter().map(|(l, n)| (l.as_str(), n.as_str()));
+    assert_eq!(
+        alt_names.collect::<Vec<_>>(),
+        [("de", "zzyzxowo"), ("es", "zzyzxova")]
+    );
+    assert_eq!(
+        serde_json::Value::Object(loc.extra.clone()),
+        json!({ "population": 4200, "wikidata": "Q1" })
+    );
+    let serialized = serde_json::to_value(&loc).unwrap();
+    assert_eq!(serialized["extra"]["population"], 4200);
+}
+
+#[test]
+fn should_search_a_location_before_indexing() {
+    let mut loc = locode("BG:ZZY", "Zzyzxburg", json!({}));
+    let st = SearchTerm::from_raw_query("Zzyzxburg".to_string(), None, 5, 0);
+    let uncached = loc.search(&st).map(|s| s.score);
+    assert!(uncached.is_some());
+    loc.cache_searchable();
+    assert_eq!(loc.search(&st).map(|s| s.score), uncached);
+}
+
+#[test]
+fn should_parse_location_keys() {
+    let key = LocationKey::parse("UN-LOCODE-gb:abc").unwrap();
+    assert_eq!(key.encoding, "UN-LOCODE");
+    assert_eq!(key.country(), Some("gb"));
+    assert_eq!(key.code(), "abc");
+    assert_eq!(key.to_string(), "UN-LOCODE-gb:abc");
+
+    let state = "ISO-3166-1-gb".parse::<LocationKey>().unwrap();
+    assert_eq!((state.country(), state.code()), (Some("gb"), "gb"));
+    let generic = LocationKey::parse("MY-STANDARD-my:1").unwrap();
+    assert_eq!(
+        (generic.encoding.as_str(), generic.id.as_str()),
+        ("MY-STANDARD", "my:1")
+    );
+    assert!(LocationKey::parse("gb:abc").is_err());
+    // known encodings are matched whole, so ids may contain dashes
+    let region = LocationKey::parse("REGION-sunny-coast").unwrap();
+    assert_eq!(
+        (region.encoding.as_str(), region.id.as_str()),
+        ("REGION", "sunny-coast")
+    );
+    let depot = LocationKey::parse_with("ACME-DEPOT-yard-1", ["ACME-DEPOT"]).unwrap();
+    assert_eq!(
+        (depot.encoding.as_str(), depot.id.as_str()),
+        ("ACME-DEPOT", "yard-1")
+    );
+
+    let db = common::load_fake_data();
+    let loc = db.get(&key).expect("location by key");
+    assert_eq!(loc.location_key(), key);
+    assert_eq!(db.get("UN-LOCODE-gb:abc").map(|l| l.key), Some(loc.key));
+    assert_eq!(db.resolve(&key).map(|r| r.location.key), Some(loc.key));
+    assert!(db.bbox(&state).is_some());
+}
+
+#[test]
+fn should_format_display_names() {
+    let db = common::load_fake_data();
+    let abercarn = &db.all[&Ustr::from("UN-LOCODE-gb:abc")];
+    assert_eq!(abercarn.display_name(&db, DisplayStyle::Name), "Abercarn");
+    assert_eq!(
+        abercarn.display_name(&db, DisplayStyle::Hierarchy),
+        "Abercarn, Caerphilly, GB"
+    );
+    assert_eq!(
+        abercarn.display_name(&db, DisplayStyle::Code),
+        "Abercarn (GB ABC)"
+    );
+
+    let gb = &db.all[&Ustr::from("ISO-3166-1-gb")];
+    assert_eq!(
+        gb.display_name(&db, DisplayStyle::Code),
+        "United Kingdom of Great Britain and Northern Ireland (GB)"
+    );
+}
+
+#[test]
+fn should_read_coordinates_from_json() {
+    let state = location(json!({
+        "<c>": "ISO-3166-1",
+        "i": "ZZ",
+        "d": {
+            "name": "Zedland",
+            "short": "Zedland",
+            "alpha2": "ZZ",
+            "alpha3": "ZZZ",
+            "continent": "Europe",
+            "c": "5130N 00007W"
+        }
+    }));
+    let coordinates = state.get_coordinates().unwrap();
+    assert!((coordinates.lat - 51.5).abs() < 1e-3);
+    assert!(state.extra.is_empty());
+    let locode = locode(
+        "ZZ:QQQ",
+        "Qoqo",
+        json!({"function_code": "1-------", "lat": 10.25, "lon": "-3.5"}),
+    );
+    let coordinates = locode.get_coordinates().unwrap();
+    assert_eq!((coordinates.lat, coordinates.lon), (10.25, -3.5));
+    let airport = location(json!({
+        "<c>": "IATA",
+        "i": "QQQ",
+        "d": {
+            "name": "Qoqo Airfield",
+            "iata": "QQQ",
+            "type": "small_airport",
+            "country": "ZZ",
+            "region": "ZZ-QQ",
+            "x": -3.5,
+            "y": 10.25
+        }
+    }));
+    assert_eq!(airport.get_coordinates(), locode.get_coordinates());
+}
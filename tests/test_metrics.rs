@@ -0,0 +1,76 @@
+#![cfg(feature = "metrics")]
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use berlin_core::metrics;
+use berlin_core::opentelemetry::metrics::{
+    Counter, Histogram, HistogramBuilder, InstrumentBuilder, InstrumentProvider, Meter,
+    MeterProvider, SyncInstrument,
+};
+use berlin_core::opentelemetry::{global, InstrumentationScope, KeyValue};
+use berlin_core::search::SearchTerm;
+
+#[derive(Clone, Default)]
+struct Recorded(Arc<Mutex<Vec<(String, f64)>>>);
+
+impl Recorded {
+    fn push(&self, name: &str, value: f64) {
+        self.0.lock().unwrap().push((name.to_string(), value));
+    }
+}
+
+struct Instrument(String, Recorded);
+
+impl SyncInstrument<u64> for Instrument {
+    fn measure(&self, value: u64, _attributes: &[KeyValue]) {
+        self.1.push(&self.0, value as f64);
+    }
+}
+
+impl SyncInstrument<f64> for Instrument {
+    fn measure(&self, value: f64, _attributes: &[KeyValue]) {
+        self.1.push(&self.0, value);
+    }
+}
+
+impl InstrumentProvider for Recorded {
+    fn u64_counter(&self, builder: InstrumentBuilder<'_, Counter<u64>>) -> Counter<u64> {
+        Counter::new(Arc::new(Instrument(builder.name.into(), self.clone())))
+    }
+    fn u64_histogram(&self, builder: HistogramBuilder<'_, Histogram<u64>>) -> Histogram<u64> {
+        Histogram::new(Arc::new(Instrument(builder.name.into(), self.clone())))
+    }
+    fn f64_histogram(&self, builder: HistogramBuilder<'_, Histogram<f64>>) -> Histogram<f64> {
+        Histogram::new(Arc::new(Instrument(builder.name.into(), self.clone())))
+    }
+}
+
+impl MeterProvider for Recorded {
+    fn meter_with_scope(&self, _scope: InstrumentationScope) -> Meter {
+        Meter::new(Arc::new(self.clone()))
+    }
+}
+
+#[test]
+fn should_record_search_metrics() {
+    let recorded = Recorded::default();
+    global::set_meter_provider(recorded.clone());
+    let db = common::load_fake_data();
+    let st = SearchTerm::from_raw_query("abercorn".to_string(), None, 5, 3);
+    db.search(&st);
+
+    let recorded = recorded.0.lock().unwrap();
+    let names = recorded.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>();
+    for name in [
+        metrics::SEARCHES,
+        metrics::PRE_FILTER_SECONDS,
+        metrics::SCORE_SECONDS,
+        metrics::RANK_SECONDS,
+        metrics::CANDIDATES,
+    ] {
+        assert!(names.contains(&name), "{name} not recorded");
+    }
+    assert!(recorded.contains(&(metrics::SCORED_CANDIDATES.to_string(), 1.)));
+}
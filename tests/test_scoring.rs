@@ -0,0 +1,580 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rstest::*;
+use serde_json::json;
+
+use berlin_core::config::{
+    standard_stop_phrases, CaseLocale, LevBreakpoints, Propagation, SearchConfig,
+};
+use berlin_core::eval::{evaluate, EvalOptions, LabelledQuery};
+use berlin_core::explain::{BoostKind, Explanation};
+use berlin_core::feedback::FeedbackStore;
+use berlin_core::filters::{CharFilter, FilterChain};
+use berlin_core::location::CsvWeight;
+use berlin_core::locations_db::{parse_weights, LocationsDb};
+use berlin_core::search::SearchTerm;
+use berlin_core::similarity::{JaroWinkler, KeyboardLevenshtein, Similarity, TokenSortRatio};
+use berlin_core::stats::{SearchStats, SearchStatsSnapshot};
+use berlin_core::ustr::Ustr;
+use berlin_core::{fold_case, normalize, normalize_with};
+
+#[fixture]
+#[once]
+fn fake_data() -> LocationsDb {
+    common::load_fake_data()
+}
+
+fn search_with(db: &LocationsDb, query: &str, config: SearchConfig) -> Vec<(String, i64)> {
+    let st = SearchTerm::with_config(query.to_string(), None, 5, 3, config);
+    db.search(&st)
+        .into_iter()
+        .map(|(key, score)| (key.to_string(), score.score))
+        .collect()
+}
+
+#[rstest]
+fn should_report_slow_queries(fake_data: &LocationsDb) {
+    let reported = Arc::new(Mutex::new(vec![]));
+    let sink = reported.clone();
+    fake_data.slow_query_log.set(
+        Duration::ZERO,
+        Some(Arc::new(move |q| {
+            if q.term.raw == "abercorn slowly" {
+                let stages = q.pre_filter + q.score + q.rank;
+                sink.lock()
+                    .unwrap()
+                    .push((q.candidates, q.scored, stages <= q.total));
+            }
+        })),
+    );
+    fake_data.search(&SearchTerm::from_raw_query(
+        "abercorn slowly".into(),
+        None,
+        5,
+        3,
+    ));
+    fake_data.slow_query_log.set(Duration::from_secs(60), None);
+    fake_data.search(&SearchTerm::from_raw_query(
+        "abercorn slowly".into(),
+        None,
+        5,
+        3,
+    ));
+    fake_data.slow_query_log.clear();
+
+    let reported = reported.lock().unwrap();
+    assert_eq!(reported.len(), 1);
+    let (candidates, scored, timed) = reported[0];
+    assert!(candidates >= scored && scored > 0 && timed);
+}
+
+#[test]
+fn should_count_searches_when_stats_are_attached() {
+    let mut db = common::load_fake_data();
+    let stats = Arc::new(SearchStats::default());
+    db.stats = Some(stats.clone());
+    db.search(&SearchTerm::from_raw_query("abercorn".into(), None, 5, 3));
+    db.search(&SearchTerm::from_raw_query(
+        "burgas bulgaria".into(),
+        None,
+        1,
+        3,
+    ));
+    db.search(&SearchTerm::from_raw_query(
+        "gb:abc xyzzy".into(),
+        None,
+        5,
+        0,
+    ));
+
+    let counted = stats.reset();
+    assert_eq!(counted.queries, 3);
+    assert_eq!(counted.truncated, 1);
+    assert!(counted.average_candidates > 0.);
+    assert!(counted.word_index_hits > 0);
+    assert_eq!(stats.snapshot(), SearchStatsSnapshot::default());
+}
+
+#[rstest]
+fn should_apply_boolean_operators(fake_data: &LocationsDb) {
+    let keys = |query: &str| {
+        let st = SearchTerm::from_raw_query(query.to_string(), None, 10, 2);
+        let keys = fake_data
+            .search(&st)
+            .into_iter()
+            .map(|(k, _)| k.to_string());
+        keys.collect::<Vec<_>>()
+    };
+    let st =
+        SearchTerm::from_raw_query("burgas OR bognor regis NOT west sussex".into(), None, 5, 2);
+    let boolean = st.boolean.as_ref().expect("boolean query");
+    assert_eq!(boolean.any_of, [vec!["burgas"], vec!["bognor regis"]]);
+    assert_eq!(boolean.none_of, ["west sussex"]);
+    assert_eq!(
+        st.parsed()
+            .fuzzy
+            .iter()
+            .filter(|t| t.term == "west")
+            .count(),
+        0
+    );
+
+    assert_eq!(keys("bognor regis AND west sussex"), ["UN-LOCODE-gb:bsi"]);
+    assert!(keys("bognor regis AND bulgaria").is_empty());
+    let either = keys("burgas OR bognor regis");
+    assert!(either.contains(&"ISO-3166-2-bg:02".to_string()));
+    assert!(either.contains(&"UN-LOCODE-gb:bsi".to_string()));
+    assert_eq!(
+        keys("burgas OR bognor regis NOT west sussex"),
+        ["ISO-3166-2-bg:02"]
+    );
+    // operators are uppercase only
+    assert!(
+        SearchTerm::from_raw_query("bognor regis and west sussex".into(), None, 5, 2)
+            .boolean
+            .is_none()
+    );
+}
+
+#[rstest]
+fn should_score_transpositions_higher_with_jaro_winkler(fake_data: &LocationsDb) {
+    let levenshtein = search_with(fake_data, "abrecarn", SearchConfig::default());
+    let config = SearchConfig {
+        similarity: Arc::new(JaroWinkler),
+        ..Default::default()
+    };
+    let jaro_winkler = search_with(fake_data, "abrecarn", config);
+    assert_eq!(jaro_winkler[0].0, "UN-LOCODE-gb:abc");
+    assert!(levenshtein.first().is_none_or(|r| r.1 < jaro_winkler[0].1));
+}
+
+#[test]
+fn should_ignore_word_order_with_token_sort_ratio() {
+    assert_eq!(
+        TokenSortRatio.similarity("regis bognor", "bognor regis"),
+        1.
+    );
+}
+
+#[test]
+fn should_favour_adjacent_key_typos() {
+    let adjacent = KeyboardLevenshtein.similarity("birmingjam", "birmingham");
+    let distant = KeyboardLevenshtein.similarity("birmingpam", "birmingham");
+    assert!(adjacent > distant);
+    assert_eq!(KeyboardLevenshtein.similarity("abercarn", "abercarn"), 1.);
+}
+
+#[rstest]
+fn should_strip_stop_phrases(fake_data: &LocationsDb) {
+    let config = SearchConfig {
+        stop_phrases: standard_stop_phrases(),
+        ..Default::default()
+    };
+    let st = SearchTerm::with_config("the Port of Abercarn".to_string(), None, 5, 3, config);
+    let results = fake_data.search(&st);
+    assert_eq!(results[0].0, "UN-LOCODE-gb:abc");
+    // the offset is that of the remaining word
+    assert_eq!(
+        (results[0].1.offset.start, results[0].1.offset.end),
+        (12, 20)
+    );
+    // stop phrases are opt-in
+    let st = SearchTerm::from_raw_query("the Port of Abercarn".to_string(), None, 5, 3);
+    let stop_words = st.parsed().stop_words.into_iter().map(|t| t.term);
+    assert_eq!(stop_words.collect::<Vec<_>>(), ["the", "of"]);
+}
+
+#[rstest]
+fn should_expose_the_parsed_query(fake_data: &LocationsDb) {
+    // exact terms are those interned by loading the data
+    let _ = fake_data;
+    let config = SearchConfig {
+        stop_phrases: standard_stop_phrases(),
+        ..Default::default()
+    };
+    let query = "the Port of Abercarn GB:ABC".to_string();
+    let st = SearchTerm::with_config(query, None, 5, 0, config);
+    let parsed = st.parsed();
+    let terms = |terms: &[berlin_core::search::ParsedTerm]| {
+        terms
+            .iter()
+            .map(|t| (t.term.clone(), t.offset.start, t.offset.end))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(terms(&parsed.codes), vec![("gb:abc".to_string(), 21, 27)]);
+    assert_eq!(terms(&parsed.exact), vec![("abercarn".to_string(), 12, 20)]);
+    assert!(parsed.fuzzy.is_empty());
+    let stop_words = parsed.stop_words.iter().map(|t| t.term.as_str());
+    assert_eq!(stop_words.collect::<Vec<_>>(), vec!["the", "port", "of"]);
+    let json = serde_json::to_value(&parsed).unwrap();
+    assert_eq!(json["exact"][0]["offset"]["start"], 12);
+}
+
+#[test]
+fn should_carry_offsets_from_tokenisation() {
+    let offsets = |st: &SearchTerm| {
+        st.tokens
+            .iter()
+            .map(|t| (t.start, t.end))
+            .collect::<Vec<_>>()
+    };
+    let mut st = SearchTerm::from_raw_query("abercarn to abercarn".to_string(), None, 5, 0);
+    assert_eq!(offsets(&st), vec![(0, 8), (12, 20)]);
+    assert!(st.add_code(Ustr::from("gb:abc")).is_err());
+    assert!(st.add_code(Ustr::from("abercarn")).is_ok());
+    // offsets are in bytes, where filters leave non-ASCII letters
+    let umlauts = FilterChain(vec![CharFilter::Replace {
+        from: "oe".to_string(),
+        to: "ö".to_string(),
+    }]);
+    let mut st = SearchTerm::from_raw_query("Schoenfeld abercarn".to_string(), None, 5, 0)
+        .with_filters(&umlauts);
+    assert_eq!(st.normalized, "schönfeld abercarn");
+    assert_eq!(offsets(&st), vec![(0, 10), (11, 19)]);
+    st.add_code(Ustr::from("abercarn")).unwrap();
+    assert_eq!(
+        st.codes.last().map(|c| (c.offset.start, c.offset.end)),
+        Some((11, 19))
+    );
+}
+
+#[rstest]
+fn should_stem_possessives_and_plurals(fake_data: &LocationsDb) {
+    let query = |q: &str, stem| {
+        let config = SearchConfig {
+            stem,
+            ..Default::default()
+        };
+        let st = SearchTerm::with_config(q.to_string(), None, 5, 0, config);
+        fake_data.search(&st)
+    };
+    assert_eq!(query("Abercarn's", true)[0].0, "UN-LOCODE-gb:abc");
+    assert_eq!(query("Abercarns", true)[0].0, "UN-LOCODE-gb:abc");
+    assert!(query("Abercarn's", false).is_empty());
+    assert!(!SearchConfig::default().stem);
+}
+
+#[rstest]
+#[case("GB ABC", (0, 6))]
+#[case("gbabc", (0, 5))]
+#[case("port GB:ABC", (5, 11))]
+fn should_match_country_prefixed_codes(
+    fake_data: &LocationsDb,
+    #[case] query: &str,
+    #[case] offset: (usize, usize),
+) {
+    let st = SearchTerm::from_raw_query(query.to_string(), None, 5, 0);
+    let results = fake_data.search(&st);
+    assert_eq!(results[0].0, "UN-LOCODE-gb:abc");
+    assert_eq!((results[0].1.offset.start, results[0].1.offset.end), offset);
+}
+
+#[test]
+fn should_limit_ngram_generation() {
+    let ngrams = |config: SearchConfig| {
+        let query = "one two three four five six".to_string();
+        let st = SearchTerm::with_config(query, None, 5, 0, config);
+        let parsed = st.parsed();
+        let terms = parsed.fuzzy.iter().chain(&parsed.exact);
+        terms.filter(|t| t.term.contains(' ')).count()
+    };
+    let config = |doublets, triplets, max_ngrams| SearchConfig {
+        doublets,
+        triplets,
+        max_ngrams,
+        ..Default::default()
+    };
+    assert_eq!(ngrams(config(false, false, None)), 0);
+    assert_eq!(ngrams(config(true, false, None)), 5);
+    assert_eq!(ngrams(config(true, false, Some(2))), 2);
+}
+
+#[rstest]
+fn should_require_coverage_of_query(fake_data: &LocationsDb) {
+    let query = "my flight leaves from abercarn tomorrow morning";
+    assert!(!search_with(fake_data, query, SearchConfig::default()).is_empty());
+    let config = SearchConfig {
+        min_coverage: Some(0.5),
+        ..Default::default()
+    };
+    assert!(search_with(fake_data, query, config).is_empty());
+    let config = SearchConfig {
+        min_coverage: Some(0.5),
+        ..Default::default()
+    };
+    assert_eq!(
+        search_with(fake_data, "abercarn gb", config)[0].0,
+        "UN-LOCODE-gb:abc"
+    );
+}
+
+#[rstest]
+fn should_return_unmatched_remainder(fake_data: &LocationsDb) {
+    let st = SearchTerm::from_raw_query("flights to Abercarn next week".to_string(), None, 1, 0);
+    let results = fake_data.search(&st);
+    let remainder = st.remainder(results.iter().map(|(_, score)| score.offset));
+    let text = remainder
+        .iter()
+        .map(|o| &st.normalized[o.start..o.end])
+        .collect::<Vec<_>>();
+    assert_eq!(text, vec!["flights to", "next week"]);
+}
+
+#[rstest]
+fn should_propagate_evidence_from_children(fake_data: &LocationsDb) {
+    let query = "bulgaria lozarevo lyuliakovo";
+    let single_pass = search_with(fake_data, query, SearchConfig::default());
+    let config = SearchConfig {
+        propagation: Some(Propagation::default()),
+        ..Default::default()
+    };
+    let propagated = search_with(fake_data, query, config);
+    let state_score = |results: &[(String, i64)]| {
+        results
+            .iter()
+            .find(|(key, _)| key == "ISO-3166-1-bg")
+            .map(|(_, score)| *score)
+            .unwrap()
+    };
+    assert_ne!(single_pass[0].0, "ISO-3166-1-bg");
+    assert_eq!(propagated[0].0, "ISO-3166-1-bg");
+    assert!(state_score(&propagated) > state_score(&single_pass));
+}
+
+#[rstest]
+fn should_skip_the_results_graph(fake_data: &LocationsDb) {
+    let query = "bulgaria lozarevo lyuliakovo";
+    let linked = search_with(fake_data, query, SearchConfig::default());
+    let config = SearchConfig {
+        skip_graph: true,
+        ..Default::default()
+    };
+    let unlinked = search_with(fake_data, query, config);
+    assert_eq!(linked.len(), unlinked.len());
+    assert!(unlinked[0].1 < linked[0].1);
+    let single = search_with(fake_data, "lozarevo", SearchConfig::default());
+    assert_eq!(single.len(), 1);
+}
+
+#[rstest]
+fn should_explain_graph_boosts(fake_data: &LocationsDb) {
+    let st = SearchTerm::from_raw_query("bulgaria lozarevo".to_string(), None, 5, 3);
+    let results = fake_data.search(&st);
+    let explained = fake_data.explain(&st);
+    assert_eq!(results.len(), explained.len());
+    for (result, explanation) in results.iter().zip(&explained) {
+        assert_eq!(result.0, explanation.key);
+        assert_eq!(result.1.score, explanation.score.score);
+        let boosted = explanation.base + explanation.boosts.iter().map(|b| b.amount).sum::<i64>();
+        assert_eq!(explanation.score.score, boosted.max(explanation.base));
+    }
+    let lozarevo = explained
+        .iter()
+        .find(|e| e.key == "UN-LOCODE-bg:loz")
+        .unwrap();
+    assert_eq!(lozarevo.boosts[0].kind, BoostKind::Parent);
+    assert_eq!(lozarevo.boosts[0].source, "ISO-3166-1-bg");
+    assert!(schemars::schema_for!(Explanation).schema.object.is_some());
+}
+
+#[rstest]
+fn should_stream_every_match_without_the_limit(fake_data: &LocationsDb) {
+    let st = SearchTerm::from_raw_query("aber".to_string(), None, 1, 2);
+    let all = fake_data.search_all(&st, None).collect::<Vec<_>>();
+    assert!(all.len() > 1);
+    assert_eq!(fake_data.search(&st).len(), 1);
+    let unlimited = SearchTerm::from_raw_query("aber".to_string(), None, 0, 2);
+    for (key, _) in fake_data.search(&unlimited) {
+        assert!(all.iter().any(|(k, _)| *k == key), "{key}");
+    }
+    let keys = all.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>();
+    assert!(keys.windows(2).all(|w| w[0] < w[1]));
+
+    let best = all.iter().map(|(_, s)| s.score).max().unwrap();
+    let above = fake_data.search_all(&st, Some(best - 1));
+    assert!(above.map(|(_, s)| s.score).all(|s| s == best));
+    assert_eq!(fake_data.search_all(&st, None).take(1).count(), 1);
+}
+
+#[test]
+fn should_configure_and_report_lev_breakpoints() {
+    let mut db = common::load_fake_data_unindexed();
+    db.insert(common::locode("GB:LPG", "Llanfairpwllgwyngyll", json!({})));
+    let db = db.mk_fst();
+    let term = |query: &str, lev_dist, lev_breakpoints| {
+        let config = SearchConfig {
+            lev_breakpoints,
+            ..Default::default()
+        };
+        SearchTerm::with_config(query.to_string(), None, 5, lev_dist, config)
+    };
+    let keys = |st: &SearchTerm| db.search(st).into_iter().map(|(k, _)| k.to_string());
+
+    // two edits in twenty letters, beyond the default distance for them
+    let st = term("llanfeirpwllgwingyll", 2, LevBreakpoints::default());
+    assert_eq!(keys(&st).count(), 0);
+    let clamps = st.lev_clamps();
+    assert_eq!(clamps.len(), 1);
+    assert_eq!(
+        (clamps[0].term.as_str(), clamps[0].distance),
+        ("llanfeirpwllgwingyll", 1)
+    );
+    let welsh = LevBreakpoints {
+        lev_2_length_max: 30,
+        ..Default::default()
+    };
+    let st = term("llanfeirpwllgwingyll", 2, welsh);
+    assert!(st.lev_clamps().is_empty());
+    assert_eq!(keys(&st).collect::<Vec<_>>(), ["UN-LOCODE-gb:lpg"]);
+
+    let st = term("llanfairpwllgwyngyli", 3, LevBreakpoints::default());
+    let explained = db.explain(&st);
+    assert_eq!(explained[0].key, "UN-LOCODE-gb:lpg");
+    assert_eq!(explained[0].lev_clamps[0].distance, 1);
+}
+
+#[rstest]
+fn should_compute_ranking_features(fake_data: &LocationsDb) {
+    let st = SearchTerm::from_raw_query("bulgaria lozarevo".to_string(), None, 5, 3);
+    let results = fake_data.search_with_features(&st);
+    let lozarevo = results
+        .iter()
+        .find(|r| r.key == "UN-LOCODE-bg:loz")
+        .unwrap();
+    assert!(lozarevo.features.exact_matches > 0);
+    assert!(lozarevo.features.parent_boost > 0);
+    assert!(!lozarevo.features.code_match);
+    assert_eq!(lozarevo.features.name_length_delta, 0);
+    let st = SearchTerm::from_raw_query("abercorn".to_string(), None, 1, 3);
+    let abercarn = &fake_data.search_with_features(&st)[0].features;
+    assert_eq!(abercarn.exact_matches, 0);
+    assert!(abercarn.fuzzy_similarity > 0.8 && abercarn.fuzzy_similarity < 1.);
+}
+
+#[rstest]
+fn should_boost_previously_picked_results(fake_data: &LocationsDb) {
+    let st = SearchTerm::from_raw_query("bulgaria lozarevo".to_string(), None, 5, 3);
+    let results = fake_data.search(&st);
+    let (picked, score) = results[1];
+    let gap = results[0].1.score - score.score;
+    let mut store = FeedbackStore {
+        boost_per_pick: gap + 1,
+        max_boost: gap + 1,
+        ..Default::default()
+    };
+    store.record(&st, picked);
+    let similar = SearchTerm::from_raw_query("Lozarevo, Bulgaria".to_string(), None, 5, 3);
+    assert_eq!(store.search(fake_data, &similar)[0].0, picked);
+    let first = SearchTerm::from_raw_query("Lozarevo, Bulgaria".to_string(), None, 1, 3);
+    let top = store.search(fake_data, &first);
+    assert_eq!(top.len(), 1);
+    assert_eq!(top[0].0, picked);
+    let mut json = vec![];
+    store.write(&mut json).unwrap();
+    assert_eq!(FeedbackStore::read(json.as_slice()).unwrap(), store);
+}
+
+#[rstest]
+fn should_boost_configured_countries(fake_data: &LocationsDb) {
+    let query = "lozarevo or abercarn";
+    let unboosted = search_with(fake_data, query, SearchConfig::default());
+    assert_eq!(unboosted[0].1, unboosted[1].1);
+    for (country, expected) in [("gb", "UN-LOCODE-gb:abc"), ("bg", "UN-LOCODE-bg:loz")] {
+        let config = SearchConfig {
+            country_boosts: [(Ustr::from(country), 1.2)].into_iter().collect(),
+            ..Default::default()
+        };
+        let boosted = search_with(fake_data, query, config);
+        assert_eq!(boosted.len(), unboosted.len());
+        assert_eq!(boosted[0].0, expected);
+        assert_eq!(boosted[0].1, unboosted[0].1 * 6 / 5);
+    }
+}
+
+#[rstest]
+fn should_histogram_candidate_scores(fake_data: &LocationsDb) {
+    let st = SearchTerm::from_raw_query("abercorn".to_string(), None, 1, 3);
+    let histogram = fake_data.score_histogram(&st, 100);
+    let scored = histogram.buckets.iter().map(|(_, n)| n).sum::<usize>();
+    assert!(scored >= fake_data.search(&st).len());
+    assert!(histogram.buckets.windows(2).all(|w| w[0].0 < w[1].0));
+    assert!(histogram
+        .buckets
+        .iter()
+        .all(|(lowest, _)| lowest % 100 == 0));
+    assert_eq!(histogram.count_above(i64::MIN), scored);
+    assert!(histogram.count_above(histogram.inclusion_threshold) >= 1);
+}
+
+#[rstest]
+#[case("İstanbul", "ISTANBUL")]
+#[case("ıspir", "ISPIR")]
+#[case("Straße", "STRASSE")]
+#[case("ΣΊΣΥΦΟΣ", "σίσυφος")]
+fn should_fold_case_before_transliterating(#[case] a: &str, #[case] b: &str) {
+    assert_eq!(normalize(a), normalize(b));
+    assert_eq!(
+        normalize_with(a, CaseLocale::Turkic),
+        normalize_with(b, CaseLocale::Turkic)
+    );
+}
+
+#[rstest]
+fn should_tailor_case_folding_to_locale(fake_data: &LocationsDb) {
+    assert_eq!(fold_case("ISPARTA", CaseLocale::Unicode), "isparta");
+    assert_eq!(fold_case("ISPARTA", CaseLocale::Turkic), "ısparta");
+    assert_eq!(fold_case("İZMİR", CaseLocale::Turkic), "izmir");
+    let config = SearchConfig {
+        case_locale: Some(CaseLocale::Turkic),
+        ..Default::default()
+    };
+    let results = search_with(fake_data, "ABERCORN", config);
+    assert_eq!(results[0].0, "UN-LOCODE-gb:abc");
+}
+
+#[rstest]
+fn should_add_record_weights_to_scores(fake_data: &LocationsDb) {
+    let weights = [CsvWeight {
+        key: "UN-LOCODE-gb:abc".to_string(),
+        weight: 50,
+    }];
+    let weighted = parse_weights(common::load_fake_data_unindexed(), weights.into_iter()).mk_fst();
+    let score = |db: &LocationsDb| {
+        let st = SearchTerm::from_raw_query("abercarn".to_string(), None, 5, 0);
+        db.search(&st)[0].1.score
+    };
+    assert_eq!(score(&weighted), score(fake_data) + 50);
+
+    let hub = common::locode(
+        "BG:ZZX",
+        "Zzyzxovo",
+        json!({"function_code": "1", "weight": "25"}),
+    );
+    assert_eq!(hub.weight, 25);
+    assert!(hub.extra.is_empty());
+    assert_eq!(hub.to_raw(None)["d"]["weight"], 25);
+}
+
+#[rstest]
+fn should_evaluate_the_ranking_against_a_corpus(fake_data: &LocationsDb) {
+    let corpus = "query,expected_key,state
+abercarn,UN-LOCODE-gb:abc,
+lozarevo,UN-LOCODE-bg:loz,bg
+nowhere,UN-LOCODE-gb:zzz,
+";
+    let corpus = LabelledQuery::read_csv(corpus.as_bytes()).unwrap();
+    assert_eq!(corpus[0].state, None);
+    let report = evaluate(fake_data, &corpus, &EvalOptions::default());
+    assert_eq!(report.queries, 3);
+    assert!((report.precision_at_1 - 2. / 3.).abs() < 1e-9);
+    assert!((report.mrr - 2. / 3.).abs() < 1e-9);
+    assert_eq!(report.failures.len(), 1);
+    let failure = &report.failures[0];
+    assert_eq!(
+        (failure.query.as_str(), failure.rank, failure.known),
+        ("nowhere", None, false)
+    );
+}
@@ -0,0 +1,41 @@
+#![cfg(feature = "test_support")]
+
+use berlin_core::lint::lint;
+use berlin_core::search::SearchTerm;
+use berlin_core::test_support::FixtureSpec;
+
+#[test]
+fn should_generate_reproducible_fixtures() {
+    let spec = FixtureSpec {
+        seed: 7,
+        states: 4,
+        subdivisions: 8,
+        locodes: 40,
+        collisions: 5,
+    };
+    let fixture = spec.generate();
+    let again = spec.generate();
+    let other = FixtureSpec {
+        seed: 8,
+        ..spec.clone()
+    }
+    .generate();
+
+    assert_eq!(fixture.db.all.len(), 4 + 8 + 40);
+    assert_eq!(fixture.locodes, again.locodes);
+    assert_eq!(fixture.collisions, again.collisions);
+    assert_ne!(fixture.collisions, other.collisions);
+    assert_eq!(spec.blocks()[0].1.as_object().map(|b| b.len()), Some(52));
+    assert!(!lint(&fixture.db).has_errors());
+
+    assert_eq!(fixture.collisions.len(), 5);
+    for (name, keys) in &fixture.collisions {
+        let st = SearchTerm::from_raw_query(name.clone(), None, 10, 0);
+        let found = fixture.db.search(&st);
+        for key in keys {
+            assert!(found.iter().any(|(k, _)| k == key), "{name}: {key}");
+        }
+        let subdivs = keys.iter().map(|k| fixture.db.all[k].get_parents().1);
+        assert_eq!(subdivs.collect::<std::collections::HashSet<_>>().len(), 2);
+    }
+}